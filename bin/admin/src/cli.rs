@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use alloy_primitives::B256;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+
+/// The environment variable holding the admin key, when no key file is set.
+const ADMIN_PRIVATE_KEY_ENV: &str = "ADMIN_PRIVATE_KEY";
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    /// The private server RPC URL.
+    #[clap(long, env)]
+    pub private_server_rpc_url: String,
+
+    /// The path of a file holding the hex-encoded private key of one of the admin addresses of
+    /// the server. Without it, the key is read from the `ADMIN_PRIVATE_KEY` environment variable.
+    #[clap(long, env)]
+    pub admin_private_key_path: Option<PathBuf>,
+
+    /// Print JSON instead of tables.
+    #[clap(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Args {
+    /// The hex-encoded admin key, from the key file or the environment. It is never taken as a
+    /// command line argument, which would show it in the process list and the shell history.
+    pub fn admin_private_key(&self) -> anyhow::Result<String> {
+        match &self.admin_private_key_path {
+            Some(path) => Ok(std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .trim()
+                .to_string()),
+            None => std::env::var(ADMIN_PRIVATE_KEY_ENV).with_context(|| {
+                format!("set {ADMIN_PRIVATE_KEY_ENV} or --admin-private-key-path")
+            }),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the queued and leased proof requests.
+    Queue,
+
+    /// Show a proof request.
+    Inspect { request_id: B256 },
+
+    /// Change the priority of a queued or leased proof request.
+    SetPriority { request_id: B256, priority: u32 },
+
+    /// Cancel a queued or leased proof request, and mark it as unfulfillable on the network.
+    Cancel { request_id: B256 },
+
+    /// Hold the queued proof requests back from the fulfiller.
+    Pause,
+
+    /// End a pause or a drain.
+    Resume,
+
+    /// Reject new proof requests, while still proving the queued ones.
+    Drain,
+
+    /// Show whether the dispatch is paused or draining, and the number of proof requests.
+    Status,
+
+    /// Show the fulfiller addresses, and the proof requests still assigned to each, to follow a
+    /// key rotation.
+    Keys,
+
+    /// Show statistics on the stdins held by the server.
+    Stdins,
+
+    /// Show the most recent admin actions.
+    AuditLog {
+        /// The number of actions to show.
+        #[clap(long, default_value = "50")]
+        limit: u32,
+    },
+}
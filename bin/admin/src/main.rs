@@ -0,0 +1,131 @@
+use clap::Parser;
+use rustls::crypto::aws_lc_rs;
+use sp1_tee_private_types::{AdminProofRequestId, GetAuditLogRequest, SetPriorityRequest};
+use sp1_tee_private_utils::{LocalSigner, admin_client, admin_request};
+
+use crate::{
+    cli::{Args, Command},
+    output::{AuditLogView, DispatchStatusView, KeyView, Output, ProofRequestView, StdinStatsView},
+};
+
+mod cli;
+mod output;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    aws_lc_rs::default_provider().install_default().unwrap();
+
+    let args = Args::parse();
+
+    let signer = LocalSigner::new(&args.admin_private_key()?)?;
+    let mut client = admin_client(&args.private_server_rpc_url).await?;
+    let output = Output::new(args.json);
+
+    match args.command {
+        Command::Queue => {
+            let request = admin_request(&signer, "ListProofRequests", ()).await?;
+            let response = client.list_proof_requests(request).await?.into_inner();
+
+            output.print(
+                &response
+                    .requests
+                    .into_iter()
+                    .map(ProofRequestView::from)
+                    .collect::<Vec<_>>(),
+            )?;
+        }
+        Command::Inspect { request_id } => {
+            let id = AdminProofRequestId {
+                request_id: request_id.to_vec(),
+            };
+            let request = admin_request(&signer, "GetProofRequest", id).await?;
+            let response = client.get_proof_request(request).await?.into_inner();
+
+            output.print(&ProofRequestView::from(response))?;
+        }
+        Command::SetPriority {
+            request_id,
+            priority,
+        } => {
+            let body = SetPriorityRequest {
+                request_id: request_id.to_vec(),
+                priority,
+            };
+            let request = admin_request(&signer, "SetPriority", body).await?;
+            client.set_priority(request).await?;
+
+            // Show the request as it is now.
+            let id = AdminProofRequestId {
+                request_id: request_id.to_vec(),
+            };
+            let request = admin_request(&signer, "GetProofRequest", id).await?;
+            let response = client.get_proof_request(request).await?.into_inner();
+
+            output.print(&ProofRequestView::from(response))?;
+        }
+        Command::Cancel { request_id } => {
+            let id = AdminProofRequestId {
+                request_id: request_id.to_vec(),
+            };
+            let request = admin_request(&signer, "CancelProofRequest", id.clone()).await?;
+            client.cancel_proof_request(request).await?;
+
+            let request = admin_request(&signer, "GetProofRequest", id).await?;
+            let response = client.get_proof_request(request).await?.into_inner();
+
+            output.print(&ProofRequestView::from(response))?;
+        }
+        Command::Pause => {
+            let request = admin_request(&signer, "Pause", ()).await?;
+            let response = client.pause(request).await?.into_inner();
+
+            output.print(&DispatchStatusView::from(response))?;
+        }
+        Command::Resume => {
+            let request = admin_request(&signer, "Resume", ()).await?;
+            let response = client.resume(request).await?.into_inner();
+
+            output.print(&DispatchStatusView::from(response))?;
+        }
+        Command::Drain => {
+            let request = admin_request(&signer, "Drain", ()).await?;
+            let response = client.drain(request).await?.into_inner();
+
+            output.print(&DispatchStatusView::from(response))?;
+        }
+        Command::Status => {
+            let request = admin_request(&signer, "GetStatus", ()).await?;
+            let response = client.get_status(request).await?.into_inner();
+
+            output.print(&DispatchStatusView::from(response))?;
+        }
+        Command::Keys => {
+            let request = admin_request(&signer, "GetKeyRotationStatus", ()).await?;
+            let response = client.get_key_rotation_status(request).await?.into_inner();
+
+            output.print(
+                &response
+                    .keys
+                    .into_iter()
+                    .map(KeyView::from)
+                    .collect::<Vec<_>>(),
+            )?;
+        }
+        Command::Stdins => {
+            let request = admin_request(&signer, "GetStdinStats", ()).await?;
+            let response = client.get_stdin_stats(request).await?.into_inner();
+
+            output.print(&StdinStatsView::from(response))?;
+        }
+        Command::AuditLog { limit } => {
+            let body = GetAuditLogRequest { limit };
+            let request = admin_request(&signer, "GetAuditLog", body).await?;
+            let response = client.get_audit_log(request).await?.into_inner();
+
+            output.print(&AuditLogView::from(response))?;
+        }
+    }
+
+    Ok(())
+}
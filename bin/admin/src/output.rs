@@ -0,0 +1,356 @@
+use alloy_primitives::{Address, B256};
+use anyhow::Result;
+use serde::Serialize;
+use sp1_sdk::network::proto::base_types::ProofMode;
+use sp1_tee_private_types::{
+    AdminProofRequest, AuditEntry, DispatchStatus, FulfillerKeyStatus, GetAuditLogResponse,
+    StdinStats,
+};
+
+/// Prints the command results, either as human-readable tables or as JSON.
+pub struct Output {
+    json: bool,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Self { json }
+    }
+
+    pub fn print<T: Serialize + Table>(&self, value: &T) -> Result<()> {
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(value)?);
+        } else {
+            value.print_table();
+        }
+
+        Ok(())
+    }
+}
+
+/// A command result printed as a table.
+pub trait Table {
+    fn print_table(&self);
+}
+
+/// Print rows with their columns aligned on the widest cell.
+fn print_rows(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths = header.iter().map(|cell| cell.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!(
+        "{}",
+        line(
+            &header
+                .iter()
+                .map(|cell| cell.to_string())
+                .collect::<Vec<_>>()
+        )
+    );
+    for row in rows {
+        println!("{}", line(row));
+    }
+}
+
+/// Print a single record, one field per line.
+fn print_fields(fields: &[(&str, String)]) {
+    let width = fields
+        .iter()
+        .map(|(name, _)| name.len() + 1)
+        .max()
+        .unwrap_or_default();
+
+    for (name, value) in fields {
+        println!("{:<width$} {value}", format!("{name}:"));
+    }
+}
+
+fn address(bytes: &[u8]) -> Option<Address> {
+    Address::try_from(bytes).ok()
+}
+
+fn display<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofRequestView {
+    request_id: Option<B256>,
+    requester: Option<Address>,
+    state: String,
+    priority: u32,
+    vk_hash: String,
+    mode: String,
+    deadline: u64,
+    cycle_limit: u64,
+    gas_limit: u64,
+    queued_at: u64,
+    lease_expires_at: Option<u64>,
+    attempts: u32,
+}
+
+impl From<AdminProofRequest> for ProofRequestView {
+    fn from(request: AdminProofRequest) -> Self {
+        Self {
+            request_id: B256::try_from(request.request_id.as_slice()).ok(),
+            requester: address(&request.requester),
+            state: request.state,
+            priority: request.priority,
+            vk_hash: format!("0x{}", hex::encode(&request.vk_hash)),
+            mode: ProofMode::try_from(request.mode).map_or_else(
+                |_| request.mode.to_string(),
+                |mode| mode.as_str_name().to_string(),
+            ),
+            deadline: request.deadline,
+            cycle_limit: request.cycle_limit,
+            gas_limit: request.gas_limit,
+            queued_at: request.queued_at,
+            lease_expires_at: (request.lease_expires_at > 0).then_some(request.lease_expires_at),
+            attempts: request.attempts,
+        }
+    }
+}
+
+impl Table for Vec<ProofRequestView> {
+    fn print_table(&self) {
+        let rows = self
+            .iter()
+            .map(|request| {
+                vec![
+                    display(request.request_id),
+                    display(request.requester),
+                    request.state.clone(),
+                    request.priority.to_string(),
+                    request.mode.clone(),
+                    request.cycle_limit.to_string(),
+                    request.deadline.to_string(),
+                    request.attempts.to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        print_rows(
+            &[
+                "REQUEST ID",
+                "REQUESTER",
+                "STATE",
+                "PRIORITY",
+                "MODE",
+                "CYCLE LIMIT",
+                "DEADLINE",
+                "ATTEMPTS",
+            ],
+            &rows,
+        );
+    }
+}
+
+impl Table for ProofRequestView {
+    fn print_table(&self) {
+        print_fields(&[
+            ("Request ID", display(self.request_id)),
+            ("Requester", display(self.requester)),
+            ("State", self.state.clone()),
+            ("Priority", self.priority.to_string()),
+            ("Verifying key hash", self.vk_hash.clone()),
+            ("Mode", self.mode.clone()),
+            ("Deadline", self.deadline.to_string()),
+            ("Cycle limit", self.cycle_limit.to_string()),
+            ("Gas limit", self.gas_limit.to_string()),
+            ("Queued at", self.queued_at.to_string()),
+            ("Lease expires at", display(self.lease_expires_at)),
+            ("Attempts", self.attempts.to_string()),
+        ]);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DispatchStatusView {
+    paused: bool,
+    draining: bool,
+    queued: u64,
+    leased: u64,
+}
+
+impl From<DispatchStatus> for DispatchStatusView {
+    fn from(status: DispatchStatus) -> Self {
+        Self {
+            paused: status.paused,
+            draining: status.draining,
+            queued: status.queued,
+            leased: status.leased,
+        }
+    }
+}
+
+impl Table for DispatchStatusView {
+    fn print_table(&self) {
+        print_fields(&[
+            ("Paused", self.paused.to_string()),
+            ("Draining", self.draining.to_string()),
+            ("Queued", self.queued.to_string()),
+            ("Leased", self.leased.to_string()),
+        ]);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyView {
+    address: Option<Address>,
+    active: bool,
+    queued: u64,
+    leased: u64,
+}
+
+impl From<FulfillerKeyStatus> for KeyView {
+    fn from(key: FulfillerKeyStatus) -> Self {
+        Self {
+            address: address(&key.address),
+            active: key.active,
+            queued: key.queued,
+            leased: key.leased,
+        }
+    }
+}
+
+impl Table for Vec<KeyView> {
+    fn print_table(&self) {
+        let rows = self
+            .iter()
+            .map(|key| {
+                vec![
+                    display(key.address),
+                    if key.active { "active" } else { "retired" }.to_string(),
+                    key.queued.to_string(),
+                    key.leased.to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        print_rows(&["ADDRESS", "STATUS", "QUEUED", "LEASED"], &rows);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StdinStatsView {
+    count: u64,
+    total_bytes: u64,
+    claimed: u64,
+    spilled: u64,
+}
+
+impl From<StdinStats> for StdinStatsView {
+    fn from(stats: StdinStats) -> Self {
+        Self {
+            count: stats.count,
+            total_bytes: stats.total_bytes,
+            claimed: stats.claimed,
+            spilled: stats.spilled,
+        }
+    }
+}
+
+impl Table for StdinStatsView {
+    fn print_table(&self) {
+        print_fields(&[
+            ("Stdins", self.count.to_string()),
+            ("Total bytes", self.total_bytes.to_string()),
+            ("Claimed", self.claimed.to_string()),
+            ("Spilled", self.spilled.to_string()),
+        ]);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntryView {
+    timestamp: u64,
+    admin: Option<Address>,
+    action: String,
+    details: String,
+    outcome: String,
+}
+
+impl From<AuditEntry> for AuditEntryView {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp,
+            admin: address(&entry.admin),
+            action: entry.action,
+            details: entry.details,
+            outcome: entry.outcome,
+        }
+    }
+}
+
+/// The audit log, along with the calls rejected since the server started.
+#[derive(Debug, Serialize)]
+pub struct AuditLogView {
+    entries: Vec<AuditEntryView>,
+    rejected_calls: u64,
+    recent_rejections: Vec<AuditEntryView>,
+}
+
+impl From<GetAuditLogResponse> for AuditLogView {
+    fn from(response: GetAuditLogResponse) -> Self {
+        Self {
+            entries: response
+                .entries
+                .into_iter()
+                .map(AuditEntryView::from)
+                .collect(),
+            rejected_calls: response.rejected_calls,
+            recent_rejections: response
+                .recent_rejections
+                .into_iter()
+                .map(AuditEntryView::from)
+                .collect(),
+        }
+    }
+}
+
+impl Table for AuditLogView {
+    fn print_table(&self) {
+        print_entries(&self.entries);
+
+        println!();
+        println!("Rejected calls: {}", self.rejected_calls);
+        if !self.recent_rejections.is_empty() {
+            print_entries(&self.recent_rejections);
+        }
+    }
+}
+
+fn print_entries(entries: &[AuditEntryView]) {
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            vec![
+                entry.timestamp.to_string(),
+                display(entry.admin),
+                entry.action.clone(),
+                entry.details.clone(),
+                entry.outcome.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    print_rows(
+        &["TIMESTAMP", "ADMIN", "ACTION", "DETAILS", "OUTCOME"],
+        &rows,
+    );
+}
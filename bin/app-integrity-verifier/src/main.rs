@@ -1,15 +1,26 @@
+use anyhow::ensure;
+use rand::{RngCore, rngs::OsRng};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
+use sha2::{Digest, Sha512};
 
 const TEE_DOMAIN: &str = "https://tee.sp1-lumiere.xyz";
 const PHALA_CLOUD_API: &str = "https://cloud-api.phala.network/api/v1";
 
+/// The tag of the report data of the attestations requested by the clients.
+const ATTESTATION_TAG: &str = "sp1-tee/attest/v2";
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let http_client = reqwest::Client::new();
 
+    // A fresh nonce ensures the quote was produced for this request.
+    let mut nonce = [0; 32];
+    OsRng.fill_bytes(&mut nonce);
+
     let quote = http_client
-        .get(format!("{TEE_DOMAIN}/evidences/quote.json"))
+        .get(format!("{TEE_DOMAIN}/attestation"))
+        .query(&[("nonce", hex::encode(nonce))])
         .send()
         .await?
         .json::<QuoteResponse>()
@@ -23,6 +34,27 @@ async fn main() -> anyhow::Result<()> {
         .json::<AttestationResponse>()
         .await?;
 
+    let report_data = attestation.quote.body.reportdata.trim_start_matches("0x");
+    // The address count and the caller data length frame the variable parts. No caller data was
+    // bound, so its length is zero.
+    let mut hasher = Sha512::new_with_prefix(ATTESTATION_TAG)
+        .chain_update(nonce)
+        .chain_update((quote.fulfiller_addresses.len() as u32).to_be_bytes());
+    for address in &quote.fulfiller_addresses {
+        let address = hex::decode(address.trim_start_matches("0x"))?;
+        ensure!(address.len() == 20, "invalid fulfiller address");
+        hasher.update(address);
+    }
+    hasher.update(0u32.to_be_bytes());
+    ensure!(
+        report_data.eq_ignore_ascii_case(&hex::encode(hasher.finalize())),
+        "the quote report data does not commit to the nonce and the fulfiller addresses"
+    );
+
+    println!(
+        "fulfiller addresses: {}",
+        quote.fulfiller_addresses.join(", ")
+    );
     println!("rtmr3: {}", attestation.quote.body.rtmr3);
 
     Ok(())
@@ -32,6 +64,8 @@ async fn main() -> anyhow::Result<()> {
 pub struct QuoteResponse {
     quote: String,
     event_log: Value,
+    report_data: String,
+    fulfiller_addresses: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
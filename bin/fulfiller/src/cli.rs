@@ -1,4 +1,7 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser};
+use sp1_tee_private_utils::{DSTACK_SOCKET_PATH, FulfillerKeySource};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -11,9 +14,40 @@ pub struct Args {
     #[clap(long, env)]
     pub private_server_rpc_url: String,
 
-    /// The fulfiller private key.
+    /// Authenticate to the private server, and encrypt the traffic, with TLS certificates issued
+    /// by a CA whose key is derived from the dstack KMS. It can only be disabled in the builds
+    /// with the `local` feature.
+    #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+    pub internal_tls: bool,
+
+    /// The path of the dstack guest agent socket, used to derive the internal CA key.
+    #[clap(long, env, default_value = DSTACK_SOCKET_PATH)]
+    pub dstack_socket_path: PathBuf,
+
+    /// The comma-separated fulfiller private keys, used when the key source is `env`.
+    #[clap(long, env, value_delimiter = ',')]
+    pub fulfiller_private_key: Vec<String>,
+
+    /// Where the fulfiller keys come from: `env` for the fulfiller private keys, `dstack` to
+    /// derive them from the dstack KMS, so they never exist outside of the enclave, or `remote`
+    /// to sign with the remote signing services.
+    #[clap(long, env, default_value = "env")]
+    pub fulfiller_key_source: FulfillerKeySource,
+
+    /// The comma-separated IDs of the keys derived from the dstack KMS, used when the key source
+    /// is `dstack`. The default key is derived if there is none.
+    #[clap(long, env, value_delimiter = ',')]
+    pub fulfiller_key_ids: Vec<String>,
+
+    /// The comma-separated URLs of the remote signing services, one per key, used when the key
+    /// source is `remote`.
+    #[clap(long, env, value_delimiter = ',')]
+    pub remote_signer_url: Vec<String>,
+
+    /// A file listing the keys of the key source, one per line, instead of the arguments: the
+    /// private keys, the key IDs or the remote signer URLs. The keys are reloaded when it changes.
     #[clap(long, env)]
-    pub fulfiller_private_key: String,
+    pub fulfiller_keys_path: Option<PathBuf>,
 
     /// The S3 region where programs are stored.
     #[clap(long, env)]
@@ -1,11 +1,17 @@
-use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Context, Result, anyhow};
 use lru::LruCache;
 use sp1_prover::components::CpuProverComponents;
 use sp1_sdk::{
-    CudaProver, NetworkSigner, Prover, ProverClient, SP1Context, SP1ProofMode, SP1Prover,
-    SP1ProvingKey, SP1Stdin,
+    CudaProver, Prover, ProverClient, SP1Context, SP1ProofMode, SP1Prover, SP1ProvingKey, SP1Stdin,
     network::{
         B256,
         proto::base_types::{
@@ -15,94 +21,288 @@ use sp1_sdk::{
         },
     },
 };
+use sp1_tee_private_types::{
+    LeaseHandle, NackProofRequestRequest, ProofRequestLease, SubscribeProofRequestsRequest,
+    prover_network_client::ProverNetworkClient as PrivateNetworkClient,
+};
 use sp1_tee_private_utils::{
-    Signable, private_network_client, prover_network_client, retry_operation,
+    DOWNLOAD_SIGNATURE_HEADER, DOWNLOAD_TIMESTAMP_HEADER, Signable, Signer, download_message,
+    internal_tls::InternalIdentity, private_network_client, prover_network_client, retry_operation,
 };
 use spn_artifacts::{Artifact, extract_artifact_name};
 use tokio::{
     sync::Mutex,
     time::{Instant, sleep},
 };
-use tonic::Code;
+use tonic::{Code, transport::Channel};
+
+use crate::keys::SharedKeySet;
+
+/// The delay before reconnecting to the private server after the subscription dropped.
+const RECONNECT_INTERVAL_SEC: u64 = 3;
 
-const REFRESH_INTERVAL_SEC: u64 = 3;
+/// How often a blocking proving step checks whether the work was aborted.
+const ABORT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why a proof request was not proved.
+#[derive(Debug, thiserror::Error)]
+pub enum ProcessError {
+    /// The request can never be proved, such as when it exceeds its limits or its program fails
+    /// on its stdin, so proving it again would fail the same way.
+    #[error("unfulfillable: {0}")]
+    Unfulfillable(anyhow::Error),
+
+    /// Another attempt may succeed.
+    #[error(transparent)]
+    Transient(#[from] anyhow::Error),
+
+    /// The lease is no longer held, so the work was stopped.
+    #[error("aborted")]
+    Aborted,
+}
+
+/// How a lease is released once the worker is done with it.
+#[derive(Debug, Clone, Copy)]
+enum Release {
+    /// The request was processed.
+    Ack,
+    /// The request goes back on the queue, for another attempt.
+    Requeue,
+    /// The request failed for good.
+    Fail,
+}
 
 pub async fn run(
     network_rpc_url: String,
     private_server_rpc_url: String,
-    fulfiller_private_key: String,
+    internal_identity: Option<InternalIdentity>,
+    fulfiller_keys: SharedKeySet,
     programs_s3_region: String,
     worker_count: usize,
 ) -> anyhow::Result<()> {
     let proving_keys = Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(32).unwrap())));
-    let fulfiller_signer = NetworkSigner::local(&fulfiller_private_key)?;
-    let fulfiller_signer = Arc::new(fulfiller_signer);
-    let private_client = private_network_client(&private_server_rpc_url).await?;
+    for address in fulfiller_keys.read().unwrap().addresses() {
+        tracing::info!("Fulfiller address: {address}");
+    }
+    let private_client = private_network_client(
+        &private_server_rpc_url,
+        internal_identity
+            .as_ref()
+            .map(InternalIdentity::tonic_config),
+    )
+    .await?;
+
+    // The stdins are downloaded from the private server, through the same internal channel.
+    let stdin_client = match &internal_identity {
+        Some(identity) => reqwest::Client::builder()
+            .use_preconfigured_tls(identity.client_config()?)
+            .build()?,
+        None => reqwest::Client::new(),
+    };
 
     for gpu_id in 0..worker_count {
-        let proving_keys = proving_keys.clone();
-        let fulfiller_signer = fulfiller_signer.clone();
-        let network_rpc_url = network_rpc_url.clone();
-        let programs_s3_region = programs_s3_region.clone();
-        let mut private_client = private_client.clone();
-
-        tokio::spawn(async move {
-            loop {
-                match private_client.take_next_proof_request(()).await {
-                    Ok(proof_request) => {
-                        let proof_request = proof_request.into_inner();
-                        let request_id = B256::from_slice(&proof_request.request_id);
-
-                        #[cfg(not(feature = "cpu"))]
-                        let fulfiller = Fulfiller::new(
-                            proof_request,
-                            gpu_id,
-                            proving_keys.clone(),
-                            fulfiller_signer.clone(),
-                            network_rpc_url.clone(),
-                            programs_s3_region.clone(),
-                        );
-
-                        #[cfg(feature = "cpu")]
-                        let fulfiller = Fulfiller::cpu(
-                            proof_request,
-                            proving_keys.clone(),
-                            fulfiller_signer.clone(),
-                            network_rpc_url.clone(),
-                            programs_s3_region.clone(),
-                        );
-
-                        if let Err(err) = fulfiller.process().await {
-                            tracing::error!(?request_id, "Error during proving: {err}");
-                        } else {
-                            tracing::info!(?request_id, "Proving sucessful!");
-                        }
-                    }
-                    Err(status) => {
-                        if status.code() != Code::NotFound {
-                            tracing::error!("{}", status.message());
-                        } else {
-                            tracing::debug!("{}", status.message());
+        let worker = Worker {
+            gpu_id,
+            proving_keys: proving_keys.clone(),
+            fulfiller_keys: fulfiller_keys.clone(),
+            network_rpc_url: network_rpc_url.clone(),
+            programs_s3_region: programs_s3_region.clone(),
+            private_client: private_client.clone(),
+            stdin_client: stdin_client.clone(),
+        };
+
+        tokio::spawn(worker.run());
+    }
+
+    Ok(())
+}
+
+/// A worker proving the requests pushed by the private server, one at a time.
+struct Worker {
+    #[cfg_attr(feature = "cpu", allow(dead_code))]
+    gpu_id: usize,
+    proving_keys: Arc<Mutex<LruCache<Vec<u8>, Arc<SP1ProvingKey>>>>,
+    fulfiller_keys: SharedKeySet,
+    network_rpc_url: String,
+    programs_s3_region: String,
+    private_client: PrivateNetworkClient<Channel>,
+    stdin_client: reqwest::Client,
+}
+
+impl Worker {
+    async fn run(mut self) {
+        loop {
+            let subscription = self
+                .private_client
+                .subscribe_proof_requests(SubscribeProofRequestsRequest { capacity: 1 })
+                .await;
+
+            match subscription {
+                Ok(stream) => {
+                    tracing::info!(gpu_id = self.gpu_id, "Subscribed to proof requests");
+                    let mut stream = stream.into_inner();
+
+                    loop {
+                        match stream.message().await {
+                            Ok(Some(lease)) => self.process_lease(lease).await,
+                            Ok(None) => {
+                                tracing::warn!(gpu_id = self.gpu_id, "Subscription closed");
+                                break;
+                            }
+                            Err(status) => {
+                                tracing::error!(
+                                    gpu_id = self.gpu_id,
+                                    "Subscription failed: {}",
+                                    status.message()
+                                );
+                                break;
+                            }
                         }
                     }
                 }
+                Err(status) => {
+                    tracing::error!(
+                        gpu_id = self.gpu_id,
+                        "Failed to subscribe: {}",
+                        status.message()
+                    );
+                }
+            }
+
+            // Wait before reconnecting.
+            sleep(Duration::from_secs(RECONNECT_INTERVAL_SEC)).await;
+        }
+    }
+
+    async fn process_lease(&self, lease: ProofRequestLease) {
+        let Some(proof_request) = lease.proof_request else {
+            tracing::error!(lease_id = %lease.lease_id, "Lease without proof request");
+            return;
+        };
+        let request_id = B256::from_slice(&proof_request.request_id);
+        let handle = LeaseHandle {
+            request_id: proof_request.request_id.clone(),
+            lease_id: lease.lease_id,
+        };
 
-                // Wait for the next interval.
-                sleep(Duration::from_secs(REFRESH_INTERVAL_SEC)).await;
+        // Sign with the key the request was assigned to, which may have been rotated since.
+        let Some(fulfiller_signer) = proof_request
+            .fulfiller
+            .as_deref()
+            .and_then(|fulfiller| self.fulfiller_keys.read().unwrap().get(fulfiller))
+        else {
+            tracing::error!(?request_id, "No key for the fulfiller of the proof request");
+            // No worker can prove it, as they share the fulfiller keys, nor mark it as
+            // unfulfillable on the network, so fail it right away rather than requeue it until
+            // its attempts run out. A cancellation is handed out again once its lease expires, in
+            // case the key is added back.
+            if !lease.cancelled {
+                release_lease(&self.private_client, handle, Release::Fail).await;
             }
-        });
+            return;
+        };
+
+        // The cancellation is only acked once reported, otherwise it is retried when its lease
+        // expires.
+        if lease.cancelled {
+            tracing::info!(?request_id, "Proof request cancelled while queued");
+            if self.fail_cancelled(request_id, &fulfiller_signer).await {
+                release_lease(&self.private_client, handle, Release::Ack).await;
+            }
+            return;
+        }
+
+        // Keep the lease alive while proving, and stop once it is lost or the request cancelled.
+        let signer = fulfiller_signer.clone();
+        let abort = Arc::new(AtomicBool::new(false));
+        let renewal = tokio::spawn(renew_lease(
+            self.private_client.clone(),
+            handle.clone(),
+            lease.expires_at,
+            abort.clone(),
+        ));
+
+        #[cfg(not(feature = "cpu"))]
+        let fulfiller = Fulfiller::new(
+            proof_request,
+            self.gpu_id,
+            self.proving_keys.clone(),
+            fulfiller_signer,
+            self.network_rpc_url.clone(),
+            self.programs_s3_region.clone(),
+            self.stdin_client.clone(),
+        );
+
+        #[cfg(feature = "cpu")]
+        let fulfiller = Fulfiller::cpu(
+            proof_request,
+            self.proving_keys.clone(),
+            fulfiller_signer,
+            self.network_rpc_url.clone(),
+            self.programs_s3_region.clone(),
+            self.stdin_client.clone(),
+        );
+
+        let result = fulfiller.process(&abort).await;
+
+        if let Err(ProcessError::Aborted) = result {
+            // The renewal stopped, so the lease is already gone.
+            if matches!(renewal.await, Ok(true)) {
+                tracing::info!(
+                    ?request_id,
+                    "Proof request cancelled while proving, aborted"
+                );
+                self.fail_cancelled(request_id, &signer).await;
+            } else {
+                tracing::warn!(?request_id, "Lease lost while proving, aborted");
+            }
+            return;
+        }
+        renewal.abort();
+
+        match result {
+            Ok(()) => {
+                tracing::info!(?request_id, "Proving sucessful!");
+                release_lease(&self.private_client, handle, Release::Ack).await;
+            }
+            // The server fails the request after its last attempt, so it is marked as
+            // unfulfillable on the network whatever the error.
+            Err(err) if matches!(err, ProcessError::Unfulfillable(_)) || lease.last_attempt => {
+                tracing::error!(?request_id, "Proof request unfulfillable: {err}");
+                if let Err(err) =
+                    fail_fulfillment(&self.network_rpc_url, &signer, request_id.to_vec()).await
+                {
+                    tracing::error!(?request_id, "Failed to mark the request: {err}");
+                }
+                release_lease(&self.private_client, handle, Release::Fail).await;
+            }
+            Err(err) => {
+                tracing::error!(?request_id, "Error during proving: {err}");
+                release_lease(&self.private_client, handle, Release::Requeue).await;
+            }
+        }
     }
 
-    Ok(())
+    /// Mark a cancelled request as unfulfillable on the network, and return whether it was.
+    async fn fail_cancelled(&self, request_id: B256, fulfiller_signer: &dyn Signer) -> bool {
+        match fail_fulfillment(&self.network_rpc_url, fulfiller_signer, request_id.to_vec()).await {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::error!(?request_id, "Failed to mark the cancelled request: {err}");
+                false
+            }
+        }
+    }
 }
 
 pub struct Fulfiller<P: Prover<CpuProverComponents>> {
     proof_request: ProofRequest,
     prover: P,
     proving_keys: Arc<Mutex<LruCache<Vec<u8>, Arc<SP1ProvingKey>>>>,
-    fulfiller_signer: Arc<NetworkSigner>,
+    fulfiller_signer: Arc<dyn Signer>,
     network_rpc_url: String,
     programs_s3_region: String,
+    stdin_client: reqwest::Client,
 }
 
 impl Fulfiller<CudaProver> {
@@ -111,9 +311,10 @@ impl Fulfiller<CudaProver> {
         proof_request: ProofRequest,
         device_id: usize,
         proving_keys: Arc<Mutex<LruCache<Vec<u8>, Arc<SP1ProvingKey>>>>,
-        fulfiller_signer: Arc<NetworkSigner>,
+        fulfiller_signer: Arc<dyn Signer>,
         network_rpc_url: String,
         programs_s3_region: String,
+        stdin_client: reqwest::Client,
     ) -> Self {
         let port = 3000 + device_id;
         let prover = ProverClient::builder()
@@ -128,6 +329,7 @@ impl Fulfiller<CudaProver> {
             fulfiller_signer,
             network_rpc_url,
             programs_s3_region,
+            stdin_client,
         }
     }
 }
@@ -137,9 +339,10 @@ impl Fulfiller<sp1_sdk::CpuProver> {
     pub fn cpu(
         proof_request: ProofRequest,
         proving_keys: Arc<Mutex<LruCache<Vec<u8>, Arc<SP1ProvingKey>>>>,
-        fulfiller_signer: Arc<NetworkSigner>,
+        fulfiller_signer: Arc<dyn Signer>,
         network_rpc_url: String,
         programs_s3_region: String,
+        stdin_client: reqwest::Client,
     ) -> Self {
         let prover = ProverClient::builder().cpu().build();
         Self {
@@ -149,18 +352,19 @@ impl Fulfiller<sp1_sdk::CpuProver> {
             fulfiller_signer,
             network_rpc_url,
             programs_s3_region,
+            stdin_client,
         }
     }
 }
 
-impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
-    pub async fn process(self) -> Result<()> {
+impl<P: Prover<CpuProverComponents> + Send + Sync + 'static> Fulfiller<P> {
+    /// Prove the request, stopping as soon as the lease is lost or the request cancelled.
+    ///
+    /// The setup, execution and proving run on the blocking thread pool, see [`run_blocking`].
+    pub async fn process(self, abort: &AtomicBool) -> Result<(), ProcessError> {
         let request_id = B256::from_slice(&self.proof_request.request_id);
-        let prover = self.prover.inner();
-        let context = SP1Context::builder()
-            .max_cycles(self.proof_request.cycle_limit)
-            .calculate_gas(true)
-            .build();
+        let prover = Arc::new(self.prover);
+        let cycle_limit = self.proof_request.cycle_limit;
 
         let pk = {
             self.proving_keys
@@ -193,7 +397,7 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
                 .await?
                 .into_inner()
                 .program
-                .ok_or(anyhow!("Program not registered"))?;
+                .ok_or_else(|| ProcessError::Unfulfillable(anyhow!("Program not registered")))?;
 
                 let artifact = Artifact {
                     id: extract_artifact_name(&program.program_uri)?,
@@ -208,7 +412,8 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
                     )
                     .await?;
 
-                let (pk, _) = self.prover.setup(&elf);
+                let setup_prover = prover.clone();
+                let (pk, _) = run_blocking(abort, move || setup_prover.setup(&elf)).await?;
                 let pk = Arc::new(pk);
 
                 self.proving_keys
@@ -220,8 +425,18 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
             }
         };
 
-        let stdin = retrieve_stdin(&self.proof_request.stdin_uri).await?;
-        let proof_mode = ProofMode::try_from(self.proof_request.mode)?;
+        check_aborted(abort)?;
+
+        let stdin = Arc::new(
+            retrieve_stdin(
+                &self.stdin_client,
+                &self.fulfiller_signer,
+                &self.proof_request.stdin_uri,
+            )
+            .await?,
+        );
+        let proof_mode = ProofMode::try_from(self.proof_request.mode)
+            .map_err(|err| ProcessError::Unfulfillable(err.into()))?;
         let proof_mode = match proof_mode {
             ProofMode::Core => SP1ProofMode::Core,
             ProofMode::Compressed => SP1ProofMode::Compressed,
@@ -231,7 +446,17 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
         };
 
         tracing::debug!(?request_id, "Executing");
-        let (execution_result, _, gas_used) = execute_program(&pk.elf, &stdin, prover, context);
+        let (execution_result, _, gas_used) = {
+            let (prover, pk, stdin) = (prover.clone(), pk.clone(), stdin.clone());
+            run_blocking(abort, move || {
+                let context = SP1Context::builder()
+                    .max_cycles(cycle_limit)
+                    .calculate_gas(true)
+                    .build();
+                execute_program(&pk.elf, &stdin, prover.inner(), context)
+            })
+            .await?
+        };
 
         let (execution_result, _) = match execution_result {
             Ok(_) => {
@@ -249,32 +474,16 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
             Err(err) => (Err(anyhow!("{err}")), ExecutionStatus::Unexecutable),
         };
 
-        // Return early if the execution failed
-        if let Err(err) = execution_result {
-            bail!("{err}")
-        };
+        // Return early if the execution failed: the request exceeds its limits, or its program
+        // cannot run on its stdin, so there is no point in trying again.
+        execution_result.map_err(ProcessError::Unfulfillable)?;
+        check_aborted(abort)?;
 
         tracing::debug!(?request_id, "Start proving");
         let prove_start = Instant::now();
-        let proof = self.prover.prove(&pk, &stdin, proof_mode);
+        let proof = run_blocking(abort, move || prover.prove(&pk, &stdin, proof_mode)).await?;
         let prove_duration = prove_start.elapsed();
 
-        let nonce = retry_operation(
-            || async {
-                let mut network_client = prover_network_client(&self.network_rpc_url).await?;
-                let nonce = network_client
-                    .get_nonce(GetNonceRequest {
-                        address: self.fulfiller_signer.address().to_vec(),
-                    })
-                    .await?;
-
-                Ok(nonce)
-            },
-            "get nonce",
-        )
-        .await?
-        .into_inner();
-
         match proof {
             Ok(proof) => {
                 tracing::info!(
@@ -282,10 +491,14 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
                     "Proof generated in {}s",
                     prove_duration.as_secs_f64()
                 );
-                let encoded_proof = bincode::serialize(&proof)?;
+                check_aborted(abort)?;
+
+                let encoded_proof = bincode::serialize(&proof).map_err(anyhow::Error::from)?;
+                let nonce =
+                    get_nonce(&self.network_rpc_url, self.fulfiller_signer.as_ref()).await?;
 
                 let body = FulfillProofRequestBody {
-                    nonce: nonce.nonce,
+                    nonce,
                     request_id: self.proof_request.request_id.clone(),
                     proof: encoded_proof,
                     reserved_metadata: None,
@@ -313,64 +526,232 @@ impl<P: Prover<CpuProverComponents>> Fulfiller<P> {
 
                 tracing::debug!(?request_id, "Proof fullfilled");
             }
+            // The execution already succeeded, so the request is within its limits and its
+            // program runs on its stdin: the prover failed for another reason, such as running
+            // out of memory, and another attempt may succeed.
             Err(err) => {
                 tracing::error!(?request_id, "Failed to prove: {err}");
+                return Err(ProcessError::Transient(anyhow!("{err}")));
+            }
+        }
 
-                let body = FailFulfillmentRequestBody {
-                    nonce: nonce.nonce,
-                    request_id: self.proof_request.request_id.clone(),
-                    error: None,
-                };
+        Ok(())
+    }
+}
 
-                // Set the proof as unfulfillable on the prover network
-                retry_operation(
-                    || async {
-                        let mut network_client =
-                            prover_network_client(&self.network_rpc_url).await?;
-                        network_client
-                            .fail_fulfillment(FailFulfillmentRequest {
-                                format: MessageFormat::Binary.into(),
-                                signature: body.sign(&self.fulfiller_signer).await?,
-                                body: Some(body.clone()),
-                            })
-                            .await?;
-                        Ok(())
-                    },
-                    "fail fulfillment",
-                )
+/// Run a blocking proving step on the blocking thread pool, and give up on it as soon as the
+/// renewal flags the lease as no longer held.
+///
+/// The prover can not be interrupted, so an abandoned step keeps running in the background until
+/// it returns, and its result is discarded. The worker still reports the cancellation and moves
+/// on to its next request right away.
+async fn run_blocking<T, F>(abort: &AtomicBool, f: F) -> Result<T, ProcessError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut task = tokio::task::spawn_blocking(f);
+
+    loop {
+        tokio::select! {
+            result = &mut task => return result.map_err(|err| ProcessError::Transient(err.into())),
+            _ = sleep(ABORT_POLL_INTERVAL) => check_aborted(abort)?,
+        }
+    }
+}
+
+/// Stop the work once the renewal flagged the lease as no longer held.
+fn check_aborted(abort: &AtomicBool) -> Result<(), ProcessError> {
+    if abort.load(Ordering::Acquire) {
+        return Err(ProcessError::Aborted);
+    }
+
+    Ok(())
+}
+
+/// The next nonce of the fulfiller key on the prover network.
+async fn get_nonce(network_rpc_url: &str, fulfiller_signer: &dyn Signer) -> Result<u64> {
+    let nonce = retry_operation(
+        || async {
+            let mut network_client = prover_network_client(network_rpc_url).await?;
+            let nonce = network_client
+                .get_nonce(GetNonceRequest {
+                    address: fulfiller_signer.address().to_vec(),
+                })
                 .await?;
 
-                tracing::debug!(?request_id, "Proof marked as unfulfillable");
+            Ok(nonce)
+        },
+        "get nonce",
+    )
+    .await?
+    .into_inner();
+
+    Ok(nonce.nonce)
+}
+
+/// Set the proof request as unfulfillable on the prover network.
+async fn fail_fulfillment(
+    network_rpc_url: &str,
+    fulfiller_signer: &dyn Signer,
+    request_id: Vec<u8>,
+) -> Result<()> {
+    let body = FailFulfillmentRequestBody {
+        nonce: get_nonce(network_rpc_url, fulfiller_signer).await?,
+        request_id,
+        error: None,
+    };
+
+    retry_operation(
+        || async {
+            let mut network_client = prover_network_client(network_rpc_url).await?;
+            network_client
+                .fail_fulfillment(FailFulfillmentRequest {
+                    format: MessageFormat::Binary.into(),
+                    signature: body.sign(fulfiller_signer).await?,
+                    body: Some(body.clone()),
+                })
+                .await?;
+            Ok(())
+        },
+        "fail fulfillment",
+    )
+    .await?;
+
+    let request_id = B256::from_slice(&body.request_id);
+    tracing::debug!(?request_id, "Proof marked as unfulfillable");
+
+    Ok(())
+}
+
+/// Renew the lease until the task is aborted, or the lease is lost, in which case `abort` is set
+/// to stop the work. Returns whether the lease was lost because the request was cancelled.
+async fn renew_lease(
+    mut private_client: PrivateNetworkClient<Channel>,
+    handle: LeaseHandle,
+    mut expires_at: u64,
+    abort: Arc<AtomicBool>,
+) -> bool {
+    let request_id = B256::from_slice(&handle.request_id);
+
+    loop {
+        // Renew when a third of the remaining lease time has elapsed.
+        let remaining = expires_at.saturating_sub(unix_timestamp());
+        sleep(Duration::from_secs((remaining / 3).max(1))).await;
+
+        match private_client.renew_lease(handle.clone()).await {
+            Ok(response) => expires_at = response.into_inner().expires_at,
+            Err(status) if status.code() == Code::Cancelled => {
+                abort.store(true, Ordering::Release);
+                return true;
+            }
+            Err(status) if status.code() == Code::FailedPrecondition => {
+                tracing::error!(?request_id, "Lease lost: {}", status.message());
+                abort.store(true, Ordering::Release);
+                return false;
+            }
+            Err(status) => {
+                tracing::warn!(?request_id, "Failed to renew lease: {}", status.message());
             }
         }
+    }
+}
 
-        Ok(())
+/// Ack the lease if the request was processed, or nack it, putting it back on the queue only if
+/// another attempt may succeed.
+async fn release_lease(
+    private_client: &PrivateNetworkClient<Channel>,
+    handle: LeaseHandle,
+    release: Release,
+) {
+    let request_id = B256::from_slice(&handle.request_id);
+
+    let result = retry_operation(
+        || async {
+            let mut private_client = private_client.clone();
+
+            match release {
+                Release::Ack => {
+                    private_client.ack_proof_request(handle.clone()).await?;
+                }
+                Release::Requeue | Release::Fail => {
+                    private_client
+                        .nack_proof_request(NackProofRequestRequest {
+                            lease: Some(handle.clone()),
+                            requeue: matches!(release, Release::Requeue),
+                        })
+                        .await?;
+                }
+            }
+
+            Ok(())
+        },
+        match release {
+            Release::Ack => "ack proof request",
+            Release::Requeue | Release::Fail => "nack proof request",
+        },
+    )
+    .await;
+
+    if let Err(err) = result {
+        tracing::error!(?request_id, "Failed to release lease: {err}");
     }
 }
 
-async fn retrieve_stdin(stdin_uri: &str) -> Result<SP1Stdin> {
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+async fn retrieve_stdin(
+    client: &reqwest::Client,
+    signer: &dyn Signer,
+    stdin_uri: &str,
+) -> Result<SP1Stdin, ProcessError> {
     tracing::debug!("Download {stdin_uri}");
 
-    let client = reqwest::Client::new();
+    // The server only serves the stdins to the fulfiller key.
+    let id = stdin_uri.rsplit('/').next().unwrap_or_default();
+    let timestamp = unix_timestamp();
+    let signature = signer
+        .sign_message(&download_message(id, timestamp))
+        .await?;
+
     let res = client
         .get(stdin_uri)
+        .header(DOWNLOAD_TIMESTAMP_HEADER, timestamp)
+        .header(DOWNLOAD_SIGNATURE_HEADER, hex::encode(signature.as_bytes()))
         .timeout(Duration::from_secs(60))
         .send()
         .await
         .context("Failed to GET HTTPS URL")?;
 
+    // The stdin was deleted, after a single-use download or past its retention, and cannot be
+    // downloaded again.
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ProcessError::Unfulfillable(anyhow!(
+            "Stdin {id} is no longer held by the server"
+        )));
+    }
+
     if !res.status().is_success() {
         return Err(anyhow!(
             "Failed to download from HTTPS URL {stdin_uri}: status {}",
             res.status()
-        ));
+        )
+        .into());
     }
     let bytes = res
         .bytes()
         .await
         .context("Failed to read HTTPS response body")?;
 
-    let stdin = bincode::deserialize(&bytes).context("Failed to deserialize stdin")?;
+    // A malformed stdin will not get any better on another attempt.
+    let stdin = bincode::deserialize(&bytes)
+        .context("Failed to deserialize stdin")
+        .map_err(ProcessError::Unfulfillable)?;
 
     Ok(stdin)
 }
@@ -0,0 +1,115 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result};
+use sp1_tee_private_utils::{
+    DstackClient, FulfillerKeySet, FulfillerKeySource, fulfiller_key_set, watch_file,
+};
+
+use crate::cli::Args;
+
+/// The fulfiller keys, replaced when the keys file changes.
+pub type SharedKeySet = Arc<RwLock<FulfillerKeySet>>;
+
+/// Loads the fulfiller keys from their source, see [`fulfiller_key_set`].
+#[derive(Clone)]
+pub struct KeyLoader {
+    source: FulfillerKeySource,
+    private_keys: Vec<String>,
+    key_ids: Vec<String>,
+    remote_signer_urls: Vec<String>,
+    keys_path: Option<PathBuf>,
+    dstack: DstackClient,
+}
+
+impl KeyLoader {
+    pub fn new(args: &Args, dstack: DstackClient) -> Self {
+        Self {
+            source: args.fulfiller_key_source,
+            private_keys: args.fulfiller_private_key.clone(),
+            key_ids: args.fulfiller_key_ids.clone(),
+            remote_signer_urls: args.remote_signer_url.clone(),
+            keys_path: args.fulfiller_keys_path.clone(),
+            dstack,
+        }
+    }
+
+    /// Load the keys. The entries of the keys file, if set, replace the keys listed in the
+    /// arguments for the key source.
+    pub async fn load(&self) -> Result<FulfillerKeySet> {
+        let mut private_keys = self.private_keys.as_slice();
+        let mut key_ids = self.key_ids.as_slice();
+        let mut remote_signer_urls = self.remote_signer_urls.as_slice();
+
+        let entries = self.keys_path.as_deref().map(load_entries).transpose()?;
+        if let Some(entries) = &entries {
+            match self.source {
+                FulfillerKeySource::Env => private_keys = entries.as_slice(),
+                FulfillerKeySource::Remote => remote_signer_urls = entries.as_slice(),
+                _ => key_ids = entries.as_slice(),
+            }
+        }
+
+        fulfiller_key_set(
+            self.source,
+            private_keys,
+            key_ids,
+            remote_signer_urls,
+            &self.dstack,
+        )
+        .await
+    }
+}
+
+/// Read the entries of a keys file, one per line. Empty lines, and the text after a `#`, are
+/// ignored.
+fn load_entries(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    Ok(content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Reload the keys whenever the keys file changes. A file that fails to load is ignored, keeping
+/// the previous keys.
+pub async fn watch_keys(loader: KeyLoader, keys: SharedKeySet) {
+    let Some(path) = loader.keys_path.clone() else {
+        return;
+    };
+
+    watch_file(path, move |_| {
+        tokio::spawn(reload_keys(loader.clone(), keys.clone()));
+    })
+    .await
+}
+
+async fn reload_keys(loader: KeyLoader, keys: SharedKeySet) {
+    let loaded = match loader.load().await {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            tracing::error!("Failed to reload the fulfiller keys: {err:#}");
+            return;
+        }
+    };
+
+    let mut current = keys.write().unwrap();
+    let new = loaded.addresses().into_iter().collect::<BTreeSet<_>>();
+    let old = current.addresses().into_iter().collect::<BTreeSet<_>>();
+
+    for address in new.difference(&old) {
+        tracing::info!(%address, "Fulfiller key added");
+    }
+    for address in old.difference(&new) {
+        tracing::info!(%address, "Fulfiller key retired");
+    }
+
+    *current = loaded;
+}
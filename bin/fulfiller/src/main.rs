@@ -1,13 +1,24 @@
+use std::sync::{Arc, RwLock};
+
 use clap::Parser;
 use rustls::crypto::aws_lc_rs;
 use sp1_sdk::install::try_install_circuit_artifacts;
+use sp1_tee_private_utils::{
+    DstackClient,
+    internal_tls::{InternalIdentity, InternalRole},
+};
 use tokio::signal;
 use tracing::info;
 
-use crate::{cli::Args, fulfiller::run};
+use crate::{
+    cli::Args,
+    fulfiller::run,
+    keys::{KeyLoader, watch_keys},
+};
 
 mod cli;
 mod fulfiller;
+mod keys;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -17,6 +28,12 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Args::parse();
 
+    #[cfg(not(feature = "local"))]
+    anyhow::ensure!(
+        args.internal_tls,
+        "Internal TLS can only be disabled in local builds"
+    );
+
     info!("Starting fulfiller...");
 
     // Install circuits
@@ -25,10 +42,23 @@ async fn main() -> anyhow::Result<()> {
 
     info!("Fulfiller ready");
 
+    let dstack = DstackClient::new(&args.dstack_socket_path);
+
+    let key_loader = KeyLoader::new(&args, dstack.clone());
+    let fulfiller_keys = Arc::new(RwLock::new(key_loader.load().await?));
+    tokio::spawn(watch_keys(key_loader, fulfiller_keys.clone()));
+
+    let internal_identity = if args.internal_tls {
+        Some(InternalIdentity::derive(&dstack, InternalRole::Fulfiller).await?)
+    } else {
+        None
+    };
+
     run(
         args.network_rpc_url,
         args.private_server_rpc_url,
-        args.fulfiller_private_key,
+        internal_identity,
+        fulfiller_keys,
         args.programs_s3_region,
         args.worker_count,
     )
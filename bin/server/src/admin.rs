@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db::{Db, TenantQueue},
+    quota::{Quotas, RequesterUsage},
+};
+
+/// The state of the admin routes, only reachable with the admin bearer token.
+pub struct AdminState<DB: Db> {
+    pub db: Arc<DB>,
+    pub quotas: Arc<Quotas>,
+    /// The hash of the admin token, so comparing it does not leak the token through timing.
+    token_hash: [u8; 32],
+}
+
+impl<DB: Db> AdminState<DB> {
+    pub fn new(db: Arc<DB>, quotas: Arc<Quotas>, token: &str) -> Self {
+        Self {
+            db,
+            quotas,
+            token_hash: Sha256::digest(token).into(),
+        }
+    }
+
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        let token = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match token {
+            Some(token) if <[u8; 32]>::from(Sha256::digest(token)) == self.token_hash => Ok(()),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+/// The quota usage of every requester holding proof requests or stdins, or with recent requests.
+pub async fn usage<DB: Db>(
+    State(state): State<Arc<AdminState<DB>>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<RequesterUsage>>, StatusCode> {
+    state.authorize(&headers)?;
+
+    let stored = state.db.usage().await.map_err(|err| {
+        tracing::error!("Failed to get the requesters usage: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(state.quotas.usage(stored)))
+}
+
+/// The queued proof requests of each requester.
+pub async fn queue<DB: Db>(
+    State(state): State<Arc<AdminState<DB>>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<TenantQueue>>, StatusCode> {
+    state.authorize(&headers)?;
+
+    let tenants = state.db.tenant_queues().await.map_err(|err| {
+        tracing::error!("Failed to get the requester queues: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(tenants))
+}
@@ -1,50 +1,333 @@
-use std::sync::Arc;
+use std::{io, path::PathBuf, sync::Arc, time::Duration};
+
+use async_stream::try_stream;
 
 use axum::{
-    body::Body,
-    extract::{Path, State},
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header::CONTENT_LENGTH},
+    response::{IntoResponse, Response},
+};
+use futures::{Stream, TryStreamExt};
+use mti::prelude::{MagicTypeIdExt, V7};
+use serde::Deserialize;
+use sp1_tee_private_utils::{
+    DOWNLOAD_SIGNATURE_HEADER, DOWNLOAD_TIMESTAMP_HEADER, download_message, recover_signer,
+    stdin_encryption::{PUBLIC_KEY_SIZE, STDIN_ENCRYPTION_HEADER, STDIN_ENCRYPTION_SCHEME},
 };
-use futures::TryStreamExt;
-use tokio::io::AsyncReadExt;
-use tokio_util::io::StreamReader;
 
-use crate::db::{Db, InMemoryDb};
+use crate::{
+    db::{Db, UploadOutcome, now},
+    fulfillers::ActiveFulfillers,
+    quota::{QuotaExceeded, Quotas},
+    stdin::{MasterKey, Stdin, StdinWriter, TransportKey},
+    url_signer::UrlSigner,
+};
+
+/// The state of the stdin upload route.
+pub struct UploadState<DB: Db> {
+    pub db: Arc<DB>,
+    /// Verifies the tokens of the presigned upload URLs.
+    pub url_signer: Arc<UrlSigner>,
+    /// Uploads larger than this are rejected.
+    pub max_stdin_size: u64,
+    /// Limits the total size of the stdins of each requester.
+    pub quotas: Arc<Quotas>,
+    /// Wraps the data keys of the uploaded stdins.
+    pub master_key: Arc<MasterKey>,
+    /// Derives the data keys of the stdins encrypted by the clients.
+    pub transport_key: Arc<TransportKey>,
+    /// Uploads larger than this are streamed to an encrypted spill file instead of kept in memory.
+    pub spill_threshold: u64,
+    pub spill_dir: PathBuf,
+}
+
+/// How far the timestamp of a signed download can be from the server clock.
+const DOWNLOAD_SIGNATURE_VALIDITY: Duration = Duration::from_secs(60);
+
+/// The state of the stdin download route.
+pub struct DownloadState<DB: Db> {
+    pub db: Arc<DB>,
+    /// Unwraps the data keys of the stdins.
+    pub master_key: Arc<MasterKey>,
+    /// The addresses allowed to download any stdin.
+    pub fulfillers: Arc<ActiveFulfillers>,
+    /// Delete the stdins after their first complete download.
+    pub single_use: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum UploadError {
+    #[error("stdin larger than the maximum size")]
+    TooLarge,
 
-pub async fn upload_artifact(
+    #[error("malformed stdin: {0}")]
+    Malformed(&'static str),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    token: Option<String>,
+}
+
+pub async fn upload_artifact<DB: Db>(
     Path(id): Path<String>,
-    State(db): State<Arc<InMemoryDb>>,
+    Query(query): Query<UploadQuery>,
+    State(state): State<Arc<UploadState<DB>>>,
+    headers: HeaderMap,
     body: Body,
-) -> StatusCode {
-    let stream = body.into_data_stream().map_err(std::io::Error::other);
-    let mut async_reader = StreamReader::new(stream);
-
+) -> Response {
     tracing::debug!("Upload {id}");
 
-    if !db.consume_artifact_request(id.clone()).await {
-        return StatusCode::UNAUTHORIZED;
+    if !query
+        .token
+        .is_some_and(|token| state.url_signer.verify(&id, &token))
+    {
+        tracing::warn!("Rejected upload {id} with a missing, invalid or expired token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    // Reject early the uploads that announce a body over the limit.
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    if content_length.is_some_and(|len| len > state.max_stdin_size) {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
     }
 
-    let mut buf = vec![];
+    let encrypted = match headers.get(STDIN_ENCRYPTION_HEADER) {
+        None => false,
+        Some(scheme) if scheme == STDIN_ENCRYPTION_SCHEME => true,
+        Some(scheme) => {
+            tracing::warn!("Rejected upload {id} with an unknown encryption scheme {scheme:?}");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
 
-    match async_reader.read_to_end(&mut buf).await {
-        Ok(_) => {
-            db.insert_stdin(id, buf).await;
-            StatusCode::OK
+    let owner = match state.db.artifact_request_owner(&id).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(err) => {
+            tracing::error!("Failed to get the artifact request: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // The stdin must also fit in the stdin quota of the requester. It is checked again when
+    // storing the stdin, as other uploads of the requester may have completed in the meantime.
+    let max_stdin_bytes = state.quotas.limits(&owner).max_stdin_bytes;
+    let max_size = match state.db.requester_usage(&owner).await {
+        Ok(usage) => state
+            .quotas
+            .remaining_stdin_bytes(&owner, &usage)
+            .map_or(state.max_stdin_size, |remaining| {
+                remaining.min(state.max_stdin_size)
+            }),
+        Err(err) => {
+            tracing::error!("Failed to get the requester usage: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let over_quota = max_size < state.max_stdin_size;
+
+    if over_quota && (max_size == 0 || content_length.is_some_and(|len| len > max_size)) {
+        return QuotaExceeded::stdin(&owner).into_response();
+    }
+
+    match receive_stdin(&state, &id, encrypted, max_size, body).await {
+        Ok(stdin) => {
+            tracing::debug!("Received stdin {id} ({} bytes)", stdin.len());
+
+            // The upload URL is consumed along with storing the stdin, so a failed upload can be
+            // retried, and only one of concurrent uploads to the same URL is stored.
+            let outcome = state
+                .db
+                .store_upload(id, owner, stdin.clone(), max_stdin_bytes)
+                .await;
+
+            if !matches!(outcome, Ok(UploadOutcome::Stored)) {
+                stdin.remove().await;
+            }
+
+            match outcome {
+                Ok(UploadOutcome::Stored) => StatusCode::OK.into_response(),
+                Ok(UploadOutcome::NotPending) => StatusCode::UNAUTHORIZED.into_response(),
+                Ok(UploadOutcome::OverQuota) => QuotaExceeded::stdin(&owner).into_response(),
+                Err(err) => {
+                    tracing::error!("Failed to store stdin artifact: {err}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }
+        Err(UploadError::TooLarge) if over_quota => QuotaExceeded::stdin(&owner).into_response(),
+        Err(UploadError::TooLarge) => StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+        Err(err @ UploadError::Malformed(_)) => {
+            tracing::warn!("Rejected upload {id}: {err}");
+            StatusCode::BAD_REQUEST.into_response()
         }
         Err(err) => {
             tracing::error!("Failed to read sdtin artifact: {err}");
-            StatusCode::INTERNAL_SERVER_ERROR
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
 
-pub async fn download_artifact(
+/// Read the stdin from the request body, up to `max_size` bytes, and encrypt it unless the client
+/// already encrypted it to the transport key.
+async fn receive_stdin<DB: Db>(
+    state: &UploadState<DB>,
+    id: &str,
+    encrypted: bool,
+    max_size: u64,
+    body: Body,
+) -> Result<Stdin, UploadError> {
+    let mut stream = body.into_data_stream().map_err(io::Error::other);
+    // Concurrent uploads to the same URL are each received in their own file, until one of them
+    // is stored.
+    let spill_path = state
+        .spill_dir
+        .join(format!("{id}-{}.stdin", "upload".create_type_id::<V7>()));
+    let mut writer =
+        (!encrypted).then(|| StdinWriter::new(spill_path.clone(), state.spill_threshold));
+    // The ephemeral public key heading an encrypted body, buffered until complete.
+    let mut ephemeral_public_key = Vec::with_capacity(PUBLIC_KEY_SIZE);
+    let mut len = 0;
+
+    while let Some(mut chunk) = stream.try_next().await? {
+        len += chunk.len() as u64;
+
+        if len > max_size {
+            return Err(UploadError::TooLarge);
+        }
+
+        if writer.is_none() {
+            let missing = PUBLIC_KEY_SIZE - ephemeral_public_key.len();
+            let key_part = chunk.split_to(missing.min(chunk.len()));
+            ephemeral_public_key.extend_from_slice(&key_part);
+
+            if ephemeral_public_key.len() < PUBLIC_KEY_SIZE {
+                continue;
+            }
+
+            let data_key = state
+                .transport_key
+                .data_key(&ephemeral_public_key)
+                .map_err(|_| UploadError::Malformed("invalid ephemeral public key"))?;
+            writer = Some(StdinWriter::encrypted(
+                spill_path.clone(),
+                state.spill_threshold,
+                data_key,
+            ));
+        }
+
+        if let Some(writer) = &mut writer {
+            writer.write(&chunk).await?;
+        }
+    }
+
+    let writer = writer.ok_or(UploadError::Malformed("missing ephemeral public key"))?;
+
+    writer
+        .finish(&state.master_key)
+        .await
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::InvalidData => UploadError::Malformed("truncated encrypted stdin"),
+            _ => err.into(),
+        })
+}
+
+/// Serve a stdin to the fulfiller, decrypting it on the fly. This is the only place where the
+/// stored stdins are decrypted.
+pub async fn download_artifact<DB: Db>(
     Path(id): Path<String>,
-    State(db): State<Arc<InMemoryDb>>,
-) -> Result<Vec<u8>, StatusCode> {
-    match db.get_stdin(&id).await {
-        Some(stdin) => Ok((*stdin).clone()),
-        None => Err(StatusCode::NOT_FOUND),
+    State(state): State<Arc<DownloadState<DB>>>,
+    headers: HeaderMap,
+) -> Result<Body, StatusCode> {
+    if !is_signed_by_fulfiller(&state, &id, &headers).await {
+        tracing::warn!("Rejected download {id} without a valid fulfiller signature");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match state.db.get_stdin(&id).await {
+        Ok(Some(stdin)) => match stdin.decrypt(&state.master_key) {
+            Ok(stream) if state.single_use => Ok(Body::from_stream(remove_after_download(
+                state.db.clone(),
+                id,
+                stream,
+            ))),
+            Ok(stream) => Ok(Body::from_stream(stream)),
+            Err(err) => {
+                tracing::error!("Failed to decrypt stdin artifact: {err}");
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        },
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            tracing::error!("Failed to retrieve stdin artifact: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Stream a stdin, and delete it once fully sent.
+fn remove_after_download<DB: Db>(
+    db: Arc<DB>,
+    id: String,
+    stream: impl Stream<Item = io::Result<Bytes>>,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    try_stream! {
+        for await chunk in stream {
+            yield chunk?;
+        }
+
+        match db.remove_stdin(&id).await {
+            Ok(_) => tracing::debug!("Deleted stdin {id} after its download"),
+            Err(err) => tracing::error!("Failed to delete downloaded stdin: {err}"),
+        }
+    }
+}
+
+/// Whether the download carries a recent signature of the artifact ID by an active fulfiller key,
+/// or by the fulfiller assigned to a leased request for the stdin, whose key may have been retired
+/// since the request was accepted.
+async fn is_signed_by_fulfiller<DB: Db>(
+    state: &DownloadState<DB>,
+    id: &str,
+    headers: &HeaderMap,
+) -> bool {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let Some(timestamp) = header(DOWNLOAD_TIMESTAMP_HEADER).and_then(|t| t.parse::<u64>().ok())
+    else {
+        return false;
+    };
+    let Some(signature) = header(DOWNLOAD_SIGNATURE_HEADER)
+        .and_then(|signature| hex::decode(signature.trim_start_matches("0x")).ok())
+    else {
+        return false;
+    };
+
+    if now().abs_diff(timestamp) > DOWNLOAD_SIGNATURE_VALIDITY.as_secs() {
+        return false;
+    }
+    let Ok(signer) = recover_signer(&download_message(id, timestamp), &signature) else {
+        return false;
+    };
+
+    if state.fulfillers.contains(signer.as_slice()) {
+        return true;
+    }
+
+    match state.db.leased_fulfillers(id).await {
+        Ok(fulfillers) => fulfillers.contains(&signer),
+        Err(err) => {
+            tracing::error!("Failed to get the fulfillers of stdin {id}: {err}");
+            false
+        }
     }
 }
@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use alloy_primitives::Address;
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sp1_tee_private_utils::{
+    ATTESTATION_TAG, DstackClient, ENCLAVE_KEY_TAG, MAX_REPORT_DATA_SIZE,
+    ra_tls::public_key_report_data, tagged_report_data,
+};
+use tonic::Status;
+
+use crate::{fulfillers::ActiveFulfillers, stdin::TransportKey};
+
+/// The size of the nonce of an attestation request.
+pub const NONCE_SIZE: usize = 32;
+
+/// The maximum size of the caller report data of an attestation request.
+pub const MAX_CALLER_REPORT_DATA_SIZE: usize = 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttestationError {
+    #[error("the nonce must be {NONCE_SIZE} bytes")]
+    InvalidNonce,
+
+    #[error("the report data must be at most {MAX_CALLER_REPORT_DATA_SIZE} bytes")]
+    ReportDataTooLarge,
+
+    #[error("failed to get a quote: {0}")]
+    Dstack(#[from] anyhow::Error),
+}
+
+impl From<AttestationError> for Status {
+    fn from(err: AttestationError) -> Self {
+        match err {
+            AttestationError::Dstack(_) => Status::unavailable(err.to_string()),
+            _ => Status::invalid_argument(err.to_string()),
+        }
+    }
+}
+
+impl From<AttestationError> for StatusCode {
+    fn from(err: AttestationError) -> Self {
+        match err {
+            AttestationError::Dstack(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// A TDX quote, fetched from the dstack guest agent.
+#[derive(Debug)]
+pub struct Attestation {
+    pub quote: Vec<u8>,
+    pub event_log: String,
+    pub report_data: [u8; MAX_REPORT_DATA_SIZE],
+    /// The fulfiller addresses bound to the report data, if any.
+    pub fulfiller_addresses: Vec<Address>,
+}
+
+/// Produces the attestations of the enclave, shared by the HTTP and gRPC APIs.
+pub struct Attester {
+    dstack: DstackClient,
+    /// The active fulfiller addresses, bound to the attestations.
+    fulfillers: Arc<ActiveFulfillers>,
+}
+
+impl Attester {
+    pub fn new(dstack: DstackClient, fulfillers: Arc<ActiveFulfillers>) -> Self {
+        Self { dstack, fulfillers }
+    }
+
+    /// Get a fresh quote whose report data binds the nonce, the active fulfiller addresses and
+    /// the caller report data, see [`attestation_report_data`].
+    pub async fn attest(
+        &self,
+        nonce: &[u8],
+        report_data: &[u8],
+    ) -> Result<Attestation, AttestationError> {
+        if nonce.len() != NONCE_SIZE {
+            return Err(AttestationError::InvalidNonce);
+        }
+        if report_data.len() > MAX_CALLER_REPORT_DATA_SIZE {
+            return Err(AttestationError::ReportDataTooLarge);
+        }
+
+        let fulfiller_addresses = self.fulfillers.addresses();
+
+        let mut attestation = self
+            .quote(attestation_report_data(
+                nonce,
+                &fulfiller_addresses,
+                report_data,
+            ))
+            .await?;
+        attestation.fulfiller_addresses = fulfiller_addresses;
+
+        Ok(attestation)
+    }
+
+    /// Get a fresh quote embedding `report_data`.
+    pub async fn quote(
+        &self,
+        report_data: [u8; MAX_REPORT_DATA_SIZE],
+    ) -> Result<Attestation, AttestationError> {
+        let response = self.dstack.get_quote(&report_data).await?;
+        let quote = hex::decode(response.quote.trim_start_matches("0x"))
+            .map_err(|err| anyhow::anyhow!("invalid quote: {err}"))?;
+
+        Ok(Attestation {
+            quote,
+            event_log: response.event_log,
+            report_data,
+            fulfiller_addresses: Vec::new(),
+        })
+    }
+}
+
+/// The report data of an attestation: the [`ATTESTATION_TAG`] tagged hash of the nonce, the
+/// number of fulfiller addresses as a 4-byte big-endian integer, the addresses in ascending
+/// order, the length of the caller report data as a 4-byte big-endian integer, and the caller
+/// report data. The counts keep the caller data from being read as one more address.
+pub fn attestation_report_data(
+    nonce: &[u8],
+    fulfiller_addresses: &[Address],
+    report_data: &[u8],
+) -> [u8; MAX_REPORT_DATA_SIZE] {
+    let address_count = (fulfiller_addresses.len() as u32).to_be_bytes();
+    let report_data_len = (report_data.len() as u32).to_be_bytes();
+
+    let mut parts = vec![nonce, &address_count[..]];
+    parts.extend(fulfiller_addresses.iter().map(Address::as_slice));
+    parts.extend([&report_data_len[..], report_data]);
+
+    tagged_report_data(ATTESTATION_TAG, &parts)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttestationQuery {
+    /// The hex-encoded nonce.
+    nonce: String,
+    /// The hex-encoded caller report data.
+    #[serde(default)]
+    report_data: String,
+}
+
+/// An attestation, in the format of the dstack guest agent quotes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    /// The hex-encoded TDX quote.
+    pub quote: String,
+    pub event_log: String,
+    /// The hex-encoded report data embedded in the quote.
+    pub report_data: String,
+    /// The active fulfiller addresses bound to the report data, in ascending order.
+    pub fulfiller_addresses: Vec<String>,
+}
+
+pub async fn attestation(
+    Query(query): Query<AttestationQuery>,
+    State(attester): State<Arc<Attester>>,
+) -> Result<Json<AttestationResponse>, StatusCode> {
+    let nonce = hex::decode(&query.nonce).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let report_data = hex::decode(&query.report_data).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let attestation = attester
+        .attest(&nonce, &report_data)
+        .await
+        .inspect_err(|err| tracing::warn!("Failed to attest: {err}"))?;
+
+    Ok(Json(AttestationResponse {
+        quote: hex::encode(attestation.quote),
+        event_log: attestation.event_log,
+        report_data: hex::encode(attestation.report_data),
+        fulfiller_addresses: attestation
+            .fulfiller_addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    }))
+}
+
+/// The enclave transport key, alongside a TDX quote whose report data is the [`ENCLAVE_KEY_TAG`]
+/// tagged hash of the key, so the clients can check it belongs to an attested enclave before
+/// encrypting to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnclaveKeyResponse {
+    /// The hex-encoded SEC1 uncompressed P-256 public key.
+    pub public_key: String,
+    /// The hex-encoded TDX quote.
+    pub quote: String,
+    pub event_log: String,
+}
+
+pub async fn enclave_key(
+    State((attester, transport_key)): State<(Arc<Attester>, Arc<TransportKey>)>,
+) -> Result<Json<EnclaveKeyResponse>, StatusCode> {
+    let public_key = transport_key.public_key();
+
+    let attestation = attester
+        .quote(public_key_report_data(ENCLAVE_KEY_TAG, public_key))
+        .await
+        .inspect_err(|err| {
+            tracing::error!("Failed to get a quote for the enclave key: {err}");
+        })?;
+
+    Ok(Json(EnclaveKeyResponse {
+        public_key: hex::encode(public_key),
+        quote: hex::encode(attestation.quote),
+        event_log: attestation.event_log,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use sp1_tee_private_utils::dstack_mock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_attest() {
+        let socket_path =
+            std::env::temp_dir().join(format!("dstack-test-{}.sock", std::process::id()));
+        dstack_mock::spawn(&socket_path, [0; 32]);
+        let fulfillers = Arc::new(ActiveFulfillers::new([
+            Address::repeat_byte(4),
+            Address::repeat_byte(3),
+        ]));
+        let attester = Attester::new(DstackClient::new(&socket_path), fulfillers);
+
+        let attestation = attester.attest(&[1; NONCE_SIZE], &[2; 4]).await.unwrap();
+        let expected = tagged_report_data(
+            ATTESTATION_TAG,
+            &[
+                &[1; NONCE_SIZE],
+                &2u32.to_be_bytes(),
+                &[3; 20],
+                &[4; 20],
+                &4u32.to_be_bytes(),
+                &[2; 4],
+            ],
+        );
+
+        assert_eq!(attestation.report_data, expected);
+        assert_eq!(attestation.quote, expected);
+        assert_eq!(attestation.event_log, "[]");
+        assert_eq!(
+            attestation.fulfiller_addresses,
+            vec![Address::repeat_byte(3), Address::repeat_byte(4)]
+        );
+
+        assert!(matches!(
+            attester.attest(&[1; 16], &[]).await,
+            Err(AttestationError::InvalidNonce)
+        ));
+        assert!(matches!(
+            attester
+                .attest(&[1; NONCE_SIZE], &[2; MAX_CALLER_REPORT_DATA_SIZE + 1])
+                .await,
+            Err(AttestationError::ReportDataTooLarge)
+        ));
+
+        let _ = std::fs::remove_file(&socket_path);
+        assert!(matches!(
+            attester.attest(&[1; NONCE_SIZE], &[]).await,
+            Err(AttestationError::Dstack(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_attest_cannot_bind_enclave_key() {
+        let socket_path =
+            std::env::temp_dir().join(format!("dstack-test-key-{}.sock", std::process::id()));
+        dstack_mock::spawn(&socket_path, [0; 32]);
+        let fulfillers = Arc::new(ActiveFulfillers::new([Address::repeat_byte(3)]));
+        let attester = Attester::new(DstackClient::new(&socket_path), fulfillers);
+
+        let public_key = [4; 65];
+        let enclave_key_report_data = public_key_report_data(ENCLAVE_KEY_TAG, &public_key);
+        let key_hash = Sha256::digest(public_key);
+
+        // Neither the enclave key report data itself, nor the former untagged layout, can be
+        // passed as the nonce and caller report data to get a quote binding the key.
+        for (nonce, report_data) in [
+            (
+                &enclave_key_report_data[..NONCE_SIZE],
+                &enclave_key_report_data[NONCE_SIZE..],
+            ),
+            (&key_hash[..], &[][..]),
+            (&key_hash[..], &[0; 32][..]),
+        ] {
+            let attestation = attester.attest(nonce, report_data).await.unwrap();
+            assert_ne!(attestation.report_data, enclave_key_report_data);
+        }
+
+        let _ = std::fs::remove_file(&socket_path);
+    }
+
+    #[test]
+    fn test_report_data_cannot_bind_extra_address() {
+        let nonce = [1; NONCE_SIZE];
+        let address = Address::repeat_byte(3);
+        let extra = Address::repeat_byte(4);
+
+        // Caller data starting with an address does not read as one more fulfiller address.
+        let mut report_data = extra.to_vec();
+        report_data.extend([2; 4]);
+        assert_ne!(
+            attestation_report_data(&nonce, &[address], &report_data),
+            attestation_report_data(&nonce, &[address, extra], &[2; 4])
+        );
+        assert_ne!(
+            attestation_report_data(&nonce, &[address, extra], &[]),
+            attestation_report_data(&nonce, &[address], extra.as_slice())
+        );
+    }
+}
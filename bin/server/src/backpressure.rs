@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tonic::Status;
+
+use crate::quota::resource_exhausted;
+
+/// The weight of the latest proving time in the moving average.
+const PROVING_TIME_WEIGHT: f64 = 0.2;
+
+/// How long a lease is tracked without being acked or nacked, before it is assumed lost.
+const STALE_LEASE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The retry hint when the proving time is not known yet.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Turns new proof requests away when the queue is deeper, or the estimated wait longer, than
+/// configured, so the clients can fall back instead of missing their deadlines.
+///
+/// The wait is estimated from the moving average of the proving times, measured between the
+/// lease and the ack of the requests, spread over the requests being proved at once.
+#[derive(Debug, Default)]
+pub struct Backpressure {
+    max_queue_depth: Option<usize>,
+    max_estimated_wait: Option<Duration>,
+    proving_times: Mutex<ProvingTimes>,
+}
+
+#[derive(Debug, Default)]
+struct ProvingTimes {
+    /// When each lease currently held was handed out.
+    leased_at: HashMap<String, Instant>,
+    average: Option<Duration>,
+}
+
+/// A proof request turned away because the server is overloaded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{reason}")]
+pub struct Overloaded {
+    reason: String,
+    retry_after: Duration,
+}
+
+impl From<Overloaded> for Status {
+    fn from(overloaded: Overloaded) -> Self {
+        resource_exhausted(&overloaded.reason, overloaded.retry_after)
+    }
+}
+
+impl Backpressure {
+    pub fn new(max_queue_depth: Option<usize>, max_estimated_wait: Option<Duration>) -> Self {
+        Self {
+            max_queue_depth,
+            max_estimated_wait,
+            proving_times: Mutex::default(),
+        }
+    }
+
+    /// Start timing a lease handed out to a fulfiller worker.
+    pub fn leased(&self, lease_id: &str) {
+        let mut proving_times = self.proving_times.lock().unwrap();
+
+        proving_times
+            .leased_at
+            .retain(|_, leased_at| leased_at.elapsed() < STALE_LEASE);
+        proving_times
+            .leased_at
+            .insert(lease_id.to_string(), Instant::now());
+    }
+
+    /// Record the proving time of an acked lease.
+    pub fn completed(&self, lease_id: &str) {
+        let leased_at = self
+            .proving_times
+            .lock()
+            .unwrap()
+            .leased_at
+            .remove(lease_id);
+
+        if let Some(leased_at) = leased_at {
+            self.record(leased_at.elapsed());
+        }
+    }
+
+    /// Stop timing a nacked lease.
+    pub fn released(&self, lease_id: &str) {
+        self.proving_times
+            .lock()
+            .unwrap()
+            .leased_at
+            .remove(lease_id);
+    }
+
+    fn record(&self, proving_time: Duration) {
+        let mut proving_times = self.proving_times.lock().unwrap();
+
+        proving_times.average = Some(match proving_times.average {
+            Some(average) => {
+                average.mul_f64(1.0 - PROVING_TIME_WEIGHT)
+                    + proving_time.mul_f64(PROVING_TIME_WEIGHT)
+            }
+            None => proving_time,
+        });
+    }
+
+    /// How long a request entering the queue now is expected to wait before being leased, or
+    /// `None` before the first request is proved.
+    pub fn estimated_wait(&self, queued: usize, leased: usize) -> Option<Duration> {
+        let average = self.proving_times.lock().unwrap().average?;
+
+        Some(average.mul_f64(queued as f64 / leased.max(1) as f64))
+    }
+
+    /// Check that the server accepts a new proof request, given the number of queued and leased
+    /// requests.
+    pub fn check(&self, queued: usize, leased: usize) -> Result<(), Overloaded> {
+        let estimated_wait = self.estimated_wait(queued, leased);
+
+        if let Some(max) = self.max_queue_depth
+            && queued >= max
+        {
+            // Wait for the requests above the limit to be handed out.
+            let excess = (queued + 1 - max) as f64 / queued.max(1) as f64;
+            return Err(Overloaded {
+                reason: format!("The queue is full, with {queued} proof requests"),
+                retry_after: estimated_wait
+                    .map_or(DEFAULT_RETRY_AFTER, |wait| wait.mul_f64(excess)),
+            });
+        }
+
+        if let Some(max) = self.max_estimated_wait
+            && let Some(wait) = estimated_wait
+            && wait > max
+        {
+            return Err(Overloaded {
+                reason: format!(
+                    "The estimated wait of {}s exceeds the maximum of {}s",
+                    wait.as_secs(),
+                    max.as_secs()
+                ),
+                retry_after: wait - max,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backpressure() {
+        let backpressure = Backpressure::new(Some(10), Some(Duration::from_secs(100)));
+
+        // The wait is unknown until a request is proved.
+        assert_eq!(backpressure.estimated_wait(9, 1), None);
+        assert!(backpressure.check(9, 1).is_ok());
+        assert!(backpressure.check(10, 1).is_err());
+
+        backpressure.record(Duration::from_secs(20));
+        backpressure.record(Duration::from_secs(70));
+        assert_eq!(
+            backpressure.estimated_wait(4, 2),
+            Some(Duration::from_secs(60))
+        );
+        assert!(backpressure.check(3, 1).is_ok());
+
+        let overloaded = backpressure.check(4, 1).unwrap_err();
+        assert_eq!(overloaded.retry_after, Duration::from_secs(20));
+
+        let overloaded = backpressure.check(10, 5).unwrap_err();
+        assert_eq!(overloaded.retry_after, Duration::from_secs(6));
+
+        backpressure.leased("lease_1");
+        backpressure.released("lease_1");
+        backpressure.completed("lease_1");
+        assert_eq!(
+            backpressure.estimated_wait(1, 1),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_empty_queue_at_zero_depth() {
+        let backpressure = Backpressure::new(Some(0), None);
+        backpressure.record(Duration::from_secs(20));
+
+        let overloaded = backpressure.check(0, 0).unwrap_err();
+        assert_eq!(overloaded.retry_after, Duration::ZERO);
+    }
+}
@@ -1,4 +1,8 @@
-use clap::Parser;
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use alloy_primitives::Address;
+use clap::{ArgAction, Parser};
+use sp1_tee_private_utils::{DSTACK_SOCKET_PATH, FulfillerKeySource};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -11,15 +15,187 @@ pub struct Args {
     #[clap(long, env)]
     pub network_rpc_url: String,
 
-    /// The fulfiller private key.
+    /// The comma-separated fulfiller private keys, used when the key source is `env`.
+    #[clap(long, env, value_delimiter = ',')]
+    pub fulfiller_private_key: Vec<String>,
+
+    /// Where the fulfiller keys come from: `env` for the fulfiller private keys, `dstack` to
+    /// derive them from the dstack KMS, so they never exist outside of the enclave, or `remote`
+    /// to sign with the remote signing services.
+    #[clap(long, env, default_value = "env")]
+    pub fulfiller_key_source: FulfillerKeySource,
+
+    /// The comma-separated IDs of the keys derived from the dstack KMS, used when the key source
+    /// is `dstack`. The default key is derived if there is none.
+    #[clap(long, env, value_delimiter = ',')]
+    pub fulfiller_key_ids: Vec<String>,
+
+    /// The comma-separated URLs of the remote signing services, one per key, used when the key
+    /// source is `remote`.
+    #[clap(long, env, value_delimiter = ',')]
+    pub remote_signer_url: Vec<String>,
+
+    /// A file listing the active fulfiller addresses, one per line, reloaded when it changes.
+    /// Defaults to the addresses of the fulfiller keys.
+    #[clap(long, env)]
+    pub fulfiller_addresses_path: Option<PathBuf>,
+
+    /// A TOML file of allow and deny rules on the requesters and their proof requests, reloaded
+    /// when it changes. Everything is allowed if not set.
+    #[clap(long, env)]
+    pub policy_path: Option<PathBuf>,
+
+    /// A TOML file of per-requester quotas, reloaded when it changes. The requesters are not
+    /// limited if not set.
     #[clap(long, env)]
-    pub fulfiller_private_key: String,
+    pub quotas_path: Option<PathBuf>,
+
+    /// The bearer token of the admin endpoints, which are disabled if not set.
+    #[clap(long, env)]
+    pub admin_token: Option<String>,
+
+    /// The comma-separated addresses of the admin keys, allowed to call the admin service and to
+    /// cancel any proof request. The admin service is disabled if there is none.
+    #[clap(long, env, value_delimiter = ',')]
+    pub admin_addresses: Vec<Address>,
 
     /// The port for the server.
     #[clap(short, long, default_value = "8080")]
     pub server_port: u16,
 
-    /// The port for the artifacts download.
+    /// Terminate TLS on the server port with RA-TLS, using a self-signed certificate carrying a
+    /// TDX quote of the enclave.
+    #[clap(long, env)]
+    pub ra_tls: bool,
+
+    /// Authenticate the fulfiller, and encrypt the traffic on the artifacts port, with TLS
+    /// certificates issued by a CA whose key is derived from the dstack KMS. It can only be
+    /// disabled in the builds with the `local` feature.
+    #[clap(long, env, default_value_t = true, action = ArgAction::Set)]
+    pub internal_tls: bool,
+
+    /// The port of the internal channel, serving the artifacts download and the fulfiller
+    /// methods.
     #[clap(short, long, default_value = "8081")]
     pub artifacts_port: u16,
+
+    /// The path of the dstack guest agent socket, used to get the TDX quotes.
+    #[clap(long, env, default_value = DSTACK_SOCKET_PATH)]
+    pub dstack_socket_path: PathBuf,
+
+    /// The path of the SQLite database used to persist the server state. If not set, the state
+    /// is kept in memory and lost on restart.
+    #[clap(long, env)]
+    pub database_path: Option<PathBuf>,
+
+    /// How long a presigned stdin upload URL stays valid, in seconds.
+    #[clap(long, env, default_value = "3600")]
+    pub stdin_url_ttl_secs: u64,
+
+    /// The maximum size of an uploaded stdin, in bytes. Larger uploads are rejected.
+    #[clap(long, env, default_value = "1073741824")]
+    pub max_stdin_size: u64,
+
+    /// The size in bytes above which an uploaded stdin is streamed to an encrypted spill file
+    /// instead of being kept in memory.
+    #[clap(long, env, default_value = "16777216")]
+    pub stdin_spill_threshold: u64,
+
+    /// How long an uploaded stdin is kept while no proof request refers to it, in seconds.
+    #[clap(long, env, default_value = "3600")]
+    pub stdin_unclaimed_retention_secs: u64,
+
+    /// How long a stdin is kept once its proof requests are fulfilled, failed or expired, in
+    /// seconds. With 0, the stdin is deleted as soon as its last proof request finishes.
+    #[clap(long, env, default_value = "0")]
+    pub stdin_retention_secs: u64,
+
+    /// How long a proof request is remembered once completed, failed, expired or cancelled, in
+    /// seconds. Its status can no longer be queried afterwards.
+    #[clap(long, env, default_value = "86400")]
+    pub request_retention_secs: u64,
+
+    /// The hex-encoded 32-byte key wrapping the data keys of the stored stdins, instead of the key
+    /// derived from the dstack KMS. Only for local development.
+    #[cfg(feature = "local")]
+    #[clap(long, env, value_parser = parse_master_key)]
+    pub stdin_master_key: Option<[u8; 32]>,
+
+    /// Delete the stdins after their first complete download by the fulfiller. A request whose
+    /// proving fails after the download can then not be retried.
+    #[clap(long, env)]
+    pub single_use_stdin_downloads: bool,
+
+    /// The directory where the stdins above the spill threshold are stored. Defaults to the
+    /// `stdins` directory next to the SQLite database, so the spilled stdins survive a restart
+    /// along with it, or to a temporary directory if the state is kept in memory.
+    #[clap(long, env)]
+    pub stdin_spill_dir: Option<PathBuf>,
+
+    /// How long a fulfiller worker holds a proof request before it goes back on the queue,
+    /// unless the lease is renewed.
+    #[clap(long, env, default_value = "120")]
+    pub lease_duration_secs: u64,
+
+    /// The per-requester priority classes, as a comma-separated list of `<address>=<priority>`.
+    /// Requests from a higher priority class are handed out first. Unlisted requesters have
+    /// priority 0.
+    #[clap(long, env, value_delimiter = ',', value_parser = parse_priority_class)]
+    pub priority_classes: Vec<(Address, u32)>,
+
+    /// The per-requester weights, as a comma-separated list of `<address>=<weight>`. Within a
+    /// priority class, the requesters share the fulfiller in proportion to their weight.
+    /// Unlisted requesters have weight 1.
+    #[clap(long, env, value_delimiter = ',', value_parser = parse_requester_weight)]
+    pub requester_weights: Vec<(Address, u32)>,
+
+    /// The maximum number of queued proof requests, at least 1. New requests are rejected once
+    /// reached.
+    #[clap(long, env)]
+    pub max_queue_depth: Option<NonZeroUsize>,
+
+    /// The maximum estimated wait of a new proof request before it is leased, in seconds. New
+    /// requests are rejected once exceeded.
+    #[clap(long, env)]
+    pub max_estimated_wait_secs: Option<u64>,
+}
+
+#[cfg(feature = "local")]
+fn parse_master_key(s: &str) -> Result<[u8; 32], String> {
+    hex::decode(s.trim_start_matches("0x"))
+        .map_err(|err| format!("invalid master key: {err}"))?
+        .try_into()
+        .map_err(|_| "invalid master key: expected 32 bytes".to_string())
+}
+
+fn parse_priority_class(s: &str) -> Result<(Address, u32), String> {
+    parse_requester_value(s, "priority")
+}
+
+fn parse_requester_weight(s: &str) -> Result<(Address, u32), String> {
+    let (address, weight) = parse_requester_value(s, "weight")?;
+
+    if weight == 0 {
+        return Err(format!("invalid weight of {address}, expected at least 1"));
+    }
+
+    Ok((address, weight))
+}
+
+/// Parse an `<address>=<value>` pair.
+fn parse_requester_value(s: &str, name: &str) -> Result<(Address, u32), String> {
+    let (address, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid {name} `{s}`, expected <address>=<{name}>"))?;
+
+    let address = address
+        .trim()
+        .parse::<Address>()
+        .map_err(|err| format!("invalid requester address `{address}`: {err}"))?;
+    let value = value
+        .trim()
+        .parse::<u32>()
+        .map_err(|err| format!("invalid {name} `{value}`: {err}"))?;
+
+    Ok((address, value))
 }
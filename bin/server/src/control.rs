@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tonic::Status;
+
+/// The dispatch switches operated by the admins. They are kept in memory, and reset on restart.
+#[derive(Debug, Default)]
+pub struct DispatchControl {
+    /// Hold the queued proof requests back from the fulfiller.
+    paused: AtomicBool,
+    /// Reject the new proof requests, while still proving the queued ones.
+    draining: AtomicBool,
+}
+
+impl DispatchControl {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Go back to normal operation, ending both the pause and the drain.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.draining.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Check that the server accepts new proof requests.
+    pub fn check_accepting(&self) -> Result<(), Status> {
+        if self.is_draining() {
+            return Err(Status::unavailable(
+                "The server is draining, and no longer accepts proof requests",
+            ));
+        }
+
+        Ok(())
+    }
+}
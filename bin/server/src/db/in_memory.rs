@@ -1,74 +1,778 @@
 use std::{
-    collections::{HashSet, VecDeque},
-    num::NonZeroUsize,
+    collections::{HashMap, VecDeque},
     sync::Arc,
+    time::Duration,
 };
 
-use lru::LruCache;
+use alloy_primitives::Address;
 use sp1_sdk::network::proto::base_types::ProofRequest;
 use tokio::sync::Mutex;
 use tonic::async_trait;
 
-use crate::db::Db;
+use crate::{
+    db::{
+        AdminAction, CancelledRequest, Db, DbError, Lease, MAX_LEASE_ATTEMPTS, ProofRequestState,
+        QueueEntry, RequestInfo, Scheduler, StdinRetention, StdinStoreStats, StoredUsage,
+        TenantQueue, UploadOutcome, generate_lease_id, now,
+    },
+    stdin::{Stdin, StdinData},
+};
+
+/// The number of admin actions kept in the audit log, the oldest are dropped first.
+const MAX_ADMIN_ACTIONS: usize = 10_000;
 
 #[derive(Debug)]
 pub struct InMemoryDb {
-    artifact_requests: Mutex<HashSet<String>>,
-    stdins: Mutex<LruCache<String, Arc<Vec<u8>>>>,
-    proof_requests: Mutex<VecDeque<ProofRequest>>,
+    retention: StdinRetention,
+    /// The pending stdin uploads, with their owner and expiry.
+    artifact_requests: Mutex<HashMap<String, (Address, u64)>>,
+    stdins: Mutex<HashMap<String, StoredStdin>>,
+    proof_requests: Mutex<ProofRequests>,
+    /// The most recent admin actions, up to [`MAX_ADMIN_ACTIONS`].
+    admin_actions: Mutex<VecDeque<AdminAction>>,
+}
+
+#[derive(Debug)]
+struct StoredStdin {
+    owner: Address,
+    stdin: Arc<Stdin>,
+    /// When the stdin is deleted, `None` while an active proof request refers to it.
+    expires_at: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct ProofRequests {
+    records: HashMap<Vec<u8>, ProofRequestRecord>,
+    scheduler: Scheduler,
+    /// The stdins of the requests that were completed, failed or expired since the last call to
+    /// [`InMemoryDb::release_stdins`].
+    finished: Vec<String>,
+}
+
+#[derive(Debug)]
+struct ProofRequestRecord {
+    proof_request: ProofRequest,
+    requester: Address,
+    stdin_id: String,
+    state: ProofRequestState,
+    priority: u32,
+    seq: u64,
+    queued_at: u64,
+    lease: Option<(String, u64)>,
+    attempts: u32,
+    /// When the request was completed, failed, expired or cancelled.
+    finished_at: Option<u64>,
+    /// Whether the request was cancelled while queued, and is still to be reported to the
+    /// network by the fulfiller.
+    cancel_pending: bool,
+}
+
+impl ProofRequestRecord {
+    fn finish(&mut self, state: ProofRequestState) {
+        self.state = state;
+        self.lease = None;
+        self.finished_at = Some(now());
+    }
+
+    fn info(&self) -> RequestInfo {
+        RequestInfo {
+            proof_request: self.proof_request.clone(),
+            requester: self.requester,
+            state: self.state,
+            priority: self.priority,
+            queued_at: self.queued_at,
+            lease_expires_at: self.lease.as_ref().map(|(_, expires_at)| *expires_at),
+            attempts: self.attempts,
+        }
+    }
+}
+
+impl ProofRequests {
+    /// Return the record if `lease_id` is the lease currently held on it.
+    fn leased_record(
+        &mut self,
+        request_id: &[u8],
+        lease_id: &str,
+    ) -> Option<&mut ProofRequestRecord> {
+        self.records.get_mut(request_id).filter(|record| {
+            record.state == ProofRequestState::Leased
+                && record.lease.as_ref().is_some_and(|(id, _)| id == lease_id)
+        })
+    }
+
+    fn mark_expired(&mut self, expired: &[QueueEntry]) {
+        for entry in expired {
+            if let Some(record) = self
+                .records
+                .get_mut(entry.request_id())
+                .filter(|record| record.state == ProofRequestState::Queued)
+            {
+                record.finish(ProofRequestState::Expired);
+                self.finished.push(record.stdin_id.clone());
+            }
+        }
+    }
+
+    fn release(&mut self, request_id: &[u8], requeue: bool) {
+        let Some(record) = self.records.get_mut(request_id) else {
+            return;
+        };
+
+        record.lease = None;
+
+        if requeue && record.attempts < MAX_LEASE_ATTEMPTS {
+            record.state = ProofRequestState::Queued;
+            self.scheduler.push(QueueEntry::new(
+                &record.proof_request,
+                record.requester,
+                record.priority,
+                record.seq,
+                record.queued_at,
+            ));
+        } else {
+            record.finish(ProofRequestState::Failed);
+            self.finished.push(record.stdin_id.clone());
+        }
+    }
 }
 
 impl InMemoryDb {
-    pub fn new() -> Self {
+    pub fn new(retention: StdinRetention) -> Self {
         Self {
-            artifact_requests: Mutex::new(HashSet::new()),
-            stdins: Mutex::new(LruCache::new(NonZeroUsize::new(1024).unwrap())),
-            proof_requests: Mutex::new(VecDeque::new()),
+            retention,
+            artifact_requests: Mutex::new(HashMap::new()),
+            stdins: Mutex::new(HashMap::new()),
+            proof_requests: Mutex::new(ProofRequests::default()),
+            admin_actions: Mutex::new(VecDeque::new()),
         }
     }
 
-    pub async fn queued_proof_request_count(&self) -> usize {
-        let proof_requests = self.proof_requests.lock().await;
+    /// Start the retention of the stdins no longer referred to by an active proof request, and
+    /// delete them right away if they are not retained.
+    async fn release_stdins(&self, proof_requests: &mut ProofRequests) {
+        if proof_requests.finished.is_empty() {
+            return;
+        }
+
+        let expires_at = now() + self.retention.after_finish.as_secs();
+        let mut stdins = self.stdins.lock().await;
+
+        for stdin_id in std::mem::take(&mut proof_requests.finished) {
+            let in_use = proof_requests
+                .records
+                .values()
+                .any(|record| record.stdin_id == stdin_id && record.state.is_active());
+
+            if !in_use && let Some(stored) = stdins.get_mut(&stdin_id) {
+                stored.expires_at = Some(expires_at);
+            }
+        }
+
+        drop(stdins);
+
+        if self.retention.after_finish.is_zero() {
+            self.purge_stdins().await;
+        }
+    }
+
+    /// Delete the stdins past their retention.
+    async fn purge_stdins(&self) -> usize {
+        let now = now();
+        let expired = {
+            let mut stdins = self.stdins.lock().await;
+            let ids = stdins
+                .iter()
+                .filter(|(_, stored)| {
+                    stored
+                        .expires_at
+                        .is_some_and(|expires_at| expires_at <= now)
+                })
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>();
+
+            ids.iter()
+                .filter_map(|id| stdins.remove(id))
+                .collect::<Vec<_>>()
+        };
 
-        proof_requests.len()
+        for stored in &expired {
+            stored.stdin.remove().await;
+        }
+
+        expired.len()
     }
 }
 
 #[async_trait]
 impl Db for InMemoryDb {
-    async fn insert_artifact_request(&self, id: String) {
+    async fn insert_artifact_request(
+        &self,
+        id: String,
+        owner: Address,
+        expires_at: u64,
+    ) -> Result<(), DbError> {
         let mut artifact_requests = self.artifact_requests.lock().await;
 
-        artifact_requests.insert(id);
+        artifact_requests.insert(id, (owner, expires_at));
+
+        Ok(())
     }
 
-    async fn consume_artifact_request(&self, id: String) -> bool {
+    async fn artifact_request_owner(&self, id: &str) -> Result<Option<Address>, DbError> {
+        let artifact_requests = self.artifact_requests.lock().await;
+
+        Ok(artifact_requests
+            .get(id)
+            .filter(|(_, expires_at)| *expires_at > now())
+            .map(|(owner, _)| *owner))
+    }
+
+    async fn remove_expired_artifact_requests(&self) -> Result<usize, DbError> {
         let mut artifact_requests = self.artifact_requests.lock().await;
+        let now = now();
+        let count = artifact_requests.len();
+
+        artifact_requests.retain(|_, (_, expires_at)| *expires_at > now);
 
-        artifact_requests.remove(&id)
+        Ok(count - artifact_requests.len())
     }
 
-    async fn insert_stdin(&self, id: String, stdin: Vec<u8>) {
+    async fn insert_stdin(&self, id: String, owner: Address, stdin: Stdin) -> Result<(), DbError> {
         let mut stdins = self.stdins.lock().await;
 
-        stdins.push(id, Arc::new(stdin));
+        stdins.insert(
+            id,
+            StoredStdin {
+                owner,
+                stdin: Arc::new(stdin),
+                expires_at: Some(now() + self.retention.unclaimed.as_secs()),
+            },
+        );
+
+        Ok(())
     }
 
-    async fn get_stdin(&self, id: &str) -> Option<Arc<Vec<u8>>> {
+    async fn store_upload(
+        &self,
+        id: String,
+        owner: Address,
+        stdin: Stdin,
+        max_stdin_bytes: Option<u64>,
+    ) -> Result<UploadOutcome, DbError> {
+        let mut artifact_requests = self.artifact_requests.lock().await;
         let mut stdins = self.stdins.lock().await;
 
-        stdins.get(id).cloned()
+        if !artifact_requests
+            .get(&id)
+            .is_some_and(|(upload_owner, expires_at)| *upload_owner == owner && *expires_at > now())
+        {
+            return Ok(UploadOutcome::NotPending);
+        }
+
+        if let Some(max_stdin_bytes) = max_stdin_bytes {
+            let stdin_bytes = stdins
+                .values()
+                .filter(|stored| stored.owner == owner)
+                .map(|stored| stored.stdin.len())
+                .sum::<u64>();
+
+            if stdin_bytes + stdin.len() > max_stdin_bytes {
+                return Ok(UploadOutcome::OverQuota);
+            }
+        }
+
+        artifact_requests.remove(&id);
+        stdins.insert(
+            id,
+            StoredStdin {
+                owner,
+                stdin: Arc::new(stdin),
+                expires_at: Some(now() + self.retention.unclaimed.as_secs()),
+            },
+        );
+
+        Ok(UploadOutcome::Stored)
+    }
+
+    async fn get_stdin(&self, id: &str) -> Result<Option<Arc<Stdin>>, DbError> {
+        let stdins = self.stdins.lock().await;
+
+        Ok(stdins.get(id).map(|stored| stored.stdin.clone()))
+    }
+
+    async fn stdin_owner(&self, id: &str) -> Result<Option<Address>, DbError> {
+        let stdins = self.stdins.lock().await;
+
+        Ok(stdins.get(id).map(|stored| stored.owner))
+    }
+
+    async fn leased_fulfillers(&self, stdin_id: &str) -> Result<Vec<Address>, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests
+            .records
+            .values()
+            .filter(|record| {
+                record.stdin_id == stdin_id && record.state == ProofRequestState::Leased
+            })
+            .filter_map(|record| record.proof_request.fulfiller.as_deref())
+            .filter_map(|fulfiller| Address::try_from(fulfiller).ok())
+            .collect())
+    }
+
+    async fn remove_stdin(&self, id: &str) -> Result<bool, DbError> {
+        let removed = self.stdins.lock().await.remove(id);
+
+        match removed {
+            Some(stored) => {
+                stored.stdin.remove().await;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn insert_request(
+        &self,
+        proof_request: ProofRequest,
+        requester: Address,
+        stdin_id: String,
+        priority: u32,
+    ) -> Result<(), DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+        let seq = proof_requests.scheduler.next_seq();
+        let queued_at = now();
+
+        // Keep the stdin until the request is done with it.
+        if let Some(stored) = self.stdins.lock().await.get_mut(&stdin_id) {
+            stored.expires_at = None;
+        }
+
+        // A request inserted again replaces the previous one, along with its queue entry.
+        proof_requests.scheduler.remove(&proof_request.request_id);
+        proof_requests.scheduler.push(QueueEntry::new(
+            &proof_request,
+            requester,
+            priority,
+            seq,
+            queued_at,
+        ));
+        proof_requests.records.insert(
+            proof_request.request_id.clone(),
+            ProofRequestRecord {
+                proof_request,
+                requester,
+                stdin_id,
+                state: ProofRequestState::Queued,
+                priority,
+                seq,
+                queued_at,
+                lease: None,
+                attempts: 0,
+                finished_at: None,
+                cancel_pending: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn requester(&self, request_id: &[u8]) -> Result<Option<Address>, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests
+            .records
+            .get(request_id)
+            .map(|record| record.requester))
+    }
+
+    async fn request_state(&self, request_id: &[u8]) -> Result<Option<ProofRequestState>, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests
+            .records
+            .get(request_id)
+            .map(|record| record.state))
+    }
+
+    async fn request_info(&self, request_id: &[u8]) -> Result<Option<RequestInfo>, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests
+            .records
+            .get(request_id)
+            .map(ProofRequestRecord::info))
+    }
+
+    async fn active_requests(&self) -> Result<Vec<RequestInfo>, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        let mut records = proof_requests
+            .records
+            .values()
+            .filter(|record| record.state.is_active())
+            .collect::<Vec<_>>();
+        records.sort_by_key(|record| record.seq);
+
+        Ok(records.into_iter().map(ProofRequestRecord::info).collect())
     }
 
-    async fn insert_request(&self, proof_request: ProofRequest) {
+    async fn set_priority(&self, request_id: &[u8], priority: u32) -> Result<bool, DbError> {
         let mut proof_requests = self.proof_requests.lock().await;
 
-        proof_requests.push_back(proof_request);
+        let Some(record) = proof_requests
+            .records
+            .get_mut(request_id)
+            .filter(|record| record.state.is_active())
+        else {
+            return Ok(false);
+        };
+
+        record.priority = priority;
+
+        // A leased request only takes its new priority if it goes back to the queue.
+        if record.state == ProofRequestState::Queued {
+            let entry = QueueEntry::new(
+                &record.proof_request,
+                record.requester,
+                record.priority,
+                record.seq,
+                record.queued_at,
+            );
+            proof_requests.scheduler.remove(request_id);
+            proof_requests.scheduler.push(entry);
+        }
+
+        Ok(true)
     }
 
-    async fn pop_request(&self) -> Option<ProofRequest> {
+    async fn lease_request(&self, duration: Duration) -> Result<Option<Lease>, DbError> {
         let mut proof_requests = self.proof_requests.lock().await;
 
-        proof_requests.pop_front()
+        let now = now();
+
+        // Skip the stale entries of the requests no longer queued, until one can be leased.
+        let mut any_expired = false;
+        let lease = loop {
+            let (next, expired) = proof_requests.scheduler.pop(now);
+            any_expired |= !expired.is_empty();
+            proof_requests.mark_expired(&expired);
+
+            let Some(entry) = next else {
+                break None;
+            };
+            let Some(record) = proof_requests
+                .records
+                .get_mut(entry.request_id())
+                .filter(|record| record.state == ProofRequestState::Queued)
+            else {
+                continue;
+            };
+
+            let lease_id = generate_lease_id();
+            let expires_at = now + duration.as_secs();
+
+            record.state = ProofRequestState::Leased;
+            record.lease = Some((lease_id.clone(), expires_at));
+            record.attempts += 1;
+
+            let lease = Lease {
+                proof_request: record.proof_request.clone(),
+                lease_id,
+                expires_at,
+                last_attempt: record.attempts >= MAX_LEASE_ATTEMPTS,
+            };
+            break Some(lease);
+        };
+
+        // Only expired requests can have released a stdin here.
+        if any_expired {
+            self.release_stdins(&mut proof_requests).await;
+        }
+
+        Ok(lease)
+    }
+
+    async fn renew_lease(
+        &self,
+        request_id: &[u8],
+        lease_id: &str,
+        duration: Duration,
+    ) -> Result<Option<u64>, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests
+            .leased_record(request_id, lease_id)
+            .map(|record| {
+                let expires_at = now() + duration.as_secs();
+                record.lease = Some((lease_id.to_string(), expires_at));
+                expires_at
+            }))
+    }
+
+    async fn lease_is_held(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests.leased_record(request_id, lease_id).is_some())
+    }
+
+    async fn complete_request(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        let Some(record) = proof_requests.leased_record(request_id, lease_id) else {
+            return Ok(false);
+        };
+
+        record.finish(ProofRequestState::Completed);
+
+        let stdin_id = record.stdin_id.clone();
+        proof_requests.finished.push(stdin_id);
+        self.release_stdins(&mut proof_requests).await;
+
+        Ok(true)
+    }
+
+    async fn fail_request(
+        &self,
+        request_id: &[u8],
+        lease_id: &str,
+        requeue: bool,
+    ) -> Result<bool, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        if proof_requests.leased_record(request_id, lease_id).is_none() {
+            return Ok(false);
+        }
+
+        proof_requests.release(request_id, requeue);
+        self.release_stdins(&mut proof_requests).await;
+
+        Ok(true)
+    }
+
+    async fn cancel_request(&self, request_id: &[u8]) -> Result<Option<CancelledRequest>, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        let Some(record) = proof_requests
+            .records
+            .get_mut(request_id)
+            .filter(|record| record.state.is_active())
+        else {
+            return Ok(None);
+        };
+
+        let cancelled = CancelledRequest {
+            proof_request: record.proof_request.clone(),
+            lease_id: record.lease.take().map(|(lease_id, _)| lease_id),
+        };
+        record.finish(ProofRequestState::Cancelled);
+        record.cancel_pending = cancelled.lease_id.is_none();
+        let stdin_id = record.stdin_id.clone();
+
+        proof_requests.scheduler.remove(request_id);
+
+        let in_use = proof_requests
+            .records
+            .values()
+            .any(|record| record.stdin_id == stdin_id && record.state.is_active());
+
+        if !in_use && let Some(stored) = self.stdins.lock().await.get_mut(&stdin_id) {
+            stored.expires_at = Some(now());
+        }
+
+        drop(proof_requests);
+        self.purge_stdins().await;
+
+        Ok(Some(cancelled))
+    }
+
+    async fn lease_cancellation(&self, duration: Duration) -> Result<Option<Lease>, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+        let now = now();
+
+        let Some(record) = proof_requests
+            .records
+            .values_mut()
+            .filter(|record| {
+                record.cancel_pending
+                    && record
+                        .lease
+                        .as_ref()
+                        .is_none_or(|(_, expires_at)| *expires_at <= now)
+            })
+            .min_by_key(|record| (record.finished_at, record.seq))
+        else {
+            return Ok(None);
+        };
+
+        let lease_id = generate_lease_id();
+        let expires_at = now + duration.as_secs();
+        record.lease = Some((lease_id.clone(), expires_at));
+
+        Ok(Some(Lease {
+            proof_request: record.proof_request.clone(),
+            lease_id,
+            expires_at,
+            last_attempt: false,
+        }))
+    }
+
+    async fn ack_cancellation(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        let Some(record) = proof_requests.records.get_mut(request_id).filter(|record| {
+            record.cancel_pending && record.lease.as_ref().is_some_and(|(id, _)| id == lease_id)
+        }) else {
+            return Ok(false);
+        };
+
+        record.cancel_pending = false;
+        record.lease = None;
+
+        Ok(true)
+    }
+
+    async fn requeue_expired_leases(&self) -> Result<usize, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+        let now = now();
+
+        let expired = proof_requests
+            .records
+            .iter()
+            .filter(|(_, record)| {
+                record.state == ProofRequestState::Leased
+                    && record
+                        .lease
+                        .as_ref()
+                        .is_some_and(|(_, expires_at)| *expires_at <= now)
+            })
+            .map(|(request_id, _)| request_id.clone())
+            .collect::<Vec<_>>();
+
+        for request_id in &expired {
+            proof_requests.release(request_id, true);
+        }
+        self.release_stdins(&mut proof_requests).await;
+
+        Ok(expired.len())
+    }
+
+    async fn expire_requests(&self) -> Result<usize, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        let expired = proof_requests.scheduler.drain_expired(now());
+        proof_requests.mark_expired(&expired);
+        self.release_stdins(&mut proof_requests).await;
+
+        Ok(expired.len())
+    }
+
+    async fn remove_expired_stdins(&self) -> Result<usize, DbError> {
+        Ok(self.purge_stdins().await)
+    }
+
+    async fn remove_finished_requests(&self, retention: Duration) -> Result<usize, DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+        let cutoff = now().saturating_sub(retention.as_secs());
+
+        let count = proof_requests.records.len();
+        proof_requests.records.retain(|_, record| {
+            record.cancel_pending
+                || record
+                    .finished_at
+                    .is_none_or(|finished_at| finished_at > cutoff)
+        });
+
+        Ok(count - proof_requests.records.len())
+    }
+
+    async fn queued_proof_request_count(&self) -> Result<usize, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests.scheduler.len())
+    }
+
+    async fn set_requester_weights(&self, weights: HashMap<Address, u32>) -> Result<(), DbError> {
+        let mut proof_requests = self.proof_requests.lock().await;
+
+        proof_requests.scheduler.set_weights(weights);
+
+        Ok(())
+    }
+
+    async fn tenant_queues(&self) -> Result<Vec<TenantQueue>, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests.scheduler.tenants(now()))
+    }
+
+    async fn leased_proof_request_count(&self) -> Result<usize, DbError> {
+        let proof_requests = self.proof_requests.lock().await;
+
+        Ok(proof_requests
+            .records
+            .values()
+            .filter(|record| record.state == ProofRequestState::Leased)
+            .count())
+    }
+
+    async fn requester_usage(&self, requester: &Address) -> Result<StoredUsage, DbError> {
+        Ok(self.usage().await?.remove(requester).unwrap_or_default())
+    }
+
+    async fn usage(&self) -> Result<HashMap<Address, StoredUsage>, DbError> {
+        let mut usage = HashMap::<Address, StoredUsage>::new();
+
+        for record in self.proof_requests.lock().await.records.values() {
+            if record.state.is_active() {
+                usage.entry(record.requester).or_default().active_requests += 1;
+            }
+        }
+
+        for stored in self.stdins.lock().await.values() {
+            usage.entry(stored.owner).or_default().stdin_bytes += stored.stdin.len();
+        }
+
+        Ok(usage)
+    }
+
+    async fn resident_stdin_count(&self) -> Result<usize, DbError> {
+        let stdins = self.stdins.lock().await;
+
+        Ok(stdins.len())
+    }
+
+    async fn stdin_stats(&self) -> Result<StdinStoreStats, DbError> {
+        let stdins = self.stdins.lock().await;
+
+        Ok(StdinStoreStats {
+            count: stdins.len(),
+            total_bytes: stdins.values().map(|stored| stored.stdin.len()).sum(),
+            claimed: stdins
+                .values()
+                .filter(|stored| stored.expires_at.is_none())
+                .count(),
+            spilled: stdins
+                .values()
+                .filter(|stored| matches!(stored.stdin.data(), StdinData::Spilled(_)))
+                .count(),
+        })
+    }
+
+    async fn record_admin_action(&self, action: AdminAction) -> Result<(), DbError> {
+        let mut admin_actions = self.admin_actions.lock().await;
+
+        if admin_actions.len() == MAX_ADMIN_ACTIONS {
+            admin_actions.pop_front();
+        }
+        admin_actions.push_back(action);
+
+        Ok(())
+    }
+
+    async fn admin_actions(&self, limit: usize) -> Result<Vec<AdminAction>, DbError> {
+        let admin_actions = self.admin_actions.lock().await;
+
+        Ok(admin_actions.iter().rev().take(limit).cloned().collect())
     }
 }
@@ -1,22 +1,351 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use alloy_primitives::Address;
+use mti::prelude::{MagicTypeIdExt, V7};
 use sp1_sdk::network::proto::base_types::ProofRequest;
-use tonic::async_trait;
+use tonic::{Status, async_trait};
+
+use crate::stdin::Stdin;
 
 mod in_memory;
 pub use in_memory::InMemoryDb;
 
+mod scheduler;
+pub use scheduler::{QueueEntry, Scheduler, TenantQueue};
+
+mod sqlite;
+pub use sqlite::SqliteDb;
+
+#[cfg(test)]
+mod tests;
+
+/// The number of times a request can be leased before being marked as failed.
+pub const MAX_LEASE_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("failed to decode stored proof request: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("database task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
+
+    #[error("unknown proof request state: {0}")]
+    UnknownState(String),
+
+    #[error("corrupted stored data: {0}")]
+    Corrupted(&'static str),
+}
+
+impl From<DbError> for Status {
+    fn from(err: DbError) -> Self {
+        tracing::error!("Database error: {err}");
+        Status::internal("database error")
+    }
+}
+
+/// How long the server keeps the stdins.
+#[derive(Debug, Clone, Copy)]
+pub struct StdinRetention {
+    /// How long an uploaded stdin is kept while no proof request refers to it.
+    pub unclaimed: Duration,
+    /// How long a stdin is kept once every proof request referring to it is completed, failed or
+    /// expired. A zero duration deletes it right away.
+    pub after_finish: Duration,
+}
+
+/// The lifecycle of a proof request inside the enclave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofRequestState {
+    Queued,
+    Leased,
+    Completed,
+    Failed,
+    Expired,
+    Cancelled,
+}
+
+impl ProofRequestState {
+    /// Whether the request is waiting to be proved or being proved.
+    pub fn is_active(&self) -> bool {
+        matches!(self, ProofRequestState::Queued | ProofRequestState::Leased)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProofRequestState::Queued => "queued",
+            ProofRequestState::Leased => "leased",
+            ProofRequestState::Completed => "completed",
+            ProofRequestState::Failed => "failed",
+            ProofRequestState::Expired => "expired",
+            ProofRequestState::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::str::FromStr for ProofRequestState {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(ProofRequestState::Queued),
+            "leased" => Ok(ProofRequestState::Leased),
+            "completed" => Ok(ProofRequestState::Completed),
+            "failed" => Ok(ProofRequestState::Failed),
+            "expired" => Ok(ProofRequestState::Expired),
+            "cancelled" => Ok(ProofRequestState::Cancelled),
+            _ => Err(DbError::UnknownState(s.to_string())),
+        }
+    }
+}
+
+/// What a requester holds on the server, counted against its quotas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoredUsage {
+    /// The number of queued and leased proof requests.
+    pub active_requests: usize,
+    /// The total size of the stored stdins.
+    pub stdin_bytes: u64,
+}
+
+/// A proof request handed out to a worker until `expires_at`.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub proof_request: ProofRequest,
+    pub lease_id: String,
+    pub expires_at: u64,
+    /// Whether the request is failed rather than requeued once this lease is released or
+    /// expires, after [`MAX_LEASE_ATTEMPTS`] attempts.
+    pub last_attempt: bool,
+}
+
+/// The stdins held by the server, as reported to the admins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StdinStoreStats {
+    pub count: usize,
+    pub total_bytes: u64,
+    /// The stdins referred to by an active proof request.
+    pub claimed: usize,
+    /// The stdins stored in a spill file.
+    pub spilled: usize,
+}
+
+/// A proof request and its scheduling state, as reported to the admins.
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    pub proof_request: ProofRequest,
+    pub requester: Address,
+    pub state: ProofRequestState,
+    pub priority: u32,
+    pub queued_at: u64,
+    /// When the lease expires, `None` if the request is not leased.
+    pub lease_expires_at: Option<u64>,
+    pub attempts: u32,
+}
+
+/// An admin action, recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminAction {
+    pub timestamp: u64,
+    pub admin: Address,
+    pub action: String,
+    pub details: String,
+    pub outcome: String,
+}
+
+/// The outcome of [`Db::store_upload`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadOutcome {
+    Stored,
+    /// The upload does not exist, was already used, or has expired.
+    NotPending,
+    /// The stdin would take the stdins of the owner over their quota.
+    OverQuota,
+}
+
+/// A proof request cancelled while queued or leased.
+#[derive(Debug, Clone)]
+pub struct CancelledRequest {
+    pub proof_request: ProofRequest,
+    /// The lease held on the request, `None` if it was queued.
+    pub lease_id: Option<String>,
+}
+
 #[async_trait]
 pub trait Db: Send + Sync + 'static {
-    async fn insert_artifact_request(&self, id: String);
+    /// Record a pending stdin upload created by `owner`, valid until `expires_at`.
+    async fn insert_artifact_request(
+        &self,
+        id: String,
+        owner: Address,
+        expires_at: u64,
+    ) -> Result<(), DbError>;
+
+    /// The owner of a pending upload, which is kept. Returns `None` if it does not exist or has
+    /// expired.
+    async fn artifact_request_owner(&self, id: &str) -> Result<Option<Address>, DbError>;
+
+    /// Remove the pending uploads that have expired, and return how many were removed.
+    async fn remove_expired_artifact_requests(&self) -> Result<usize, DbError>;
+
+    /// Store an uploaded stdin. It is deleted after [`StdinRetention::unclaimed`] unless a proof
+    /// request refers to it.
+    async fn insert_stdin(&self, id: String, owner: Address, stdin: Stdin) -> Result<(), DbError>;
+
+    /// Store the stdin received for the pending upload `id` of `owner`, and remove the upload, in
+    /// a single step. Nothing is changed unless the upload is still pending, and the stdins of
+    /// the owner stay within `max_stdin_bytes`, if set.
+    async fn store_upload(
+        &self,
+        id: String,
+        owner: Address,
+        stdin: Stdin,
+        max_stdin_bytes: Option<u64>,
+    ) -> Result<UploadOutcome, DbError>;
+
+    async fn get_stdin(&self, id: &str) -> Result<Option<Arc<Stdin>>, DbError>;
+
+    /// The requester who uploaded the stdin, or `None` if it is not held by the server.
+    async fn stdin_owner(&self, id: &str) -> Result<Option<Address>, DbError>;
+
+    /// The fulfillers assigned to the leased proof requests referring to a stdin.
+    async fn leased_fulfillers(&self, stdin_id: &str) -> Result<Vec<Address>, DbError>;
+
+    /// Delete and wipe a stdin regardless of its retention, and return whether it existed.
+    async fn remove_stdin(&self, id: &str) -> Result<bool, DbError>;
+
+    /// Queue a proof request signed by `requester`. Requests with a higher `priority` are handed
+    /// out first.
+    ///
+    /// The stdin `stdin_id` is kept until the request is completed, failed or expired, then
+    /// deleted after [`StdinRetention::after_finish`].
+    async fn insert_request(
+        &self,
+        proof_request: ProofRequest,
+        requester: Address,
+        stdin_id: String,
+        priority: u32,
+    ) -> Result<(), DbError>;
+
+    /// The requester who signed a proof request, or `None` if the request is unknown.
+    async fn requester(&self, request_id: &[u8]) -> Result<Option<Address>, DbError>;
+
+    /// The state of a proof request, or `None` if the request is unknown.
+    async fn request_state(&self, request_id: &[u8]) -> Result<Option<ProofRequestState>, DbError>;
+
+    /// A proof request and its scheduling state, or `None` if the request is unknown.
+    async fn request_info(&self, request_id: &[u8]) -> Result<Option<RequestInfo>, DbError>;
+
+    /// The queued and leased proof requests, oldest first.
+    async fn active_requests(&self) -> Result<Vec<RequestInfo>, DbError>;
+
+    /// Change the priority of a queued or leased request. Returns `false` if the request is
+    /// unknown or no longer active.
+    async fn set_priority(&self, request_id: &[u8], priority: u32) -> Result<bool, DbError>;
+
+    /// Lease the most urgent queued request for `duration`. Queued requests past their deadline
+    /// are marked as expired instead of being handed out.
+    async fn lease_request(&self, duration: Duration) -> Result<Option<Lease>, DbError>;
+
+    /// Extend a lease, returning the new expiry, or `None` if the lease is no longer held.
+    async fn renew_lease(
+        &self,
+        request_id: &[u8],
+        lease_id: &str,
+        duration: Duration,
+    ) -> Result<Option<u64>, DbError>;
+
+    /// Whether `lease_id` is still the lease held on the request.
+    async fn lease_is_held(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError>;
 
-    async fn consume_artifact_request(&self, id: String) -> bool;
+    /// Mark a leased request as completed. Returns `false` if the lease is no longer held.
+    async fn complete_request(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError>;
 
-    async fn insert_stdin(&self, id: String, stdin: Vec<u8>);
+    /// Release a leased request, either back to the queue or as failed. Returns `false` if the
+    /// lease is no longer held.
+    async fn fail_request(
+        &self,
+        request_id: &[u8],
+        lease_id: &str,
+        requeue: bool,
+    ) -> Result<bool, DbError>;
 
-    async fn get_stdin(&self, id: &str) -> Option<Arc<Vec<u8>>>;
+    /// Cancel a queued or leased request, dropping it from the queue and revoking its lease. Its
+    /// stdin is deleted right away unless another active request refers to it. Returns `None` if
+    /// the request is unknown or no longer active.
+    async fn cancel_request(&self, request_id: &[u8]) -> Result<Option<CancelledRequest>, DbError>;
 
-    async fn insert_request(&self, proof_request: ProofRequest);
+    /// Lease for `duration` the oldest request cancelled while queued that is still to be
+    /// reported to the network, so it is handed to a single fulfiller worker at a time. It is
+    /// handed out again once the lease expires, until acked with [`Db::ack_cancellation`]. The
+    /// pending cancellations are kept until acked, even past the retention of the finished
+    /// requests.
+    async fn lease_cancellation(&self, duration: Duration) -> Result<Option<Lease>, DbError>;
+
+    /// Mark a leased cancellation as reported to the network. Returns `false` if the lease is no
+    /// longer held.
+    async fn ack_cancellation(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError>;
+
+    /// Put back on the queue every request whose lease has expired, and return how many were
+    /// released.
+    async fn requeue_expired_leases(&self) -> Result<usize, DbError>;
+
+    /// Mark every queued request past its deadline as expired, and return how many were dropped.
+    async fn expire_requests(&self) -> Result<usize, DbError>;
+
+    /// Delete and wipe the stdins past their retention, and return how many were deleted.
+    async fn remove_expired_stdins(&self) -> Result<usize, DbError>;
+
+    /// Delete the completed, failed, expired and cancelled requests finished for longer than
+    /// `retention`, and return how many were deleted. They are unknown to the server afterwards.
+    async fn remove_finished_requests(&self, retention: Duration) -> Result<usize, DbError>;
+
+    async fn queued_proof_request_count(&self) -> Result<usize, DbError>;
+
+    /// Set the weight of the requesters, which share the fulfiller in proportion to it. The
+    /// requesters not listed have a weight of 1.
+    async fn set_requester_weights(&self, weights: HashMap<Address, u32>) -> Result<(), DbError>;
+
+    /// The queue of every requester with queued proof requests.
+    async fn tenant_queues(&self) -> Result<Vec<TenantQueue>, DbError>;
+
+    /// The number of proof requests being proved.
+    async fn leased_proof_request_count(&self) -> Result<usize, DbError>;
+
+    /// The usage of a requester.
+    async fn requester_usage(&self, requester: &Address) -> Result<StoredUsage, DbError>;
+
+    /// The usage of every requester holding proof requests or stdins.
+    async fn usage(&self) -> Result<HashMap<Address, StoredUsage>, DbError>;
+
+    /// The number of stdins currently held by the server.
+    async fn resident_stdin_count(&self) -> Result<usize, DbError>;
+
+    /// Statistics on the stdins currently held by the server.
+    async fn stdin_stats(&self) -> Result<StdinStoreStats, DbError>;
+
+    /// Append an admin action to the audit log.
+    async fn record_admin_action(&self, action: AdminAction) -> Result<(), DbError>;
+
+    /// The `limit` most recent admin actions, the most recent first.
+    async fn admin_actions(&self, limit: usize) -> Result<Vec<AdminAction>, DbError>;
+}
+
+pub fn generate_lease_id() -> String {
+    "lease".create_type_id::<V7>().to_string()
+}
 
-    async fn pop_request(&self) -> Option<ProofRequest>;
+/// The current unix timestamp, in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
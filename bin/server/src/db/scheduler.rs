@@ -0,0 +1,375 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+};
+
+use alloy_primitives::Address;
+use serde::{Deserialize, Serialize};
+use sp1_sdk::network::proto::base_types::ProofRequest;
+
+/// How far the virtual time of a requester of weight 1 advances for each request handed out.
+const STRIDE: u64 = 1 << 32;
+
+/// The scheduling key of a queued proof request.
+///
+/// Within the queue of a requester, requests are ordered by priority class first, then by
+/// earliest deadline, and the cheapest ones go first among equal deadlines. The insertion order
+/// breaks the remaining ties.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QueueEntry {
+    priority: Reverse<u32>,
+    deadline: u64,
+    gas_limit: u64,
+    cycle_limit: u64,
+    seq: u64,
+    request_id: Vec<u8>,
+    requester: Address,
+    queued_at: u64,
+}
+
+impl QueueEntry {
+    pub fn new(
+        proof_request: &ProofRequest,
+        requester: Address,
+        priority: u32,
+        seq: u64,
+        queued_at: u64,
+    ) -> Self {
+        Self::from_parts(
+            proof_request.request_id.clone(),
+            requester,
+            priority,
+            proof_request.deadline,
+            proof_request.gas_limit,
+            proof_request.cycle_limit,
+            seq,
+            queued_at,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_parts(
+        request_id: Vec<u8>,
+        requester: Address,
+        priority: u32,
+        deadline: u64,
+        gas_limit: u64,
+        cycle_limit: u64,
+        seq: u64,
+        queued_at: u64,
+    ) -> Self {
+        Self {
+            priority: Reverse(priority),
+            // A request without deadline never expires, and goes after the ones with a deadline.
+            deadline: if deadline == 0 { u64::MAX } else { deadline },
+            gas_limit,
+            cycle_limit,
+            seq,
+            request_id,
+            requester,
+            queued_at,
+        }
+    }
+
+    pub fn request_id(&self) -> &[u8] {
+        &self.request_id
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.deadline <= now
+    }
+}
+
+/// The queued requests of a requester, reported on the health and admin endpoints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantQueue {
+    pub requester: Address,
+    pub weight: u32,
+    pub depth: usize,
+    /// How long the oldest queued request has been waiting, in seconds.
+    pub wait_secs: u64,
+}
+
+#[derive(Debug)]
+struct Tenant {
+    queue: BTreeSet<QueueEntry>,
+    /// The virtual time at which the requester is served next.
+    pass: u64,
+}
+
+/// Orders the queued proof requests, so a single requester cannot starve the others.
+///
+/// Each requester has its own queue. The requesters whose most urgent request has the highest
+/// priority class are served first, and share the fulfiller in proportion to their weights, by
+/// weighted fair queuing: each time a request is handed out, the virtual time of its requester
+/// advances by the inverse of its weight, and the requester with the earliest virtual time is
+/// served next.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    tenants: HashMap<Address, Tenant>,
+    /// The weight of the requesters, 1 if not configured.
+    weights: HashMap<Address, u32>,
+    /// The virtual time of the last requester served. The requesters with no queued request
+    /// catch up to it, so they do not bank the time they were idle.
+    virtual_time: u64,
+    next_seq: u64,
+}
+
+impl Scheduler {
+    pub fn set_weights(&mut self, weights: HashMap<Address, u32>) {
+        self.weights = weights;
+    }
+
+    fn weight(&self, requester: &Address) -> u32 {
+        self.weights.get(requester).copied().unwrap_or(1).max(1)
+    }
+
+    /// The sequence number to use for the next request entering the queue.
+    pub fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    pub fn push(&mut self, entry: QueueEntry) {
+        self.next_seq = self.next_seq.max(entry.seq + 1);
+
+        let virtual_time = self.virtual_time;
+        let tenant = self.tenants.entry(entry.requester).or_insert(Tenant {
+            queue: BTreeSet::new(),
+            pass: virtual_time,
+        });
+
+        if tenant.queue.is_empty() {
+            tenant.pass = tenant.pass.max(virtual_time);
+        }
+        tenant.queue.insert(entry);
+    }
+
+    /// Pop the most urgent request of the next requester to serve. The expired requests heading
+    /// the queues are dropped and returned alongside.
+    pub fn pop(&mut self, now: u64) -> (Option<QueueEntry>, Vec<QueueEntry>) {
+        let mut expired = vec![];
+
+        for tenant in self.tenants.values_mut() {
+            while tenant
+                .queue
+                .first()
+                .is_some_and(|entry| entry.is_expired(now))
+            {
+                expired.extend(tenant.queue.pop_first());
+            }
+        }
+
+        let next = self
+            .tenants
+            .iter()
+            .filter_map(|(requester, tenant)| {
+                Some((tenant.queue.first()?.priority, tenant.pass, *requester))
+            })
+            .min()
+            .map(|(_, _, requester)| requester);
+
+        let Some(requester) = next else {
+            self.tenants.clear();
+            return (None, expired);
+        };
+
+        let stride = STRIDE / self.weight(&requester) as u64;
+        let tenant = self
+            .tenants
+            .get_mut(&requester)
+            .expect("the requester was just found");
+
+        self.virtual_time = self.virtual_time.max(tenant.pass);
+        tenant.pass += stride;
+        let entry = tenant.queue.pop_first();
+
+        // Forget the idle requesters that would catch up to the virtual time anyway.
+        let virtual_time = self.virtual_time;
+        self.tenants
+            .retain(|_, tenant| !tenant.queue.is_empty() || tenant.pass > virtual_time);
+
+        (entry, expired)
+    }
+
+    /// Drop a request from the queue, and return whether it was queued.
+    pub fn remove(&mut self, request_id: &[u8]) -> bool {
+        for tenant in self.tenants.values_mut() {
+            let entry = tenant
+                .queue
+                .iter()
+                .find(|entry| entry.request_id == request_id)
+                .cloned();
+
+            if let Some(entry) = entry {
+                return tenant.queue.remove(&entry);
+            }
+        }
+
+        false
+    }
+
+    /// Drop the expired requests from the queue and return them.
+    pub fn drain_expired(&mut self, now: u64) -> Vec<QueueEntry> {
+        let mut expired = vec![];
+
+        for tenant in self.tenants.values_mut() {
+            let tenant_expired = tenant
+                .queue
+                .iter()
+                .filter(|entry| entry.is_expired(now))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            for entry in &tenant_expired {
+                tenant.queue.remove(entry);
+            }
+
+            expired.extend(tenant_expired);
+        }
+
+        expired
+    }
+
+    pub fn len(&self) -> usize {
+        self.tenants.values().map(|tenant| tenant.queue.len()).sum()
+    }
+
+    /// The queue of every requester with queued requests, ordered by address.
+    pub fn tenants(&self, now: u64) -> Vec<TenantQueue> {
+        let mut tenants = self
+            .tenants
+            .iter()
+            .filter(|(_, tenant)| !tenant.queue.is_empty())
+            .map(|(requester, tenant)| {
+                let oldest = tenant
+                    .queue
+                    .iter()
+                    .map(|entry| entry.queued_at)
+                    .min()
+                    .unwrap_or(now);
+
+                TenantQueue {
+                    requester: *requester,
+                    weight: self.weight(requester),
+                    depth: tenant.queue.len(),
+                    wait_secs: now.saturating_sub(oldest),
+                }
+            })
+            .collect::<Vec<_>>();
+        tenants.sort_by_key(|tenant| tenant.requester);
+
+        tenants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u8, priority: u32, deadline: u64, gas_limit: u64, seq: u64) -> QueueEntry {
+        tenant_entry(id, Address::ZERO, priority, deadline, gas_limit, seq)
+    }
+
+    fn tenant_entry(
+        id: u8,
+        requester: Address,
+        priority: u32,
+        deadline: u64,
+        gas_limit: u64,
+        seq: u64,
+    ) -> QueueEntry {
+        QueueEntry::from_parts(
+            vec![id],
+            requester,
+            priority,
+            deadline,
+            gas_limit,
+            0,
+            seq,
+            seq,
+        )
+    }
+
+    fn order(scheduler: &mut Scheduler) -> Vec<u8> {
+        std::iter::from_fn(|| scheduler.pop(0).0)
+            .map(|entry| entry.request_id()[0])
+            .collect()
+    }
+
+    #[test]
+    fn test_order() {
+        let mut scheduler = Scheduler::default();
+
+        scheduler.push(entry(1, 0, 200, 10, 0));
+        scheduler.push(entry(2, 0, 100, 10, 1));
+        scheduler.push(entry(3, 0, 100, 5, 2));
+        scheduler.push(entry(4, 1, 300, 10, 3));
+        scheduler.push(entry(5, 0, 0, 10, 4));
+
+        assert_eq!(order(&mut scheduler), vec![4, 3, 2, 1, 5]);
+    }
+
+    #[test]
+    fn test_expired_requests_are_skipped() {
+        let mut scheduler = Scheduler::default();
+
+        scheduler.push(entry(1, 0, 100, 10, 0));
+        scheduler.push(entry(2, 0, 200, 10, 1));
+
+        let (next, expired) = scheduler.pop(150);
+
+        assert_eq!(next.unwrap().request_id(), &[2]);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].request_id(), &[1]);
+        assert_eq!(scheduler.len(), 0);
+    }
+
+    #[test]
+    fn test_weighted_fair_queuing() {
+        let heavy = Address::repeat_byte(1);
+        let light = Address::repeat_byte(2);
+        let late = Address::repeat_byte(3);
+        let mut scheduler = Scheduler::default();
+        scheduler.set_weights(HashMap::from([(heavy, 2)]));
+
+        // The heavy requester queues its requests first, and gets twice the share.
+        for id in 0..6 {
+            scheduler.push(tenant_entry(id, heavy, 0, 0, 10, id as u64));
+        }
+        for id in 10..16 {
+            scheduler.push(tenant_entry(id, light, 0, 0, 10, id as u64));
+        }
+        assert_eq!(
+            scheduler.tenants(20),
+            vec![
+                TenantQueue {
+                    requester: heavy,
+                    weight: 2,
+                    depth: 6,
+                    wait_secs: 20,
+                },
+                TenantQueue {
+                    requester: light,
+                    weight: 1,
+                    depth: 6,
+                    wait_secs: 10,
+                },
+            ]
+        );
+
+        let first = (0..6)
+            .map(|_| scheduler.pop(0).0.unwrap().request_id()[0])
+            .collect::<Vec<_>>();
+        assert_eq!(first, vec![0, 10, 1, 2, 11, 3]);
+
+        // A requester joining later does not wait for the others to catch up.
+        scheduler.push(tenant_entry(20, late, 0, 0, 10, 20));
+        assert_eq!(scheduler.pop(0).0.unwrap().request_id(), &[20]);
+
+        // A higher priority class still goes first.
+        scheduler.push(tenant_entry(21, late, 1, 0, 10, 21));
+        assert_eq!(order(&mut scheduler), vec![21, 4, 12, 5, 13, 14, 15]);
+    }
+}
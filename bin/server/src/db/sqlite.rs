@@ -0,0 +1,1347 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alloy_primitives::Address;
+use axum::body::Bytes;
+use prost::Message;
+use rusqlite::{Connection, OptionalExtension, Row, Transaction, params, types::Type};
+use sp1_sdk::network::proto::base_types::ProofRequest;
+use tonic::async_trait;
+
+use crate::{
+    db::{
+        AdminAction, CancelledRequest, Db, DbError, Lease, MAX_LEASE_ATTEMPTS, ProofRequestState,
+        QueueEntry, RequestInfo, Scheduler, StdinRetention, StdinStoreStats, StoredUsage,
+        TenantQueue, UploadOutcome, generate_lease_id, now,
+    },
+    stdin::{Stdin, StdinData},
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS artifact_requests (
+        id TEXT PRIMARY KEY,
+        owner BLOB NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS stdins (
+        id TEXT PRIMARY KEY,
+        owner BLOB NOT NULL,
+        data BLOB,
+        spill_path TEXT,
+        wrapped_key BLOB NOT NULL,
+        size INTEGER NOT NULL,
+        expires_at INTEGER
+    );
+
+    CREATE TABLE IF NOT EXISTS proof_requests (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        request_id BLOB NOT NULL UNIQUE,
+        proof_request BLOB NOT NULL,
+        requester BLOB NOT NULL,
+        stdin_id TEXT NOT NULL,
+        state TEXT NOT NULL,
+        priority INTEGER NOT NULL,
+        deadline INTEGER NOT NULL,
+        gas_limit INTEGER NOT NULL,
+        cycle_limit INTEGER NOT NULL,
+        lease_id TEXT,
+        lease_expires_at INTEGER,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        queued_at INTEGER NOT NULL,
+        finished_at INTEGER,
+        cancel_pending INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE INDEX IF NOT EXISTS proof_requests_stdin_id ON proof_requests (stdin_id);
+
+    CREATE TABLE IF NOT EXISTS admin_actions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp INTEGER NOT NULL,
+        admin BLOB NOT NULL,
+        action TEXT NOT NULL,
+        details TEXT NOT NULL,
+        outcome TEXT NOT NULL
+    );
+";
+
+/// The columns needed to rebuild the [`QueueEntry`] of a request, see [`queue_entry`].
+const QUEUE_ENTRY_COLUMNS: &str =
+    "request_id, state, priority, deadline, gas_limit, cycle_limit, seq, requester, queued_at";
+
+/// The columns read by [`request_info`].
+const REQUEST_INFO_COLUMNS: &str =
+    "proof_request, requester, state, priority, queued_at, lease_expires_at, attempts";
+
+/// A [`Db`] backed by an SQLite database, so the queued requests, the pending artifact IDs and
+/// the stdins survive a server restart.
+///
+/// The order of the queued requests is kept in memory, and rebuilt from the database on open.
+#[derive(Debug, Clone)]
+pub struct SqliteDb {
+    state: Arc<Mutex<SqliteState>>,
+}
+
+#[derive(Debug)]
+struct SqliteState {
+    conn: Connection,
+    scheduler: Scheduler,
+    retention: StdinRetention,
+}
+
+impl SqliteDb {
+    pub fn open(path: impl AsRef<Path>, retention: StdinRetention) -> Result<Self, DbError> {
+        let conn = Connection::open(path)?;
+
+        // Use WAL with full sync, so a committed write is never lost on crash.
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        // Overwrite the deleted stdins with zeros, instead of leaving them in free pages.
+        conn.pragma_update(None, "secure_delete", "ON")?;
+        conn.execute_batch(SCHEMA)?;
+
+        let mut scheduler = Scheduler::default();
+        let queued = conn
+            .prepare(&format!(
+                "SELECT {QUEUE_ENTRY_COLUMNS} FROM proof_requests WHERE state = ?1"
+            ))?
+            .query_map(params![ProofRequestState::Queued.as_str()], queue_entry)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (_, entry) in queued {
+            scheduler.push(entry);
+        }
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(SqliteState {
+                conn,
+                scheduler,
+                retention,
+            })),
+        })
+    }
+
+    /// Run a closure against the database state on the blocking thread pool.
+    async fn with_state<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut SqliteState) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = self.state.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().unwrap();
+            f(&mut state)
+        })
+        .await?
+    }
+
+    /// Run a closure against the connection on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.with_state(move |state| f(&mut state.conn)).await
+    }
+}
+
+impl SqliteState {
+    /// Start a transaction. The scheduler is returned along with it, to be updated once the
+    /// transaction is committed.
+    fn transaction(&mut self) -> Result<(Tx<'_>, &mut Scheduler), DbError> {
+        let tx = Tx {
+            tx: self.conn.transaction()?,
+            retention: self.retention,
+            purged: 0,
+            spill_paths: Vec::new(),
+        };
+
+        Ok((tx, &mut self.scheduler))
+    }
+}
+
+/// A write spanning several statements, so a crash never leaves it half applied. The spill files
+/// of the stdins it deletes are only removed once it is committed.
+struct Tx<'a> {
+    tx: Transaction<'a>,
+    retention: StdinRetention,
+    /// The number of stdins deleted.
+    purged: usize,
+    spill_paths: Vec<String>,
+}
+
+impl Deref for Tx<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.tx
+    }
+}
+
+impl Tx<'_> {
+    /// Commit the transaction and remove the spill files of the deleted stdins. Returns the
+    /// number of stdins deleted.
+    fn commit(self) -> Result<usize, DbError> {
+        self.tx.commit()?;
+
+        for path in &self.spill_paths {
+            if let Err(err) = std::fs::remove_file(path) {
+                tracing::warn!("Failed to remove spill file {path}: {err}");
+            }
+        }
+
+        Ok(self.purged)
+    }
+
+    fn mark_expired(&self, expired: &[QueueEntry]) -> Result<(), DbError> {
+        for entry in expired {
+            self.execute(
+                "UPDATE proof_requests SET state = ?1, finished_at = ?2
+                 WHERE request_id = ?3 AND state = ?4",
+                params![
+                    ProofRequestState::Expired.as_str(),
+                    now(),
+                    entry.request_id(),
+                    ProofRequestState::Queued.as_str()
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Start the retention of the stdins no longer referred to by an active proof request, and
+    /// delete them right away if they are not retained.
+    fn release_stdins(&mut self) -> Result<(), DbError> {
+        self.execute(
+            "UPDATE stdins SET expires_at = ?1
+             WHERE expires_at IS NULL AND NOT EXISTS (
+                 SELECT 1 FROM proof_requests
+                 WHERE stdin_id = stdins.id AND state IN (?2, ?3)
+             )",
+            params![
+                now() + self.retention.after_finish.as_secs(),
+                ProofRequestState::Queued.as_str(),
+                ProofRequestState::Leased.as_str()
+            ],
+        )?;
+
+        if self.retention.after_finish.is_zero() {
+            self.purge_stdins()?;
+        }
+
+        Ok(())
+    }
+
+    /// Delete the stdins past their retention. Their spill files are removed on commit.
+    fn purge_stdins(&mut self) -> Result<(), DbError> {
+        let spill_paths = self
+            .prepare("DELETE FROM stdins WHERE expires_at <= ?1 RETURNING spill_path")?
+            .query_map(params![now()], |row| row.get::<_, Option<String>>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.purged += spill_paths.len();
+        self.spill_paths.extend(spill_paths.into_iter().flatten());
+
+        Ok(())
+    }
+}
+
+/// Put back on the queue the released requests that went back to the queued state.
+fn requeue_released(scheduler: &mut Scheduler, released: Vec<(String, QueueEntry)>) -> usize {
+    let count = released.len();
+
+    for (state, entry) in released {
+        if state == ProofRequestState::Queued.as_str() {
+            scheduler.push(entry);
+        }
+    }
+
+    count
+}
+
+/// Store a stdin, deleted after [`StdinRetention::unclaimed`] unless a proof request refers to
+/// it.
+fn store_stdin(
+    conn: &Connection,
+    retention: StdinRetention,
+    id: &str,
+    owner: Address,
+    stdin: &Stdin,
+) -> Result<(), DbError> {
+    let (data, spill_path) = match stdin.data() {
+        StdinData::Memory(data) => (Some(&data[..]), None),
+        StdinData::Spilled(path) => (None, Some(path.to_string_lossy().into_owned())),
+    };
+
+    conn.execute(
+        "INSERT OR REPLACE INTO stdins
+         (id, owner, data, spill_path, wrapped_key, size, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            id,
+            owner.as_slice(),
+            data,
+            spill_path,
+            stdin.wrapped_key(),
+            stdin.len(),
+            now() + retention.unclaimed.as_secs()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The usage of every requester, or only of `requester` if set.
+fn query_usage(
+    conn: &Connection,
+    requester: Option<&[u8]>,
+) -> Result<HashMap<Address, StoredUsage>, DbError> {
+    let mut usage = HashMap::<Address, StoredUsage>::new();
+
+    let active_requests = conn
+        .prepare(
+            "SELECT requester, COUNT(*) FROM proof_requests
+             WHERE state IN (?1, ?2) AND (?3 IS NULL OR requester = ?3)
+             GROUP BY requester",
+        )?
+        .query_map(
+            params![
+                ProofRequestState::Queued.as_str(),
+                ProofRequestState::Leased.as_str(),
+                requester
+            ],
+            |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?)),
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (requester, count) in active_requests {
+        usage
+            .entry(address(requester)?)
+            .or_default()
+            .active_requests = count as usize;
+    }
+
+    let stdin_bytes = conn
+        .prepare(
+            "SELECT owner, SUM(size) FROM stdins WHERE ?1 IS NULL OR owner = ?1 GROUP BY owner",
+        )?
+        .query_map(params![requester], |row| {
+            Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (owner, size) in stdin_bytes {
+        usage.entry(address(owner)?).or_default().stdin_bytes = size as u64;
+    }
+
+    Ok(usage)
+}
+
+fn address(bytes: Vec<u8>) -> Result<Address, DbError> {
+    Address::try_from(bytes.as_slice()).map_err(|_| DbError::Corrupted("invalid address"))
+}
+
+/// Read a row selected with [`QUEUE_ENTRY_COLUMNS`].
+fn queue_entry(row: &Row) -> rusqlite::Result<(String, QueueEntry)> {
+    let requester = Address::try_from(row.get::<_, Vec<u8>>(7)?.as_slice())
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(7, Type::Blob, Box::new(err)))?;
+
+    Ok((
+        row.get(1)?,
+        QueueEntry::from_parts(
+            row.get(0)?,
+            requester,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(8)?,
+        ),
+    ))
+}
+
+/// Read a row selected with [`REQUEST_INFO_COLUMNS`].
+fn request_info(row: &Row) -> Result<RequestInfo, DbError> {
+    Ok(RequestInfo {
+        proof_request: ProofRequest::decode(row.get::<_, Vec<u8>>(0)?.as_slice())?,
+        requester: address(row.get(1)?)?,
+        state: row.get::<_, String>(2)?.parse()?,
+        priority: row.get(3)?,
+        queued_at: row.get(4)?,
+        lease_expires_at: row.get(5)?,
+        attempts: row.get(6)?,
+    })
+}
+
+#[async_trait]
+impl Db for SqliteDb {
+    async fn insert_artifact_request(
+        &self,
+        id: String,
+        owner: Address,
+        expires_at: u64,
+    ) -> Result<(), DbError> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO artifact_requests (id, owner, expires_at)
+                 VALUES (?1, ?2, ?3)",
+                params![id, owner.as_slice(), expires_at],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn artifact_request_owner(&self, id: &str) -> Result<Option<Address>, DbError> {
+        let id = id.to_string();
+
+        self.with_conn(move |conn| {
+            let owner = conn
+                .query_row(
+                    "SELECT owner FROM artifact_requests WHERE id = ?1 AND expires_at > ?2",
+                    params![id, now()],
+                    |row| row.get::<_, Vec<u8>>(0),
+                )
+                .optional()?;
+
+            owner.map(address).transpose()
+        })
+        .await
+    }
+
+    async fn remove_expired_artifact_requests(&self) -> Result<usize, DbError> {
+        self.with_conn(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM artifact_requests WHERE expires_at <= ?1",
+                params![now()],
+            )?;
+            Ok(deleted)
+        })
+        .await
+    }
+
+    async fn insert_stdin(&self, id: String, owner: Address, stdin: Stdin) -> Result<(), DbError> {
+        self.with_state(move |state| store_stdin(&state.conn, state.retention, &id, owner, &stdin))
+            .await
+    }
+
+    async fn store_upload(
+        &self,
+        id: String,
+        owner: Address,
+        stdin: Stdin,
+        max_stdin_bytes: Option<u64>,
+    ) -> Result<UploadOutcome, DbError> {
+        self.with_state(move |state| {
+            let (tx, _) = state.transaction()?;
+
+            let pending = tx
+                .query_row(
+                    "SELECT 1 FROM artifact_requests
+                     WHERE id = ?1 AND owner = ?2 AND expires_at > ?3",
+                    params![id, owner.as_slice(), now()],
+                    |_| Ok(()),
+                )
+                .optional()?;
+
+            if pending.is_none() {
+                return Ok(UploadOutcome::NotPending);
+            }
+
+            if let Some(max_stdin_bytes) = max_stdin_bytes {
+                let stdin_bytes = tx.query_row(
+                    "SELECT COALESCE(SUM(size), 0) FROM stdins WHERE owner = ?1",
+                    params![owner.as_slice()],
+                    |row| row.get::<_, u64>(0),
+                )?;
+
+                if stdin_bytes + stdin.len() > max_stdin_bytes {
+                    return Ok(UploadOutcome::OverQuota);
+                }
+            }
+
+            tx.execute("DELETE FROM artifact_requests WHERE id = ?1", params![id])?;
+            store_stdin(&tx, tx.retention, &id, owner, &stdin)?;
+            tx.commit()?;
+
+            Ok(UploadOutcome::Stored)
+        })
+        .await
+    }
+
+    async fn get_stdin(&self, id: &str) -> Result<Option<Arc<Stdin>>, DbError> {
+        let id = id.to_string();
+
+        self.with_conn(move |conn| {
+            let row = conn
+                .query_row(
+                    "SELECT data, spill_path, wrapped_key, size FROM stdins WHERE id = ?1",
+                    params![id],
+                    |row| {
+                        Ok((
+                            row.get::<_, Option<Vec<u8>>>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, Vec<u8>>(2)?,
+                            row.get::<_, u64>(3)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            let Some((data, spill_path, wrapped_key, size)) = row else {
+                return Ok(None);
+            };
+
+            let data = match (data, spill_path) {
+                (Some(data), _) => StdinData::Memory(Bytes::from(data)),
+                (None, Some(path)) => StdinData::Spilled(PathBuf::from(path)),
+                (None, None) => return Err(DbError::Corrupted("stdin without data")),
+            };
+
+            Ok(Some(Arc::new(Stdin::from_parts(data, wrapped_key, size))))
+        })
+        .await
+    }
+
+    async fn stdin_owner(&self, id: &str) -> Result<Option<Address>, DbError> {
+        let id = id.to_string();
+
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT owner FROM stdins WHERE id = ?1",
+                params![id],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .map(address)
+            .transpose()
+        })
+        .await
+    }
+
+    async fn leased_fulfillers(&self, stdin_id: &str) -> Result<Vec<Address>, DbError> {
+        let stdin_id = stdin_id.to_string();
+
+        self.with_conn(move |conn| {
+            let encoded = conn
+                .prepare(
+                    "SELECT proof_request FROM proof_requests WHERE stdin_id = ?1 AND state = ?2",
+                )?
+                .query_map(
+                    params![stdin_id, ProofRequestState::Leased.as_str()],
+                    |row| row.get::<_, Vec<u8>>(0),
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut fulfillers = Vec::new();
+            for encoded in encoded {
+                let proof_request = ProofRequest::decode(encoded.as_slice())?;
+                if let Some(fulfiller) = proof_request.fulfiller.as_deref()
+                    && let Ok(fulfiller) = Address::try_from(fulfiller)
+                {
+                    fulfillers.push(fulfiller);
+                }
+            }
+
+            Ok(fulfillers)
+        })
+        .await
+    }
+
+    async fn remove_stdin(&self, id: &str) -> Result<bool, DbError> {
+        let id = id.to_string();
+
+        self.with_conn(move |conn| {
+            let spill_path = conn
+                .query_row(
+                    "DELETE FROM stdins WHERE id = ?1 RETURNING spill_path",
+                    params![id],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()?;
+
+            if let Some(Some(path)) = &spill_path
+                && let Err(err) = std::fs::remove_file(path)
+            {
+                tracing::warn!("Failed to remove spill file {path}: {err}");
+            }
+
+            Ok(spill_path.is_some())
+        })
+        .await
+    }
+
+    async fn insert_request(
+        &self,
+        proof_request: ProofRequest,
+        requester: Address,
+        stdin_id: String,
+        priority: u32,
+    ) -> Result<(), DbError> {
+        let queued_at = now();
+
+        self.with_state(move |state| {
+            let (tx, scheduler) = state.transaction()?;
+
+            tx.execute(
+                "INSERT OR REPLACE INTO proof_requests
+                 (request_id, proof_request, requester, stdin_id, state, priority, deadline,
+                  gas_limit, cycle_limit, queued_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    proof_request.request_id,
+                    proof_request.encode_to_vec(),
+                    requester.as_slice(),
+                    stdin_id,
+                    ProofRequestState::Queued.as_str(),
+                    priority,
+                    proof_request.deadline,
+                    proof_request.gas_limit,
+                    proof_request.cycle_limit,
+                    queued_at
+                ],
+            )?;
+
+            let seq = tx.last_insert_rowid() as u64;
+
+            // Keep the stdin until the request is done with it.
+            tx.execute(
+                "UPDATE stdins SET expires_at = NULL WHERE id = ?1",
+                params![stdin_id],
+            )?;
+            tx.commit()?;
+
+            // A request inserted again replaces the previous one, along with its queue entry.
+            scheduler.remove(&proof_request.request_id);
+            scheduler.push(QueueEntry::new(
+                &proof_request,
+                requester,
+                priority,
+                seq,
+                queued_at,
+            ));
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn requester(&self, request_id: &[u8]) -> Result<Option<Address>, DbError> {
+        let request_id = request_id.to_vec();
+
+        self.with_conn(move |conn| {
+            let requester = conn
+                .query_row(
+                    "SELECT requester FROM proof_requests WHERE request_id = ?1",
+                    params![request_id],
+                    |row| row.get::<_, Vec<u8>>(0),
+                )
+                .optional()?;
+
+            requester.map(address).transpose()
+        })
+        .await
+    }
+
+    async fn request_state(&self, request_id: &[u8]) -> Result<Option<ProofRequestState>, DbError> {
+        let request_id = request_id.to_vec();
+
+        self.with_conn(move |conn| {
+            let state = conn
+                .query_row(
+                    "SELECT state FROM proof_requests WHERE request_id = ?1",
+                    params![request_id],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?;
+
+            state.map(|state| state.parse()).transpose()
+        })
+        .await
+    }
+
+    async fn request_info(&self, request_id: &[u8]) -> Result<Option<RequestInfo>, DbError> {
+        let request_id = request_id.to_vec();
+
+        self.with_conn(move |conn| {
+            conn.prepare(&format!(
+                "SELECT {REQUEST_INFO_COLUMNS} FROM proof_requests WHERE request_id = ?1"
+            ))?
+            .query(params![request_id])?
+            .next()?
+            .map(request_info)
+            .transpose()
+        })
+        .await
+    }
+
+    async fn active_requests(&self) -> Result<Vec<RequestInfo>, DbError> {
+        self.with_conn(|conn| {
+            let mut statement = conn.prepare(&format!(
+                "SELECT {REQUEST_INFO_COLUMNS} FROM proof_requests
+                 WHERE state IN (?1, ?2) ORDER BY seq"
+            ))?;
+            let mut rows = statement.query(params![
+                ProofRequestState::Queued.as_str(),
+                ProofRequestState::Leased.as_str()
+            ])?;
+
+            let mut requests = vec![];
+            while let Some(row) = rows.next()? {
+                requests.push(request_info(row)?);
+            }
+
+            Ok(requests)
+        })
+        .await
+    }
+
+    async fn set_priority(&self, request_id: &[u8], priority: u32) -> Result<bool, DbError> {
+        let request_id = request_id.to_vec();
+
+        self.with_state(move |state| {
+            let updated = state
+                .conn
+                .prepare(&format!(
+                    "UPDATE proof_requests SET priority = ?1
+                     WHERE request_id = ?2 AND state IN (?3, ?4)
+                     RETURNING {QUEUE_ENTRY_COLUMNS}"
+                ))?
+                .query_map(
+                    params![
+                        priority,
+                        request_id,
+                        ProofRequestState::Queued.as_str(),
+                        ProofRequestState::Leased.as_str()
+                    ],
+                    queue_entry,
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let Some((request_state, entry)) = updated.into_iter().next() else {
+                return Ok(false);
+            };
+
+            // A leased request only takes its new priority if it goes back to the queue.
+            if request_state == ProofRequestState::Queued.as_str() {
+                state.scheduler.remove(&request_id);
+                state.scheduler.push(entry);
+            }
+
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn lease_request(&self, duration: Duration) -> Result<Option<Lease>, DbError> {
+        self.with_state(move |state| {
+            let now = now();
+            let lease_id = generate_lease_id();
+            let expires_at = now + duration.as_secs();
+
+            let (mut tx, scheduler) = state.transaction()?;
+
+            // Skip the stale entries of the requests no longer queued, until one can be leased.
+            let mut any_expired = false;
+            let leased = loop {
+                let (next, expired) = scheduler.pop(now);
+                any_expired |= !expired.is_empty();
+                tx.mark_expired(&expired)?;
+
+                let Some(entry) = next else {
+                    break None;
+                };
+
+                let leased = tx
+                    .query_row(
+                        "UPDATE proof_requests
+                         SET state = ?1, lease_id = ?2, lease_expires_at = ?3,
+                             attempts = attempts + 1
+                         WHERE request_id = ?4 AND state = ?5
+                         RETURNING proof_request, attempts",
+                        params![
+                            ProofRequestState::Leased.as_str(),
+                            lease_id,
+                            expires_at,
+                            entry.request_id(),
+                            ProofRequestState::Queued.as_str()
+                        ],
+                        |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, u32>(1)?)),
+                    )
+                    .optional()?;
+
+                if leased.is_some() {
+                    break leased;
+                }
+            };
+
+            if any_expired {
+                tx.release_stdins()?;
+            }
+            tx.commit()?;
+
+            let Some((encoded, attempts)) = leased else {
+                return Ok(None);
+            };
+
+            Ok(Some(Lease {
+                proof_request: ProofRequest::decode(encoded.as_slice())?,
+                lease_id,
+                expires_at,
+                last_attempt: attempts >= MAX_LEASE_ATTEMPTS,
+            }))
+        })
+        .await
+    }
+
+    async fn renew_lease(
+        &self,
+        request_id: &[u8],
+        lease_id: &str,
+        duration: Duration,
+    ) -> Result<Option<u64>, DbError> {
+        let request_id = request_id.to_vec();
+        let lease_id = lease_id.to_string();
+
+        self.with_conn(move |conn| {
+            let expires_at = now() + duration.as_secs();
+            let updated = conn.execute(
+                "UPDATE proof_requests SET lease_expires_at = ?1
+                 WHERE request_id = ?2 AND lease_id = ?3 AND state = ?4",
+                params![
+                    expires_at,
+                    request_id,
+                    lease_id,
+                    ProofRequestState::Leased.as_str()
+                ],
+            )?;
+            Ok((updated > 0).then_some(expires_at))
+        })
+        .await
+    }
+
+    async fn lease_is_held(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError> {
+        let request_id = request_id.to_vec();
+        let lease_id = lease_id.to_string();
+
+        self.with_conn(move |conn| {
+            let held = conn
+                .query_row(
+                    "SELECT 1 FROM proof_requests
+                     WHERE request_id = ?1 AND lease_id = ?2 AND state = ?3",
+                    params![request_id, lease_id, ProofRequestState::Leased.as_str()],
+                    |_| Ok(()),
+                )
+                .optional()?;
+            Ok(held.is_some())
+        })
+        .await
+    }
+
+    async fn complete_request(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError> {
+        let request_id = request_id.to_vec();
+        let lease_id = lease_id.to_string();
+
+        self.with_state(move |state| {
+            let (mut tx, _) = state.transaction()?;
+
+            let updated = tx.execute(
+                "UPDATE proof_requests
+                 SET state = ?1, lease_id = NULL, lease_expires_at = NULL, finished_at = ?2
+                 WHERE request_id = ?3 AND lease_id = ?4 AND state = ?5",
+                params![
+                    ProofRequestState::Completed.as_str(),
+                    now(),
+                    request_id,
+                    lease_id,
+                    ProofRequestState::Leased.as_str()
+                ],
+            )?;
+
+            if updated > 0 {
+                tx.release_stdins()?;
+            }
+            tx.commit()?;
+
+            Ok(updated > 0)
+        })
+        .await
+    }
+
+    async fn fail_request(
+        &self,
+        request_id: &[u8],
+        lease_id: &str,
+        requeue: bool,
+    ) -> Result<bool, DbError> {
+        let request_id = request_id.to_vec();
+        let lease_id = lease_id.to_string();
+
+        self.with_state(move |state| {
+            let (mut tx, scheduler) = state.transaction()?;
+
+            let released = tx
+                .prepare(&format!(
+                    "UPDATE proof_requests
+                     SET state = CASE WHEN ?1 AND attempts < ?2 THEN ?3 ELSE ?4 END,
+                         finished_at = CASE WHEN ?1 AND attempts < ?2 THEN NULL ELSE ?8 END,
+                         lease_id = NULL, lease_expires_at = NULL
+                     WHERE request_id = ?5 AND lease_id = ?6 AND state = ?7
+                     RETURNING {QUEUE_ENTRY_COLUMNS}"
+                ))?
+                .query_map(
+                    params![
+                        requeue,
+                        MAX_LEASE_ATTEMPTS,
+                        ProofRequestState::Queued.as_str(),
+                        ProofRequestState::Failed.as_str(),
+                        request_id,
+                        lease_id,
+                        ProofRequestState::Leased.as_str(),
+                        now()
+                    ],
+                    queue_entry,
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if released.is_empty() {
+                return Ok(false);
+            }
+
+            tx.release_stdins()?;
+            tx.commit()?;
+            requeue_released(scheduler, released);
+
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn cancel_request(&self, request_id: &[u8]) -> Result<Option<CancelledRequest>, DbError> {
+        let request_id = request_id.to_vec();
+
+        self.with_state(move |state| {
+            let (mut tx, scheduler) = state.transaction()?;
+
+            let cancelled = tx
+                .query_row(
+                    "SELECT proof_request, lease_id, stdin_id FROM proof_requests
+                     WHERE request_id = ?1 AND state IN (?2, ?3)",
+                    params![
+                        request_id,
+                        ProofRequestState::Queued.as_str(),
+                        ProofRequestState::Leased.as_str()
+                    ],
+                    |row| {
+                        Ok((
+                            row.get::<_, Vec<u8>>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            let Some((encoded, lease_id, stdin_id)) = cancelled else {
+                return Ok(None);
+            };
+
+            // Only a queued request is left for the fulfiller to report, a leased one is reported
+            // by the worker proving it.
+            tx.execute(
+                "UPDATE proof_requests
+                 SET state = ?1, lease_id = NULL, lease_expires_at = NULL, finished_at = ?2,
+                     cancel_pending = ?3
+                 WHERE request_id = ?4",
+                params![
+                    ProofRequestState::Cancelled.as_str(),
+                    now(),
+                    lease_id.is_none(),
+                    request_id
+                ],
+            )?;
+
+            tx.execute(
+                "UPDATE stdins SET expires_at = ?1
+                 WHERE id = ?2 AND NOT EXISTS (
+                     SELECT 1 FROM proof_requests
+                     WHERE stdin_id = stdins.id AND state IN (?3, ?4)
+                 )",
+                params![
+                    now(),
+                    stdin_id,
+                    ProofRequestState::Queued.as_str(),
+                    ProofRequestState::Leased.as_str()
+                ],
+            )?;
+            tx.purge_stdins()?;
+            tx.commit()?;
+            scheduler.remove(&request_id);
+
+            Ok(Some(CancelledRequest {
+                proof_request: ProofRequest::decode(encoded.as_slice())?,
+                lease_id,
+            }))
+        })
+        .await
+    }
+
+    async fn lease_cancellation(&self, duration: Duration) -> Result<Option<Lease>, DbError> {
+        self.with_conn(move |conn| {
+            let now = now();
+            let lease_id = generate_lease_id();
+            let expires_at = now + duration.as_secs();
+
+            let encoded = conn
+                .query_row(
+                    "UPDATE proof_requests SET lease_id = ?1, lease_expires_at = ?2
+                     WHERE seq = (
+                         SELECT seq FROM proof_requests
+                         WHERE cancel_pending
+                             AND (lease_expires_at IS NULL OR lease_expires_at <= ?3)
+                         ORDER BY finished_at, seq LIMIT 1
+                     )
+                     RETURNING proof_request",
+                    params![lease_id, expires_at, now],
+                    |row| row.get::<_, Vec<u8>>(0),
+                )
+                .optional()?;
+
+            let Some(encoded) = encoded else {
+                return Ok(None);
+            };
+
+            Ok(Some(Lease {
+                proof_request: ProofRequest::decode(encoded.as_slice())?,
+                lease_id,
+                expires_at,
+                last_attempt: false,
+            }))
+        })
+        .await
+    }
+
+    async fn ack_cancellation(&self, request_id: &[u8], lease_id: &str) -> Result<bool, DbError> {
+        let request_id = request_id.to_vec();
+        let lease_id = lease_id.to_string();
+
+        self.with_conn(move |conn| {
+            let acked = conn.execute(
+                "UPDATE proof_requests
+                 SET cancel_pending = 0, lease_id = NULL, lease_expires_at = NULL
+                 WHERE request_id = ?1 AND lease_id = ?2 AND cancel_pending",
+                params![request_id, lease_id],
+            )?;
+
+            Ok(acked > 0)
+        })
+        .await
+    }
+
+    async fn requeue_expired_leases(&self) -> Result<usize, DbError> {
+        self.with_state(|state| {
+            let (mut tx, scheduler) = state.transaction()?;
+
+            let released = tx
+                .prepare(&format!(
+                    "UPDATE proof_requests
+                     SET state = CASE WHEN attempts < ?1 THEN ?2 ELSE ?3 END,
+                         finished_at = CASE WHEN attempts < ?1 THEN NULL ELSE ?5 END,
+                         lease_id = NULL, lease_expires_at = NULL
+                     WHERE state = ?4 AND lease_expires_at <= ?5
+                     RETURNING {QUEUE_ENTRY_COLUMNS}"
+                ))?
+                .query_map(
+                    params![
+                        MAX_LEASE_ATTEMPTS,
+                        ProofRequestState::Queued.as_str(),
+                        ProofRequestState::Failed.as_str(),
+                        ProofRequestState::Leased.as_str(),
+                        now()
+                    ],
+                    queue_entry,
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if !released.is_empty() {
+                tx.release_stdins()?;
+            }
+            tx.commit()?;
+
+            Ok(requeue_released(scheduler, released))
+        })
+        .await
+    }
+
+    async fn expire_requests(&self) -> Result<usize, DbError> {
+        self.with_state(|state| {
+            let (mut tx, scheduler) = state.transaction()?;
+
+            let expired = scheduler.drain_expired(now());
+            tx.mark_expired(&expired)?;
+            if !expired.is_empty() {
+                tx.release_stdins()?;
+            }
+            tx.commit()?;
+
+            Ok(expired.len())
+        })
+        .await
+    }
+
+    async fn remove_expired_stdins(&self) -> Result<usize, DbError> {
+        self.with_state(|state| {
+            let (mut tx, _) = state.transaction()?;
+            tx.purge_stdins()?;
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn remove_finished_requests(&self, retention: Duration) -> Result<usize, DbError> {
+        self.with_conn(move |conn| {
+            let removed = conn.execute(
+                "DELETE FROM proof_requests
+                 WHERE state NOT IN (?1, ?2) AND NOT cancel_pending AND finished_at <= ?3",
+                params![
+                    ProofRequestState::Queued.as_str(),
+                    ProofRequestState::Leased.as_str(),
+                    now().saturating_sub(retention.as_secs())
+                ],
+            )?;
+
+            Ok(removed)
+        })
+        .await
+    }
+
+    async fn queued_proof_request_count(&self) -> Result<usize, DbError> {
+        self.with_conn(|conn| {
+            let count = conn.query_row(
+                "SELECT COUNT(*) FROM proof_requests WHERE state = ?1",
+                params![ProofRequestState::Queued.as_str()],
+                |row| row.get::<_, i64>(0),
+            )?;
+            Ok(count as usize)
+        })
+        .await
+    }
+
+    async fn set_requester_weights(&self, weights: HashMap<Address, u32>) -> Result<(), DbError> {
+        self.with_state(move |state| {
+            state.scheduler.set_weights(weights);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn tenant_queues(&self) -> Result<Vec<TenantQueue>, DbError> {
+        self.with_state(|state| Ok(state.scheduler.tenants(now())))
+            .await
+    }
+
+    async fn leased_proof_request_count(&self) -> Result<usize, DbError> {
+        self.with_conn(|conn| {
+            let count = conn.query_row(
+                "SELECT COUNT(*) FROM proof_requests WHERE state = ?1",
+                params![ProofRequestState::Leased.as_str()],
+                |row| row.get::<_, i64>(0),
+            )?;
+            Ok(count as usize)
+        })
+        .await
+    }
+
+    async fn requester_usage(&self, requester: &Address) -> Result<StoredUsage, DbError> {
+        let requester = *requester;
+
+        self.with_conn(move |conn| {
+            Ok(query_usage(conn, Some(requester.as_slice()))?
+                .remove(&requester)
+                .unwrap_or_default())
+        })
+        .await
+    }
+
+    async fn usage(&self) -> Result<HashMap<Address, StoredUsage>, DbError> {
+        self.with_conn(|conn| query_usage(conn, None)).await
+    }
+
+    async fn resident_stdin_count(&self) -> Result<usize, DbError> {
+        self.with_conn(|conn| {
+            let count = conn.query_row("SELECT COUNT(*) FROM stdins", [], |row| {
+                row.get::<_, i64>(0)
+            })?;
+            Ok(count as usize)
+        })
+        .await
+    }
+
+    async fn stdin_stats(&self) -> Result<StdinStoreStats, DbError> {
+        self.with_conn(|conn| {
+            let (count, total_bytes, claimed, spilled) = conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0), COALESCE(SUM(expires_at IS NULL), 0),
+                     COALESCE(SUM(spill_path IS NOT NULL), 0)
+                 FROM stdins",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )?;
+
+            Ok(StdinStoreStats {
+                count: count as usize,
+                total_bytes: total_bytes as u64,
+                claimed: claimed as usize,
+                spilled: spilled as usize,
+            })
+        })
+        .await
+    }
+
+    async fn record_admin_action(&self, action: AdminAction) -> Result<(), DbError> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO admin_actions (timestamp, admin, action, details, outcome)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    action.timestamp,
+                    action.admin.as_slice(),
+                    action.action,
+                    action.details,
+                    action.outcome
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn admin_actions(&self, limit: usize) -> Result<Vec<AdminAction>, DbError> {
+        self.with_conn(move |conn| {
+            let rows = conn
+                .prepare(
+                    "SELECT timestamp, admin, action, details, outcome FROM admin_actions
+                     ORDER BY id DESC LIMIT ?1",
+                )?
+                .query_map(params![limit as i64], |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, Vec<u8>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            rows.into_iter()
+                .map(|(timestamp, admin, action, details, outcome)| {
+                    Ok(AdminAction {
+                        timestamp,
+                        admin: address(admin)?,
+                        action,
+                        details,
+                        outcome,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::{REQUESTER, RETENTION, proof_request, stdin};
+
+    #[tokio::test]
+    async fn test_state_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!("sp1-tee-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("server.db");
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let db = SqliteDb::open(&path, RETENTION).unwrap();
+            db.insert_artifact_request(
+                "artifact_1".to_string(),
+                Address::repeat_byte(1),
+                now() + 60,
+            )
+            .await
+            .unwrap();
+            db.insert_stdin("artifact_2".to_string(), Address::repeat_byte(2), stdin())
+                .await
+                .unwrap();
+            db.insert_request(proof_request(1), REQUESTER, "artifact_3".to_string(), 0)
+                .await
+                .unwrap();
+            db.insert_request(
+                ProofRequest {
+                    deadline: now() + 60,
+                    ..proof_request(2)
+                },
+                REQUESTER,
+                "artifact_3".to_string(),
+                0,
+            )
+            .await
+            .unwrap();
+            db.insert_request(proof_request(4), REQUESTER, "artifact_3".to_string(), 0)
+                .await
+                .unwrap();
+            db.cancel_request(&[4; 32]).await.unwrap().unwrap();
+        }
+
+        let db = SqliteDb::open(&path, RETENTION).unwrap();
+
+        assert_eq!(
+            db.artifact_request_owner("artifact_1").await.unwrap(),
+            Some(Address::repeat_byte(1))
+        );
+        let stored = db.get_stdin("artifact_2").await.unwrap().unwrap();
+        assert!(matches!(stored.data(), StdinData::Memory(data) if data == &[1, 2, 3][..]));
+        assert_eq!(stored.wrapped_key(), &[4; 60]);
+        assert_eq!(
+            db.stdin_owner("artifact_2").await.unwrap(),
+            Some(Address::repeat_byte(2))
+        );
+        assert_eq!(db.queued_proof_request_count().await.unwrap(), 2);
+        let tenants = db.tenant_queues().await.unwrap();
+        assert_eq!(tenants.len(), 1);
+        assert_eq!((tenants[0].requester, tenants[0].depth), (REQUESTER, 2));
+        assert_eq!(db.requester(&[1; 32]).await.unwrap(), Some(REQUESTER));
+        assert_eq!(db.requester(&[3; 32]).await.unwrap(), None);
+
+        let lease = db
+            .lease_request(Duration::from_secs(60))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lease.proof_request.request_id, vec![2; 32]);
+        assert_eq!(db.queued_proof_request_count().await.unwrap(), 1);
+
+        // The cancellation is still to be reported to the network.
+        let cancelled = db
+            .lease_cancellation(Duration::from_secs(60))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cancelled.proof_request.request_id, vec![4; 32]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,520 @@
+//! The tests shared by the [`Db`] backends, run against each of them.
+
+use std::{collections::HashMap, time::Duration};
+
+use alloy_primitives::Address;
+use axum::body::Bytes;
+use sp1_sdk::network::proto::base_types::ProofRequest;
+
+use crate::{
+    db::{
+        AdminAction, Db, InMemoryDb, MAX_LEASE_ATTEMPTS, ProofRequestState, SqliteDb,
+        StdinRetention, StdinStoreStats, StoredUsage, UploadOutcome, now,
+    },
+    stdin::{Stdin, StdinData},
+};
+
+pub(super) const RETENTION: StdinRetention = StdinRetention {
+    unclaimed: Duration::from_secs(60),
+    after_finish: Duration::ZERO,
+};
+
+pub(super) const REQUESTER: Address = Address::repeat_byte(9);
+
+pub(super) fn stdin() -> Stdin {
+    Stdin::from_parts(
+        StdinData::Memory(Bytes::from_static(&[1, 2, 3])),
+        vec![4; 60],
+        3,
+    )
+}
+
+pub(super) fn proof_request(id: u8) -> ProofRequest {
+    ProofRequest {
+        request_id: vec![id; 32],
+        ..Default::default()
+    }
+}
+
+/// Run each test against every backend, in a module named after the backend.
+macro_rules! db_tests {
+    ($($test:ident),* $(,)?) => {
+        mod in_memory {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    super::$test(super::InMemoryDb::new(super::RETENTION)).await;
+                }
+            )*
+        }
+
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    let db = super::SqliteDb::open(":memory:", super::RETENTION).unwrap();
+                    super::$test(db).await;
+                }
+            )*
+        }
+    };
+}
+
+db_tests!(
+    test_upload_is_stored_once,
+    test_expired_lease_is_requeued,
+    test_last_attempt_is_flagged,
+    test_reinserted_request_is_leased_once,
+    test_leased_fulfillers,
+    test_stdin_is_deleted_once_its_requests_finish,
+    test_finished_requests_are_removed,
+    test_cancel_request,
+    test_set_priority,
+    test_admin_actions,
+    test_requester_usage,
+);
+
+async fn test_upload_is_stored_once(db: impl Db) {
+    for (id, expires_at) in [("artifact_1", now() + 60), ("artifact_2", now())] {
+        db.insert_artifact_request(id.to_string(), REQUESTER, expires_at)
+            .await
+            .unwrap();
+    }
+
+    // Looking up the owner keeps the upload pending.
+    for _ in 0..2 {
+        assert_eq!(
+            db.artifact_request_owner("artifact_1").await.unwrap(),
+            Some(REQUESTER)
+        );
+    }
+
+    // The upload is only consumed once stored, within the quota of its owner.
+    assert_eq!(
+        db.store_upload("artifact_1".to_string(), Address::ZERO, stdin(), None)
+            .await
+            .unwrap(),
+        UploadOutcome::NotPending
+    );
+    assert_eq!(
+        db.store_upload("artifact_1".to_string(), REQUESTER, stdin(), Some(2))
+            .await
+            .unwrap(),
+        UploadOutcome::OverQuota
+    );
+    assert_eq!(
+        db.store_upload("artifact_1".to_string(), REQUESTER, stdin(), Some(3))
+            .await
+            .unwrap(),
+        UploadOutcome::Stored
+    );
+    assert_eq!(
+        db.store_upload("artifact_1".to_string(), REQUESTER, stdin(), None)
+            .await
+            .unwrap(),
+        UploadOutcome::NotPending
+    );
+    assert!(
+        db.artifact_request_owner("artifact_1")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(db.stdin_owner("artifact_1").await.unwrap(), Some(REQUESTER));
+
+    // An expired upload can no longer be looked up nor stored.
+    assert!(
+        db.artifact_request_owner("artifact_2")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(
+        db.store_upload("artifact_2".to_string(), REQUESTER, stdin(), None)
+            .await
+            .unwrap(),
+        UploadOutcome::NotPending
+    );
+}
+
+async fn test_expired_lease_is_requeued(db: impl Db) {
+    db.insert_request(proof_request(1), REQUESTER, "artifact_1".to_string(), 0)
+        .await
+        .unwrap();
+
+    let lease = db.lease_request(Duration::ZERO).await.unwrap().unwrap();
+    assert_eq!(db.queued_proof_request_count().await.unwrap(), 0);
+    assert_eq!(db.leased_proof_request_count().await.unwrap(), 1);
+    assert_eq!(db.requeue_expired_leases().await.unwrap(), 1);
+    assert_eq!(db.queued_proof_request_count().await.unwrap(), 1);
+    assert_eq!(db.leased_proof_request_count().await.unwrap(), 0);
+
+    // The stale lease can no longer be acked.
+    assert!(
+        !db.complete_request(&lease.proof_request.request_id, &lease.lease_id)
+            .await
+            .unwrap()
+    );
+
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        db.complete_request(&lease.proof_request.request_id, &lease.lease_id)
+            .await
+            .unwrap()
+    );
+    assert!(db.lease_request(Duration::ZERO).await.unwrap().is_none());
+}
+
+async fn test_last_attempt_is_flagged(db: impl Db) {
+    db.insert_request(proof_request(1), REQUESTER, "artifact_1".to_string(), 0)
+        .await
+        .unwrap();
+
+    for attempt in 1..=MAX_LEASE_ATTEMPTS {
+        let lease = db.lease_request(Duration::ZERO).await.unwrap().unwrap();
+        assert_eq!(lease.last_attempt, attempt == MAX_LEASE_ATTEMPTS);
+        assert!(
+            db.fail_request(&lease.proof_request.request_id, &lease.lease_id, true)
+                .await
+                .unwrap()
+        );
+    }
+
+    // The request is failed, rather than requeued, after its last attempt.
+    assert!(db.lease_request(Duration::ZERO).await.unwrap().is_none());
+    assert_eq!(
+        db.request_state(&[1; 32]).await.unwrap(),
+        Some(ProofRequestState::Failed)
+    );
+}
+
+async fn test_reinserted_request_is_leased_once(db: impl Db) {
+    for priority in [0, 1] {
+        db.insert_request(
+            proof_request(1),
+            REQUESTER,
+            "artifact_1".to_string(),
+            priority,
+        )
+        .await
+        .unwrap();
+    }
+    db.insert_request(proof_request(2), REQUESTER, "artifact_2".to_string(), 0)
+        .await
+        .unwrap();
+
+    let mut leased = Vec::new();
+    while let Some(lease) = db.lease_request(Duration::from_secs(60)).await.unwrap() {
+        leased.push(lease.proof_request.request_id);
+    }
+    leased.sort();
+    assert_eq!(leased, vec![vec![1; 32], vec![2; 32]]);
+}
+
+async fn test_leased_fulfillers(db: impl Db) {
+    let fulfiller = Address::repeat_byte(5);
+    db.insert_request(
+        ProofRequest {
+            fulfiller: Some(fulfiller.to_vec()),
+            ..proof_request(1)
+        },
+        REQUESTER,
+        "artifact_1".to_string(),
+        0,
+    )
+    .await
+    .unwrap();
+
+    // Only the leased requests count.
+    assert!(db.leased_fulfillers("artifact_1").await.unwrap().is_empty());
+    db.lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        db.leased_fulfillers("artifact_1").await.unwrap(),
+        vec![fulfiller]
+    );
+    assert!(db.leased_fulfillers("artifact_2").await.unwrap().is_empty());
+}
+
+async fn test_stdin_is_deleted_once_its_requests_finish(db: impl Db) {
+    db.insert_stdin("artifact_1".to_string(), Address::ZERO, stdin())
+        .await
+        .unwrap();
+
+    // The same stdin is used by two requests.
+    for id in [1, 2] {
+        db.insert_request(proof_request(id), REQUESTER, "artifact_1".to_string(), 0)
+            .await
+            .unwrap();
+    }
+
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        db.complete_request(&lease.proof_request.request_id, &lease.lease_id)
+            .await
+            .unwrap()
+    );
+    assert_eq!(db.resident_stdin_count().await.unwrap(), 1);
+
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        db.fail_request(&lease.proof_request.request_id, &lease.lease_id, false)
+            .await
+            .unwrap()
+    );
+    assert_eq!(db.resident_stdin_count().await.unwrap(), 0);
+    assert!(db.get_stdin("artifact_1").await.unwrap().is_none());
+}
+
+async fn test_finished_requests_are_removed(db: impl Db) {
+    for id in [1, 2] {
+        db.insert_request(proof_request(id), REQUESTER, "artifact_1".to_string(), 0)
+            .await
+            .unwrap();
+    }
+
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        db.complete_request(&lease.proof_request.request_id, &lease.lease_id)
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(
+        db.remove_finished_requests(Duration::from_secs(60))
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        db.remove_finished_requests(Duration::ZERO).await.unwrap(),
+        1
+    );
+    assert!(
+        db.request_state(&lease.proof_request.request_id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(db.queued_proof_request_count().await.unwrap(), 1);
+}
+
+async fn test_cancel_request(db: impl Db) {
+    for id in [1, 2] {
+        let stdin_id = format!("artifact_{id}");
+        db.insert_stdin(stdin_id.clone(), REQUESTER, stdin())
+            .await
+            .unwrap();
+        db.insert_request(proof_request(id), REQUESTER, stdin_id, 0)
+            .await
+            .unwrap();
+    }
+
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    let leased_id = lease.proof_request.request_id.clone();
+    let queued_id = proof_request(2).request_id;
+
+    // The worker holding the lease can no longer renew nor ack it.
+    let cancelled = db.cancel_request(&leased_id).await.unwrap().unwrap();
+    assert_eq!(cancelled.lease_id.as_ref(), Some(&lease.lease_id));
+    assert!(
+        db.renew_lease(&leased_id, &lease.lease_id, Duration::from_secs(60))
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        !db.complete_request(&leased_id, &lease.lease_id)
+            .await
+            .unwrap()
+    );
+
+    let cancelled = db.cancel_request(&queued_id).await.unwrap().unwrap();
+    assert_eq!(cancelled.proof_request.request_id, queued_id);
+    assert!(cancelled.lease_id.is_none());
+    assert_eq!(db.queued_proof_request_count().await.unwrap(), 0);
+    assert!(db.lease_request(Duration::ZERO).await.unwrap().is_none());
+
+    // Both stdins are deleted right away.
+    assert_eq!(db.resident_stdin_count().await.unwrap(), 0);
+    assert_eq!(
+        db.request_state(&queued_id).await.unwrap(),
+        Some(ProofRequestState::Cancelled)
+    );
+    assert!(db.cancel_request(&queued_id).await.unwrap().is_none());
+
+    // Only the request cancelled while queued is left to report, and it is kept until acked.
+    assert_eq!(
+        db.remove_finished_requests(Duration::ZERO).await.unwrap(),
+        1
+    );
+    let unacked = db
+        .lease_cancellation(Duration::ZERO)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(unacked.proof_request.request_id, queued_id);
+
+    // The cancellation is handed out again once its lease expires, and the stale lease is
+    // rejected.
+    let reported = db
+        .lease_cancellation(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reported.proof_request.request_id, queued_id);
+    assert!(
+        db.lease_cancellation(Duration::from_secs(60))
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        !db.ack_cancellation(&queued_id, &unacked.lease_id)
+            .await
+            .unwrap()
+    );
+    assert!(
+        db.ack_cancellation(&queued_id, &reported.lease_id)
+            .await
+            .unwrap()
+    );
+    assert!(
+        db.lease_cancellation(Duration::ZERO)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert_eq!(
+        db.remove_finished_requests(Duration::ZERO).await.unwrap(),
+        1
+    );
+}
+
+async fn test_set_priority(db: impl Db) {
+    for id in [1, 2] {
+        db.insert_request(proof_request(id), REQUESTER, format!("artifact_{id}"), 0)
+            .await
+            .unwrap();
+    }
+
+    assert!(
+        db.set_priority(&proof_request(2).request_id, 1)
+            .await
+            .unwrap()
+    );
+    assert!(!db.set_priority(&[3; 32], 1).await.unwrap());
+
+    let requests = db.active_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].priority, 1);
+    assert_eq!(requests[1].requester, REQUESTER);
+
+    // The reprioritized request goes first.
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lease.proof_request.request_id, proof_request(2).request_id);
+
+    let info = db
+        .request_info(&lease.proof_request.request_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.state, ProofRequestState::Leased);
+    assert_eq!(info.attempts, 1);
+    assert!(info.lease_expires_at.is_some());
+}
+
+async fn test_admin_actions(db: impl Db) {
+    for action in ["Pause", "Resume"] {
+        db.record_admin_action(AdminAction {
+            timestamp: 1,
+            admin: REQUESTER,
+            action: action.to_string(),
+            details: String::new(),
+            outcome: "ok".to_string(),
+        })
+        .await
+        .unwrap();
+    }
+
+    let actions = db.admin_actions(1).await.unwrap();
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].action, "Resume");
+    assert_eq!(actions[0].admin, REQUESTER);
+}
+
+async fn test_requester_usage(db: impl Db) {
+    db.insert_stdin("artifact_1".to_string(), REQUESTER, stdin())
+        .await
+        .unwrap();
+    db.insert_stdin("artifact_2".to_string(), REQUESTER, stdin())
+        .await
+        .unwrap();
+    for id in [1, 2] {
+        db.insert_request(proof_request(id), REQUESTER, "artifact_1".to_string(), 0)
+            .await
+            .unwrap();
+    }
+
+    let lease = db
+        .lease_request(Duration::from_secs(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        db.complete_request(&lease.proof_request.request_id, &lease.lease_id)
+            .await
+            .unwrap()
+    );
+
+    let usage = StoredUsage {
+        active_requests: 1,
+        stdin_bytes: 6,
+    };
+    assert_eq!(db.requester_usage(&REQUESTER).await.unwrap(), usage);
+    assert_eq!(
+        db.requester_usage(&Address::ZERO).await.unwrap(),
+        StoredUsage::default()
+    );
+    assert_eq!(
+        db.usage().await.unwrap(),
+        HashMap::from([(REQUESTER, usage)])
+    );
+
+    // Only the stdin of the queued request is claimed.
+    assert_eq!(
+        db.stdin_stats().await.unwrap(),
+        StdinStoreStats {
+            count: 2,
+            total_bytes: 6,
+            claimed: 1,
+            spilled: 0,
+        }
+    );
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use alloy_primitives::Address;
+use anyhow::{Context, Result};
+use sp1_tee_private_utils::watch_file;
+
+/// The fulfiller addresses the server accepts proof requests and stdin downloads for. Several
+/// addresses can be active at once, so a key can be rotated while the requests assigned to the
+/// previous one are still in flight.
+#[derive(Debug)]
+pub struct ActiveFulfillers {
+    addresses: RwLock<BTreeSet<Address>>,
+}
+
+impl ActiveFulfillers {
+    pub fn new(addresses: impl IntoIterator<Item = Address>) -> Self {
+        Self {
+            addresses: RwLock::new(addresses.into_iter().collect()),
+        }
+    }
+
+    pub fn contains(&self, address: &[u8]) -> bool {
+        Address::try_from(address)
+            .is_ok_and(|address| self.addresses.read().unwrap().contains(&address))
+    }
+
+    /// The active addresses, in ascending order.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.addresses.read().unwrap().iter().copied().collect()
+    }
+
+    /// Replace the active addresses, and log the added and retired ones.
+    pub fn replace(&self, addresses: BTreeSet<Address>) {
+        let mut current = self.addresses.write().unwrap();
+
+        for address in addresses.difference(&current) {
+            tracing::info!(%address, "Fulfiller address added");
+        }
+        for address in current.difference(&addresses) {
+            tracing::info!(%address, "Fulfiller address retired");
+        }
+
+        *current = addresses;
+    }
+}
+
+/// Parse a list of addresses, one per line. Empty lines, and the text after a `#`, are ignored.
+pub fn parse_addresses(content: &str) -> Result<BTreeSet<Address>> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse()
+                .with_context(|| format!("invalid fulfiller address `{line}`"))
+        })
+        .collect()
+}
+
+pub fn load_addresses(path: &Path) -> Result<BTreeSet<Address>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    parse_addresses(&content)
+}
+
+/// Reload the active addresses whenever the file changes. An invalid file is ignored, keeping
+/// the previous addresses.
+pub async fn watch_addresses(path: PathBuf, fulfillers: Arc<ActiveFulfillers>) {
+    watch_file(path, |path| match load_addresses(path) {
+        Ok(addresses) if addresses.is_empty() => {
+            tracing::error!(
+                "No active fulfiller address in {}, ignoring",
+                path.display()
+            );
+        }
+        Ok(addresses) => fulfillers.replace(addresses),
+        Err(err) => tracing::error!("Failed to reload the fulfiller addresses: {err:#}"),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_addresses() {
+        let old = Address::repeat_byte(1);
+        let new = Address::repeat_byte(2);
+
+        let content = format!("# Rotating to the new key\n{old}  # retiring\n\n{new}\n");
+        let addresses = parse_addresses(&content).unwrap();
+        assert_eq!(addresses, BTreeSet::from([old, new]));
+        assert!(parse_addresses("0x1234").is_err());
+
+        let fulfillers = ActiveFulfillers::new([old]);
+        assert!(fulfillers.contains(old.as_slice()));
+        assert!(!fulfillers.contains(new.as_slice()));
+        assert!(!fulfillers.contains(&[1; 4]));
+
+        fulfillers.replace(addresses);
+        assert_eq!(fulfillers.addresses(), vec![old, new]);
+
+        fulfillers.replace(BTreeSet::from([new]));
+        assert!(!fulfillers.contains(old.as_slice()));
+        assert!(fulfillers.contains(new.as_slice()));
+    }
+}
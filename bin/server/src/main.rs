@@ -1,102 +1,474 @@
-use std::sync::Arc;
+use std::{io, net::SocketAddr, num::NonZeroUsize, sync::Arc, time::Duration};
 
 use axum::{
     Json, Router,
     extract::State,
+    http::StatusCode,
     routing::{get, put},
 };
+use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
 use rustls::crypto::aws_lc_rs;
 use serde::{Deserialize, Serialize};
 use sp1_sdk::network::proto::artifact::artifact_store_server::ArtifactStoreServer;
-use sp1_tee_private_types::prover_network_server::ProverNetworkServer;
+use sp1_tee_private_types::{
+    admin_server::AdminServer, attestation_server::AttestationServer,
+    prover_network_server::ProverNetworkServer,
+};
+use sp1_tee_private_utils::{
+    DstackClient, fulfiller_key_set,
+    internal_tls::{InternalIdentity, InternalRole},
+};
+use tokio::sync::Notify;
 use tonic::service::Routes;
 use tower_http::cors::CorsLayer;
 use tracing::info;
 
 use crate::{
-    artifact_routes::{download_artifact, upload_artifact},
+    admin::AdminState,
+    artifact_routes::{DownloadState, UploadState, download_artifact, upload_artifact},
+    attestation::{Attester, attestation, enclave_key},
+    backpressure::Backpressure,
     cli::Args,
-    db::InMemoryDb,
-    server::{DefaultArtifactStoreServer, DefaultPrivateProverServer},
+    control::DispatchControl,
+    db::{Db, InMemoryDb, SqliteDb, StdinRetention},
+    fulfillers::{ActiveFulfillers, load_addresses, watch_addresses},
+    policy::{AccessPolicy, Policy, watch_policy},
+    quota::{QuotaConfig, Quotas, watch_quotas},
+    server::{
+        DefaultAdminServer, DefaultArtifactStoreServer, DefaultAttestationServer,
+        DefaultPrivateProverServer,
+    },
+    stdin::{MasterKey, TransportKey},
+    url_signer::UrlSigner,
 };
 
+mod admin;
 mod artifact_routes;
+mod attestation;
+mod backpressure;
 mod cli;
+mod control;
 mod db;
+mod fulfillers;
+mod policy;
+mod quota;
+mod ra_tls;
 mod server;
+mod stdin;
+mod url_signer;
+
+const REAPER_INTERVAL: Duration = Duration::from_secs(5);
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
     sp1_sdk::utils::setup_logger();
     aws_lc_rs::default_provider().install_default().unwrap();
 
     let args = Args::parse();
 
+    #[cfg(not(feature = "local"))]
+    anyhow::ensure!(
+        args.internal_tls,
+        "Internal TLS can only be disabled in local builds"
+    );
+
     info!("Starting server on port {}...", args.server_port);
 
-    let db = Arc::new(InMemoryDb::new());
+    let retention = StdinRetention {
+        unclaimed: Duration::from_secs(args.stdin_unclaimed_retention_secs),
+        after_finish: Duration::from_secs(args.stdin_retention_secs),
+    };
 
-    let mut routes_builder = Routes::builder();
+    match &args.database_path {
+        Some(path) => {
+            info!("Using SQLite database at {}", path.display());
+            let db = SqliteDb::open(path, retention).unwrap();
+            run(args, Arc::new(db)).await;
+        }
+        None => {
+            info!("Using in-memory database");
+            run(args, Arc::new(InMemoryDb::new(retention))).await;
+        }
+    }
+}
+
+async fn run<DB: Db>(args: Args, db: Arc<DB>) {
+    let lease_duration = Duration::from_secs(args.lease_duration_secs);
+
+    let dispatch = Arc::new(Notify::new());
+
+    db.set_requester_weights(args.requester_weights.iter().copied().collect())
+        .await
+        .expect("failed to set the requester weights");
+
+    let dstack = DstackClient::new(&args.dstack_socket_path);
+
+    let url_signer = Arc::new(
+        UrlSigner::derive(&dstack, Duration::from_secs(args.stdin_url_ttl_secs))
+            .await
+            .expect("failed to derive the upload URL signing key"),
+    );
+
+    let fulfiller_addresses = match &args.fulfiller_addresses_path {
+        Some(path) => load_addresses(path).expect("failed to load the fulfiller addresses"),
+        None => fulfiller_key_set(
+            args.fulfiller_key_source,
+            &args.fulfiller_private_key,
+            &args.fulfiller_key_ids,
+            &args.remote_signer_url,
+            &dstack,
+        )
+        .await
+        .expect("failed to get the fulfiller keys")
+        .addresses()
+        .into_iter()
+        .collect(),
+    };
+    for address in &fulfiller_addresses {
+        info!("Fulfiller address: {address}");
+    }
+    let fulfillers = Arc::new(ActiveFulfillers::new(fulfiller_addresses));
+
+    if let Some(path) = &args.fulfiller_addresses_path {
+        tokio::spawn(watch_addresses(path.clone(), fulfillers.clone()));
+    }
+
+    let policy = Arc::new(match &args.policy_path {
+        Some(path) => {
+            info!("Using the access policy at {}", path.display());
+            AccessPolicy::new(Policy::load(path).expect("failed to load the access policy"))
+        }
+        None => AccessPolicy::default(),
+    });
+
+    if let Some(path) = &args.policy_path {
+        tokio::spawn(watch_policy(path.clone(), policy.clone()));
+    }
+
+    let quotas = Arc::new(match &args.quotas_path {
+        Some(path) => {
+            info!("Using the quotas at {}", path.display());
+            Quotas::new(QuotaConfig::load(path).expect("failed to load the quotas"))
+        }
+        None => Quotas::default(),
+    });
+
+    if let Some(path) = &args.quotas_path {
+        tokio::spawn(watch_quotas(path.clone(), quotas.clone()));
+    }
+
+    let backpressure = Arc::new(Backpressure::new(
+        args.max_queue_depth.map(NonZeroUsize::get),
+        args.max_estimated_wait_secs.map(Duration::from_secs),
+    ));
+
+    let control = Arc::new(DispatchControl::default());
+
+    let attester = Arc::new(Attester::new(dstack.clone(), fulfillers.clone()));
 
-    routes_builder.add_service(ProverNetworkServer::new(DefaultPrivateProverServer::new(
+    tokio::spawn(reap_expired(
+        db.clone(),
+        dispatch.clone(),
+        Duration::from_secs(args.request_retention_secs),
+    ));
+
+    let internal_identity = if args.internal_tls {
+        let identity = InternalIdentity::derive(&dstack, InternalRole::Server)
+            .await
+            .expect("failed to derive the internal TLS identity");
+        Some(identity)
+    } else {
+        tracing::warn!("Internal TLS disabled, the stdins are sent in plaintext to the fulfiller");
+        None
+    };
+
+    let artifacts_scheme = if internal_identity.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    #[cfg(feature = "local")]
+    let artifacts_host = "localhost";
+    #[cfg(not(feature = "local"))]
+    let artifacts_host = "server";
+
+    let prover_server = DefaultPrivateProverServer::new(
         args.hostname.clone(),
         args.network_rpc_url.clone(),
-        args.fulfiller_private_key.clone(),
-        args.artifacts_port,
+        fulfillers.clone(),
+        policy.clone(),
+        quotas.clone(),
+        backpressure.clone(),
+        control.clone(),
+        format!(
+            "{artifacts_scheme}://{artifacts_host}:{}",
+            args.artifacts_port
+        ),
+        lease_duration,
+        args.priority_classes.iter().copied().collect(),
+        args.admin_addresses.iter().copied().collect(),
+        dispatch,
         db.clone(),
-    )));
+    );
+    let internal_prover_server = prover_server.internal();
+
+    let mut routes_builder = Routes::builder();
+
+    if args.admin_addresses.is_empty() {
+        info!("No admin addresses set, the admin service is disabled");
+    } else {
+        routes_builder.add_service(AdminServer::new(DefaultAdminServer::new(
+            prover_server.clone(),
+            control.clone(),
+            fulfillers.clone(),
+            args.admin_addresses.iter().copied().collect(),
+            db.clone(),
+        )));
+    }
+
+    routes_builder.add_service(ProverNetworkServer::new(prover_server));
 
     routes_builder.add_service(ArtifactStoreServer::new(
         DefaultArtifactStoreServer::new(
             args.hostname.clone(),
             args.network_rpc_url.clone(),
+            url_signer.clone(),
+            policy,
+            quotas.clone(),
             db.clone(),
         )
         .await,
     ));
 
+    routes_builder.add_service(AttestationServer::new(DefaultAttestationServer::new(
+        attester.clone(),
+    )));
+
     let grpc_routes = routes_builder.routes().into_axum_router();
 
-    let server = Router::new()
-        .route("/artifacts/stdin/:id", put(upload_artifact))
-        .route("/health", get(health))
-        .with_state(db.clone())
-        .merge(grpc_routes)
-        .layer(CorsLayer::permissive());
+    let spill_dir = match (&args.stdin_spill_dir, &args.database_path) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(path)) => path.with_file_name("stdins"),
+        (None, None) => std::env::temp_dir().join("sp1-tee-stdins"),
+    };
+    std::fs::create_dir_all(&spill_dir).unwrap();
 
-    let download_artifacts = Router::new()
-        .route("/artifacts/stdin/:id", get(download_artifact))
-        .with_state(db);
+    #[cfg(feature = "local")]
+    let master_key_override = args.stdin_master_key;
+    #[cfg(not(feature = "local"))]
+    let master_key_override = None;
 
-    let server_listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.server_port))
-        .await
-        .unwrap();
+    let master_key = Arc::new(match master_key_override {
+        Some(key) => {
+            tracing::warn!("Using the stdin master key set in the environment");
+            MasterKey::new(key)
+        }
+        None => MasterKey::derive(&dstack)
+            .await
+            .expect("failed to derive the stdin master key"),
+    });
+
+    let transport_key = Arc::new(TransportKey::generate());
+
+    let upload_state = Arc::new(UploadState {
+        db: db.clone(),
+        url_signer,
+        master_key: master_key.clone(),
+        transport_key: transport_key.clone(),
+        max_stdin_size: args.max_stdin_size,
+        quotas: quotas.clone(),
+        spill_threshold: args.stdin_spill_threshold,
+        spill_dir,
+    });
+
+    let mut server = Router::new()
+        .route("/artifacts/stdin/:id", put(upload_artifact::<DB>))
+        .with_state(upload_state)
+        .merge(
+            Router::new()
+                .route("/health", get(health::<DB>))
+                .with_state((db.clone(), backpressure, control)),
+        )
+        .merge(
+            Router::new()
+                .route("/enclave-key", get(enclave_key))
+                .with_state((attester.clone(), transport_key)),
+        )
+        .merge(
+            Router::new()
+                .route("/attestation", get(attestation))
+                .with_state(attester.clone()),
+        )
+        .merge(grpc_routes);
+
+    match &args.admin_token {
+        Some(token) => {
+            server = server.merge(
+                Router::new()
+                    .route("/admin/usage", get(admin::usage::<DB>))
+                    .route("/admin/queue", get(admin::queue::<DB>))
+                    .with_state(Arc::new(AdminState::new(db.clone(), quotas, token))),
+            );
+        }
+        None => info!("No admin token set, the admin endpoints are disabled"),
+    }
+
+    let server = server.layer(CorsLayer::permissive());
 
-    let artifacts_listener =
-        tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.artifacts_port))
+    // The fulfiller reaches the server through its own port, authenticated by the internal TLS
+    // channel.
+    let internal = Router::new()
+        .route("/artifacts/stdin/:id", get(download_artifact::<DB>))
+        .with_state(Arc::new(DownloadState {
+            db,
+            master_key,
+            fulfillers,
+            single_use: args.single_use_stdin_downloads,
+        }))
+        .merge(Routes::new(ProverNetworkServer::new(internal_prover_server)).into_axum_router());
+
+    let tls_config = if args.ra_tls {
+        let config = ra_tls::server_config(&attester, &args.hostname)
             .await
-            .unwrap();
+            .expect("failed to build the RA-TLS certificate");
+        info!("Serving with RA-TLS");
+        Some(RustlsConfig::from_config(Arc::new(config)))
+    } else {
+        None
+    };
+
+    let internal_tls_config = internal_identity.map(|identity| {
+        let config = identity
+            .server_config()
+            .expect("failed to build the internal TLS configuration");
+        RustlsConfig::from_config(Arc::new(config))
+    });
 
-    let (server_result, artifacts_result) = tokio::join!(
-        axum::serve(server_listener, server),
-        axum::serve(artifacts_listener, download_artifacts)
+    let (server_result, internal_result) = tokio::join!(
+        serve(args.server_port, tls_config, server),
+        serve(args.artifacts_port, internal_tls_config, internal)
     );
 
-    server_result.unwrap();
-    artifacts_result.unwrap();
+    server_result?;
+    internal_result?;
+
+    Ok(())
+}
+
+/// Serve the router on the port, over TLS if configured.
+async fn serve(port: u16, tls_config: Option<RustlsConfig>, router: Router) -> io::Result<()> {
+    match tls_config {
+        Some(tls_config) => {
+            axum_server::bind_rustls(SocketAddr::from(([0, 0, 0, 0], port)), tls_config)
+                .serve(router.into_make_service())
+                .await
+        }
+        None => {
+            let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}")).await?;
+            axum::serve(listener, router).await
+        }
+    }
 }
 
-async fn health(State(db): State<Arc<InMemoryDb>>) -> Json<HealthResponse> {
+/// Periodically put back on the queue the requests whose lease expired, so a crashed worker does
+/// not lose them, drop the queued requests past their deadline, forget the presigned upload URLs
+/// that expired unused, delete the stdins past their retention, and forget the finished requests
+/// after `request_retention`.
+async fn reap_expired<DB: Db>(db: Arc<DB>, dispatch: Arc<Notify>, request_retention: Duration) {
+    let mut interval = tokio::time::interval(REAPER_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match db.requeue_expired_leases().await {
+            Ok(0) => {}
+            Ok(count) => {
+                tracing::warn!("Requeued {count} proof requests with an expired lease");
+                dispatch.notify_waiters();
+            }
+            Err(err) => tracing::error!("Failed to requeue expired leases: {err}"),
+        }
+
+        match db.expire_requests().await {
+            Ok(0) => {}
+            Ok(count) => {
+                tracing::warn!("Dropped {count} queued proof requests past their deadline")
+            }
+            Err(err) => tracing::error!("Failed to expire proof requests: {err}"),
+        }
+
+        match db.remove_expired_artifact_requests().await {
+            Ok(0) => {}
+            Ok(count) => tracing::debug!("Removed {count} expired presigned upload URLs"),
+            Err(err) => tracing::error!("Failed to remove expired artifact requests: {err}"),
+        }
+
+        match db.remove_expired_stdins().await {
+            Ok(0) => {}
+            Ok(count) => tracing::debug!("Deleted {count} stdins past their retention"),
+            Err(err) => tracing::error!("Failed to delete expired stdins: {err}"),
+        }
+
+        match db.remove_finished_requests(request_retention).await {
+            Ok(0) => {}
+            Ok(count) => tracing::debug!("Removed {count} finished proof requests"),
+            Err(err) => tracing::error!("Failed to remove finished proof requests: {err}"),
+        }
+    }
+}
+
+async fn health<DB: Db>(
+    State((db, backpressure, control)): State<(Arc<DB>, Arc<Backpressure>, Arc<DispatchControl>)>,
+) -> Result<Json<HealthResponse>, StatusCode> {
+    let queued_proof_request_count = db
+        .queued_proof_request_count()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let leased_proof_request_count = db
+        .leased_proof_request_count()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     let response = HealthResponse {
-        queued_proof_request_count: db.queued_proof_request_count().await,
+        queued_proof_request_count,
+        leased_proof_request_count,
+        resident_stdin_count: db
+            .resident_stdin_count()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        accepting_work: !control.is_draining()
+            && backpressure
+                .check(queued_proof_request_count, leased_proof_request_count)
+                .is_ok(),
+        dispatch_paused: control.is_paused(),
+        estimated_wait_secs: backpressure
+            .estimated_wait(queued_proof_request_count, leased_proof_request_count)
+            .map(|wait| wait.as_secs()),
+        tenant_count: db
+            .tenant_queues()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .len(),
     };
 
-    Json(response)
+    Ok(Json(response))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
     queued_proof_request_count: usize,
+    leased_proof_request_count: usize,
+    resident_stdin_count: usize,
+    /// Whether new proof requests are accepted, or turned away by the backpressure or a drain.
+    accepting_work: bool,
+    /// Whether an admin paused the dispatch of the queued proof requests.
+    dispatch_paused: bool,
+    /// The estimated wait of a new proof request before it is leased, unknown until a request is
+    /// proved.
+    estimated_wait_secs: Option<u64>,
+    /// The number of requesters with queued proof requests. Their queues are only reported to
+    /// the admins, on `/admin/queue`.
+    tenant_count: usize,
 }
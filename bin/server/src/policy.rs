@@ -0,0 +1,419 @@
+//! The access-control policy of the server, deciding which requesters can upload stdins, and
+//! which proof requests they can submit.
+//!
+//! The policy is a TOML file of `[[deny]]` and `[[allow]]` rules. A rule matches a request when
+//! every criterion it sets matches: the requester address in `requesters`, the program in
+//! `vk_hashes`, and the proof mode in `proof_modes`. A request matching a deny rule is rejected.
+//! If there are allow rules, a request must also match one of them, and stay under its
+//! `max_cycles` and `max_gas` limits.
+//!
+//! ```toml
+//! [[deny]]
+//! name = "banned"
+//! requesters = ["0x0000000000000000000000000000000000000001"]
+//!
+//! [[allow]]
+//! name = "partners"
+//! requesters = ["0x0000000000000000000000000000000000000002"]
+//! proof_modes = ["compressed", "groth16"]
+//! max_cycles = 1_000_000_000
+//! ```
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use alloy_primitives::{Address, B256};
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use sp1_sdk::network::proto::base_types::{ProofMode, RequestProofRequestBody};
+use sp1_tee_private_utils::watch_file;
+use tonic::Status;
+
+/// Why a request was rejected by the policy.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{0}")]
+pub struct PolicyViolation(String);
+
+impl From<PolicyViolation> for Status {
+    fn from(violation: PolicyViolation) -> Self {
+        Status::permission_denied(violation.0)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    allow: Vec<RuleFile>,
+    #[serde(default)]
+    deny: Vec<RuleFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    name: Option<String>,
+    requesters: Option<Vec<Address>>,
+    vk_hashes: Option<Vec<B256>>,
+    proof_modes: Option<Vec<String>>,
+    max_cycles: Option<u64>,
+    max_gas: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    requesters: Option<HashSet<Address>>,
+    vk_hashes: Option<HashSet<B256>>,
+    proof_modes: Option<Vec<ProofMode>>,
+    max_cycles: Option<u64>,
+    max_gas: Option<u64>,
+}
+
+impl Rule {
+    fn new(rule: RuleFile, name: String) -> Result<Self> {
+        let proof_modes = rule
+            .proof_modes
+            .map(|modes| {
+                modes
+                    .iter()
+                    .map(|mode| parse_proof_mode(mode))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()
+            .with_context(|| format!("invalid `{name}` rule"))?;
+
+        Ok(Self {
+            name: rule.name.unwrap_or(name),
+            requesters: rule.requesters.map(HashSet::from_iter),
+            vk_hashes: rule.vk_hashes.map(HashSet::from_iter),
+            proof_modes,
+            max_cycles: rule.max_cycles,
+            max_gas: rule.max_gas,
+        })
+    }
+
+    fn matches_requester(&self, requester: &Address) -> bool {
+        self.requesters
+            .as_ref()
+            .is_none_or(|requesters| requesters.contains(requester))
+    }
+
+    fn matches(&self, requester: &Address, vk_hash: Option<B256>, mode: ProofMode) -> bool {
+        self.matches_requester(requester)
+            && self
+                .vk_hashes
+                .as_ref()
+                .is_none_or(|vk_hashes| vk_hash.is_some_and(|vk_hash| vk_hashes.contains(&vk_hash)))
+            && self
+                .proof_modes
+                .as_ref()
+                .is_none_or(|modes| modes.contains(&mode))
+    }
+
+    /// Whether the rule only depends on the requester.
+    fn is_requester_only(&self) -> bool {
+        self.vk_hashes.is_none() && self.proof_modes.is_none()
+    }
+
+    fn check_limits(&self, body: &RequestProofRequestBody) -> Result<(), PolicyViolation> {
+        if let Some(max_cycles) = self.max_cycles
+            && body.cycle_limit > max_cycles
+        {
+            return Err(PolicyViolation(format!(
+                "The cycle limit {} exceeds the maximum {max_cycles} of the `{}` rule",
+                body.cycle_limit, self.name
+            )));
+        }
+
+        if let Some(max_gas) = self.max_gas
+            && body.gas_limit > max_gas
+        {
+            return Err(PolicyViolation(format!(
+                "The gas limit {} exceeds the maximum {max_gas} of the `{}` rule",
+                body.gas_limit, self.name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_proof_mode(mode: &str) -> Result<ProofMode> {
+    match mode {
+        "core" => Ok(ProofMode::Core),
+        "compressed" => Ok(ProofMode::Compressed),
+        "plonk" => Ok(ProofMode::Plonk),
+        "groth16" => Ok(ProofMode::Groth16),
+        _ => bail!(
+            "invalid proof mode `{mode}`, expected `core`, `compressed`, `plonk` or `groth16`"
+        ),
+    }
+}
+
+/// A parsed policy. The default policy allows everything.
+#[derive(Debug, Default)]
+pub struct Policy {
+    allow: Vec<Rule>,
+    deny: Vec<Rule>,
+}
+
+impl Policy {
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: PolicyFile = toml::from_str(content)?;
+
+        let deny = file
+            .deny
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                if rule.max_cycles.is_some() || rule.max_gas.is_some() {
+                    bail!("the limits of the `deny[{i}]` rule are only supported on allow rules");
+                }
+                Rule::new(rule, format!("deny[{i}]"))
+            })
+            .collect::<Result<_>>()?;
+        let allow = file
+            .allow
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| Rule::new(rule, format!("allow[{i}]")))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { allow, deny })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        Self::parse(&content)
+    }
+
+    /// Check that a requester can use the server at all, before it uploads a stdin. Only the
+    /// rules depending on the requester alone can reject it.
+    pub fn check_requester(&self, requester: &Address) -> Result<(), PolicyViolation> {
+        if let Some(rule) = self
+            .deny
+            .iter()
+            .find(|rule| rule.is_requester_only() && rule.matches_requester(requester))
+        {
+            return Err(PolicyViolation(format!(
+                "The requester {requester} is denied by the `{}` rule",
+                rule.name
+            )));
+        }
+
+        if !self.allow.is_empty()
+            && !self
+                .allow
+                .iter()
+                .any(|rule| rule.matches_requester(requester))
+        {
+            return Err(PolicyViolation(format!(
+                "The requester {requester} is not allowed by any rule"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Check a proof request signed by `requester`.
+    pub fn check_request(
+        &self,
+        requester: &Address,
+        body: &RequestProofRequestBody,
+    ) -> Result<(), PolicyViolation> {
+        let vk_hash = B256::try_from(body.vk_hash.as_slice()).ok();
+        let mode = ProofMode::try_from(body.mode).unwrap_or(ProofMode::UnspecifiedProofMode);
+
+        if let Some(rule) = self
+            .deny
+            .iter()
+            .find(|rule| rule.matches(requester, vk_hash, mode))
+        {
+            return Err(PolicyViolation(format!(
+                "The request is denied by the `{}` rule",
+                rule.name
+            )));
+        }
+
+        if self.allow.is_empty() {
+            return Ok(());
+        }
+
+        // Report the limits of the first matching rule if no rule accepts the request.
+        let mut violation = None;
+        for rule in self
+            .allow
+            .iter()
+            .filter(|rule| rule.matches(requester, vk_hash, mode))
+        {
+            match rule.check_limits(body) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    violation.get_or_insert(err);
+                }
+            }
+        }
+
+        Err(violation.unwrap_or_else(|| {
+            PolicyViolation(format!(
+                "The request from {requester} is not allowed by any rule"
+            ))
+        }))
+    }
+}
+
+/// The current policy, shared by the services, and replaced when the policy file changes.
+#[derive(Debug, Default)]
+pub struct AccessPolicy {
+    policy: RwLock<Arc<Policy>>,
+}
+
+impl AccessPolicy {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy: RwLock::new(Arc::new(policy)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Policy> {
+        self.policy.read().unwrap().clone()
+    }
+
+    fn replace(&self, policy: Policy) {
+        *self.policy.write().unwrap() = Arc::new(policy);
+    }
+}
+
+/// Reload the policy whenever the file changes. An invalid file is ignored, keeping the previous
+/// policy.
+pub async fn watch_policy(path: PathBuf, policy: Arc<AccessPolicy>) {
+    watch_file(path, |path| match Policy::load(path) {
+        Ok(new) => {
+            tracing::info!(
+                allow = new.allow.len(),
+                deny = new.deny.len(),
+                "Reloaded the access policy"
+            );
+            policy.replace(new);
+        }
+        Err(err) => tracing::error!("Failed to reload the access policy: {err:#}"),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+        [[deny]]
+        name = "banned"
+        requesters = ["0x0101010101010101010101010101010101010101"]
+
+        [[deny]]
+        vk_hashes = ["0x0303030303030303030303030303030303030303030303030303030303030303"]
+
+        [[allow]]
+        name = "partners"
+        requesters = ["0x0202020202020202020202020202020202020202"]
+        proof_modes = ["compressed", "groth16"]
+        max_cycles = 1000
+
+        [[allow]]
+        name = "large"
+        requesters = ["0x0202020202020202020202020202020202020202"]
+        proof_modes = ["groth16"]
+        max_cycles = 5000
+        max_gas = 100
+    "#;
+
+    fn body(
+        vk_hash: u8,
+        mode: ProofMode,
+        cycle_limit: u64,
+        gas_limit: u64,
+    ) -> RequestProofRequestBody {
+        RequestProofRequestBody {
+            vk_hash: vec![vk_hash; 32],
+            mode: mode as i32,
+            cycle_limit,
+            gas_limit,
+            ..Default::default()
+        }
+    }
+
+    fn reason(result: Result<(), PolicyViolation>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let banned = Address::repeat_byte(1);
+        let partner = Address::repeat_byte(2);
+        let stranger = Address::repeat_byte(4);
+
+        assert_eq!(
+            reason(policy.check_requester(&banned)),
+            format!("The requester {banned} is denied by the `banned` rule")
+        );
+        assert!(policy.check_requester(&partner).is_ok());
+        assert!(policy.check_requester(&stranger).is_err());
+
+        assert!(
+            policy
+                .check_request(&partner, &body(5, ProofMode::Compressed, 1000, 0))
+                .is_ok()
+        );
+        assert_eq!(
+            reason(policy.check_request(&partner, &body(3, ProofMode::Compressed, 1000, 0))),
+            "The request is denied by the `deny[1]` rule"
+        );
+        assert_eq!(
+            reason(policy.check_request(&partner, &body(5, ProofMode::Compressed, 1001, 0))),
+            "The cycle limit 1001 exceeds the maximum 1000 of the `partners` rule"
+        );
+        assert!(
+            policy
+                .check_request(&partner, &body(5, ProofMode::Groth16, 5000, 100))
+                .is_ok()
+        );
+        assert_eq!(
+            reason(policy.check_request(&partner, &body(5, ProofMode::Groth16, 5000, 101))),
+            "The cycle limit 5000 exceeds the maximum 1000 of the `partners` rule"
+        );
+        assert!(
+            policy
+                .check_request(&partner, &body(5, ProofMode::Core, 10, 0))
+                .is_err()
+        );
+        assert!(
+            policy
+                .check_request(&stranger, &body(5, ProofMode::Compressed, 10, 0))
+                .is_err()
+        );
+
+        // Without rules, everything is allowed.
+        let policy = Policy::default();
+        assert!(policy.check_requester(&banned).is_ok());
+        assert!(
+            policy
+                .check_request(&banned, &body(3, ProofMode::Core, u64::MAX, u64::MAX))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        assert!(Policy::parse("[[allow]]\nproof_modes = [\"fast\"]").is_err());
+        assert!(Policy::parse("[[deny]]\nmax_cycles = 10").is_err());
+        assert!(Policy::parse("[[allow]]\nrequester = []").is_err());
+        assert!(Policy::parse("[[allow]]\nrequesters = [\"0x01\"]").is_err());
+    }
+}
@@ -0,0 +1,565 @@
+//! The per-requester quotas, limiting how much of the server a single requester can use.
+//!
+//! The quotas are read from a TOML file. The `[default]` limits apply to every requester, and can
+//! be overridden per requester address. An unset limit is unlimited.
+//!
+//! ```toml
+//! [default]
+//! requests_per_minute = 10
+//! max_active_requests = 20
+//! max_stdin_bytes = 1_000_000_000
+//! max_cycles_per_day = 100_000_000_000
+//!
+//! [requesters."0x0000000000000000000000000000000000000002"]
+//! requests_per_minute = 100
+//! max_active_requests = 200
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use alloy_primitives::Address;
+use anyhow::{Context, Result};
+use axum::{
+    http::{StatusCode, header::RETRY_AFTER},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sp1_tee_private_utils::watch_file;
+use tonic::{Status, metadata::MetadataValue};
+
+use crate::db::{StoredUsage, now};
+
+/// The window of the requests per minute quota, in seconds.
+const MINUTE: u64 = 60;
+
+/// The window of the cycles per day quota, in seconds.
+const DAY: u64 = 24 * 60 * 60;
+
+/// The retry hint when a requester holds too many requests or stdins, as they are only released
+/// once the requests are proved.
+const HELD_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// The limits of a requester.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaLimits {
+    /// The proof requests accepted per minute.
+    pub requests_per_minute: Option<usize>,
+    /// The proof requests queued or being proved at once.
+    pub max_active_requests: Option<usize>,
+    /// The total size of the stored stdins.
+    pub max_stdin_bytes: Option<u64>,
+    /// The total cycle limit of the proof requests accepted over the last 24 hours.
+    pub max_cycles_per_day: Option<u64>,
+}
+
+impl QuotaLimits {
+    /// The limits set here, falling back to `default` for the others.
+    fn or(self, default: Self) -> Self {
+        Self {
+            requests_per_minute: self.requests_per_minute.or(default.requests_per_minute),
+            max_active_requests: self.max_active_requests.or(default.max_active_requests),
+            max_stdin_bytes: self.max_stdin_bytes.or(default.max_stdin_bytes),
+            max_cycles_per_day: self.max_cycles_per_day.or(default.max_cycles_per_day),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuotaFile {
+    #[serde(default)]
+    default: QuotaLimits,
+    #[serde(default)]
+    requesters: HashMap<String, QuotaLimits>,
+}
+
+/// The parsed quotas. The default quotas are unlimited.
+#[derive(Debug, Default)]
+pub struct QuotaConfig {
+    default: QuotaLimits,
+    requesters: HashMap<Address, QuotaLimits>,
+}
+
+impl QuotaConfig {
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: QuotaFile = toml::from_str(content)?;
+
+        let requesters = file
+            .requesters
+            .into_iter()
+            .map(|(address, limits)| {
+                let address = address
+                    .parse::<Address>()
+                    .with_context(|| format!("invalid requester address `{address}`"))?;
+                Ok((address, limits.or(file.default)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            default: file.default,
+            requesters,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        Self::parse(&content)
+    }
+
+    pub fn limits(&self, requester: &Address) -> QuotaLimits {
+        self.requesters
+            .get(requester)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// A request rejected because the requester is over one of its quotas.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{reason}")]
+pub struct QuotaExceeded {
+    reason: String,
+    /// When the requester can expect to be under the quota again, `None` if the request can
+    /// never fit in it.
+    retry_after: Option<Duration>,
+}
+
+impl QuotaExceeded {
+    fn new(reason: String, retry_after: Duration) -> Self {
+        Self {
+            reason,
+            retry_after: Some(retry_after),
+        }
+    }
+
+    /// A request over the quota on its own, which retrying will not help.
+    fn permanent(reason: String) -> Self {
+        Self {
+            reason,
+            retry_after: None,
+        }
+    }
+
+    /// The requester stores the maximum total size of stdins.
+    pub fn stdin(requester: &Address) -> Self {
+        Self::new(
+            format!("The requester {requester} stores the maximum stdin size"),
+            HELD_RETRY_AFTER,
+        )
+    }
+}
+
+impl From<QuotaExceeded> for Status {
+    fn from(exceeded: QuotaExceeded) -> Self {
+        match exceeded.retry_after {
+            Some(retry_after) => resource_exhausted(&exceeded.reason, retry_after),
+            None => Status::failed_precondition(exceeded.reason),
+        }
+    }
+}
+
+impl IntoResponse for QuotaExceeded {
+    fn into_response(self) -> Response {
+        match self.retry_after {
+            Some(retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after_secs(retry_after).to_string())],
+                self.reason,
+            )
+                .into_response(),
+            None => (StatusCode::BAD_REQUEST, self.reason).into_response(),
+        }
+    }
+}
+
+/// A `ResourceExhausted` status, with the `retry-after` hint in seconds in its metadata.
+pub fn resource_exhausted(reason: &str, retry_after: Duration) -> Status {
+    let retry_after = retry_after_secs(retry_after);
+    let mut status = Status::resource_exhausted(format!("{reason}, retry after {retry_after}s"));
+    status
+        .metadata_mut()
+        .insert("retry-after", MetadataValue::from(retry_after));
+
+    status
+}
+
+/// A retry hint in whole seconds, at least one.
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs().max(1)
+}
+
+/// The usage of a requester, reported on the admin endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequesterUsage {
+    pub requester: Address,
+    pub requests_last_minute: usize,
+    pub active_requests: usize,
+    pub stdin_bytes: u64,
+    pub cycles_last_day: u64,
+    pub limits: QuotaLimits,
+}
+
+/// The proof requests of a requester accepted over the last day, or being submitted.
+#[derive(Debug, Default)]
+struct RecentRequests {
+    /// The acceptance time and cycle limit of each request, oldest first.
+    accepted: VecDeque<(u64, u64)>,
+    /// The requests being submitted, not yet counted as active by the database.
+    pending: usize,
+}
+
+impl RecentRequests {
+    fn prune(&mut self, now: u64) {
+        while self.accepted.front().is_some_and(|(at, _)| at + DAY <= now) {
+            self.accepted.pop_front();
+        }
+    }
+
+    /// The acceptance times of the requests of the last minute, oldest first.
+    fn last_minute(&self, now: u64) -> impl Iterator<Item = u64> + '_ {
+        self.accepted
+            .iter()
+            .map(|(at, _)| *at)
+            .filter(move |at| at + MINUTE > now)
+    }
+
+    fn cycles(&self) -> u64 {
+        self.accepted.iter().map(|(_, cycles)| cycles).sum()
+    }
+
+    /// How long until enough cycles leave the window to accept `cycles` more, which must be at
+    /// most `max_cycles`.
+    fn cycles_retry_after(&self, max_cycles: u64, cycles: u64, now: u64) -> Duration {
+        let mut total = self.cycles();
+
+        for (at, request_cycles) in &self.accepted {
+            total -= request_cycles;
+            if total.saturating_add(cycles) <= max_cycles {
+                return Duration::from_secs(at + DAY - now);
+            }
+        }
+
+        // Every request in the window has left it by then.
+        Duration::from_secs(DAY)
+    }
+}
+
+/// The quotas enforced by the services, replaced when the quota file changes.
+///
+/// The requests per minute and the cycles per day are counted in memory, from the proof requests
+/// accepted since the server started.
+#[derive(Debug, Default)]
+pub struct Quotas {
+    config: RwLock<Arc<QuotaConfig>>,
+    recent: Mutex<HashMap<Address, RecentRequests>>,
+}
+
+impl Quotas {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config: RwLock::new(Arc::new(config)),
+            recent: Mutex::default(),
+        }
+    }
+
+    fn replace(&self, config: QuotaConfig) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    pub fn limits(&self, requester: &Address) -> QuotaLimits {
+        self.config.read().unwrap().limits(requester)
+    }
+
+    /// How many more stdin bytes the requester can upload, `None` if unlimited.
+    pub fn remaining_stdin_bytes(&self, requester: &Address, usage: &StoredUsage) -> Option<u64> {
+        self.limits(requester)
+            .max_stdin_bytes
+            .map(|max| max.saturating_sub(usage.stdin_bytes))
+    }
+
+    /// Check that the requester can upload another stdin.
+    pub fn check_stdin(
+        &self,
+        requester: &Address,
+        usage: &StoredUsage,
+    ) -> Result<(), QuotaExceeded> {
+        match self.remaining_stdin_bytes(requester, usage) {
+            Some(0) => Err(QuotaExceeded::stdin(requester)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the requester can submit a proof request with a limit of `cycles`, and count
+    /// it against the quotas in the same step, so concurrent requests can not all pass the check.
+    /// The reservation is released when dropped, unless committed once the request is queued.
+    pub fn reserve_request(
+        &self,
+        requester: &Address,
+        usage: &StoredUsage,
+        cycles: u64,
+    ) -> Result<QuotaReservation<'_>, QuotaExceeded> {
+        self.reserve_request_at(requester, usage, cycles, now())
+    }
+
+    fn reserve_request_at(
+        &self,
+        requester: &Address,
+        usage: &StoredUsage,
+        cycles: u64,
+        now: u64,
+    ) -> Result<QuotaReservation<'_>, QuotaExceeded> {
+        let limits = self.limits(requester);
+
+        if let Some(max) = limits.max_cycles_per_day
+            && cycles > max
+        {
+            return Err(QuotaExceeded::permanent(format!(
+                "The cycle limit of {cycles} is over the quota of {max} cycles per day of the \
+                 requester {requester}"
+            )));
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        let requests = recent.entry(*requester).or_default();
+        requests.prune(now);
+
+        if let Some(max) = limits.max_active_requests
+            && usage.active_requests + requests.pending >= max
+        {
+            return Err(QuotaExceeded::new(
+                format!("The requester {requester} has the maximum of {max} active proof requests"),
+                HELD_RETRY_AFTER,
+            ));
+        }
+
+        if let Some(max) = limits.requests_per_minute
+            && requests.last_minute(now).count() >= max
+        {
+            let oldest = requests.last_minute(now).next().unwrap_or(now);
+            return Err(QuotaExceeded::new(
+                format!("The requester {requester} is over its quota of {max} requests per minute"),
+                Duration::from_secs(oldest + MINUTE - now),
+            ));
+        }
+
+        if let Some(max) = limits.max_cycles_per_day
+            && requests.cycles().saturating_add(cycles) > max
+        {
+            return Err(QuotaExceeded::new(
+                format!("The requester {requester} is over its quota of {max} cycles per day"),
+                requests.cycles_retry_after(max, cycles, now),
+            ));
+        }
+
+        requests.accepted.push_back((now, cycles));
+        requests.pending += 1;
+
+        Ok(QuotaReservation {
+            quotas: self,
+            requester: *requester,
+            request: (now, cycles),
+            committed: false,
+        })
+    }
+
+    /// The usage of the requesters holding requests or stdins, or with recent requests.
+    pub fn usage(&self, stored: HashMap<Address, StoredUsage>) -> Vec<RequesterUsage> {
+        let now = now();
+        let mut recent = self.recent.lock().unwrap();
+
+        recent.retain(|_, requests| {
+            requests.prune(now);
+            !requests.accepted.is_empty() || requests.pending > 0
+        });
+
+        let mut requesters = stored
+            .keys()
+            .chain(recent.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        requesters.sort();
+        requesters.dedup();
+
+        requesters
+            .into_iter()
+            .map(|requester| {
+                let usage = stored.get(&requester).copied().unwrap_or_default();
+                let requests = recent.get(&requester);
+
+                RequesterUsage {
+                    requester,
+                    requests_last_minute: requests.map_or(0, |r| r.last_minute(now).count()),
+                    active_requests: usage.active_requests,
+                    stdin_bytes: usage.stdin_bytes,
+                    cycles_last_day: requests.map_or(0, RecentRequests::cycles),
+                    limits: self.limits(&requester),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A proof request counted against the quotas of its requester while it is submitted, see
+/// [`Quotas::reserve_request`].
+#[must_use]
+#[derive(Debug)]
+pub struct QuotaReservation<'a> {
+    quotas: &'a Quotas,
+    requester: Address,
+    /// The acceptance time and cycle limit of the request.
+    request: (u64, u64),
+    committed: bool,
+}
+
+impl QuotaReservation<'_> {
+    /// Keep counting the request, once it is queued and counted as active by the database.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for QuotaReservation<'_> {
+    fn drop(&mut self) {
+        let mut recent = self.quotas.recent.lock().unwrap();
+        let Some(requests) = recent.get_mut(&self.requester) else {
+            return;
+        };
+
+        requests.pending = requests.pending.saturating_sub(1);
+        if !self.committed
+            && let Some(index) = requests
+                .accepted
+                .iter()
+                .rposition(|request| *request == self.request)
+        {
+            requests.accepted.remove(index);
+        }
+    }
+}
+
+/// Reload the quotas whenever the file changes. An invalid file is ignored, keeping the previous
+/// quotas.
+pub async fn watch_quotas(path: PathBuf, quotas: Arc<Quotas>) {
+    watch_file(path, |path| match QuotaConfig::load(path) {
+        Ok(config) => {
+            tracing::info!("Reloaded the quotas");
+            quotas.replace(config);
+        }
+        Err(err) => tracing::error!("Failed to reload the quotas: {err:#}"),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTAS: &str = r#"
+        [default]
+        requests_per_minute = 2
+        max_active_requests = 3
+        max_stdin_bytes = 100
+        max_cycles_per_day = 1000
+
+        [requesters."0x0202020202020202020202020202020202020202"]
+        requests_per_minute = 10
+    "#;
+
+    #[test]
+    fn test_quotas() {
+        let quotas = Quotas::new(QuotaConfig::parse(QUOTAS).unwrap());
+        let requester = Address::repeat_byte(1);
+        let partner = Address::repeat_byte(2);
+        let idle = StoredUsage::default();
+
+        // The overrides fall back to the default limits.
+        assert_eq!(quotas.limits(&partner).requests_per_minute, Some(10));
+        assert_eq!(quotas.limits(&partner).max_stdin_bytes, Some(100));
+
+        // Reserve a request, and keep it counted as if it was queued.
+        let submit = |requester: &Address, cycles: u64, at: u64| {
+            quotas
+                .reserve_request_at(requester, &idle, cycles, at)?
+                .commit();
+            Ok::<_, QuotaExceeded>(())
+        };
+
+        let now = 1_000_000;
+        assert!(submit(&requester, 100, now).is_ok());
+        assert!(submit(&requester, 100, now + 10).is_ok());
+        let exceeded = submit(&requester, 100, now + 20).unwrap_err();
+        assert_eq!(exceeded.retry_after, Some(Duration::from_secs(40)));
+        assert!(submit(&partner, 100, now + 20).is_ok());
+
+        // The rejected requests are not counted.
+        assert!(submit(&requester, 100, now + 60).is_ok());
+        let exceeded = submit(&requester, 800, now + 120).unwrap_err();
+        assert_eq!(exceeded.retry_after, Some(Duration::from_secs(DAY - 120)));
+        assert!(submit(&requester, 700, now + 120).is_ok());
+
+        // A request over the daily quota on its own can never be accepted.
+        let exceeded = submit(&partner, 1001, now + 120).unwrap_err();
+        assert_eq!(exceeded.retry_after, None);
+
+        // The reservations dropped without commit, as the request failed to be queued, are not
+        // counted.
+        let failed = Address::repeat_byte(3);
+        for _ in 0..3 {
+            assert!(quotas.reserve_request_at(&failed, &idle, 100, now).is_ok());
+        }
+
+        let busy = StoredUsage {
+            active_requests: 3,
+            stdin_bytes: 100,
+        };
+        let exceeded = quotas
+            .reserve_request_at(&partner, &busy, 0, now + 120)
+            .unwrap_err();
+        assert_eq!(exceeded.retry_after, Some(HELD_RETRY_AFTER));
+        assert!(quotas.check_stdin(&partner, &busy).is_err());
+        assert_eq!(quotas.remaining_stdin_bytes(&partner, &idle), Some(100));
+    }
+
+    #[test]
+    fn test_concurrent_requests_are_reserved() {
+        let quotas = Quotas::new(QuotaConfig::parse(QUOTAS).unwrap());
+        let requester = Address::repeat_byte(1);
+        let idle = StoredUsage::default();
+        let now = 1_000_000;
+
+        // A burst of requests, all submitted before any is queued, fits in the quota.
+        let reservations = std::thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|_| scope.spawn(|| quotas.reserve_request_at(&requester, &idle, 100, now)))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().unwrap().ok())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(reservations.len(), 2);
+
+        // The reservations being submitted count as active requests.
+        let config = "[default]\nmax_active_requests = 1";
+        let held = Quotas::new(QuotaConfig::parse(config).unwrap());
+        let reservation = held.reserve_request_at(&requester, &idle, 0, now).unwrap();
+        assert!(held.reserve_request_at(&requester, &idle, 0, now).is_err());
+        drop(reservation);
+        assert!(held.reserve_request_at(&requester, &idle, 0, now).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_quotas() {
+        assert!(QuotaConfig::parse("[default]\nmax_requests = 1").is_err());
+        assert!(QuotaConfig::parse("[requesters.\"0x01\"]\nmax_stdin_bytes = 1").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use rcgen::{CertificateParams, CustomExtension, KeyPair};
+use rustls::{ServerConfig, pki_types::PrivateKeyDer};
+use sp1_tee_private_utils::ra_tls::{RA_TLS_TAG, TDX_QUOTE_OID, public_key_report_data};
+
+use crate::attestation::Attester;
+
+/// Build the TLS configuration of the server, with a self-signed certificate carrying a TDX quote
+/// that commits to its key, see [`sp1_tee_private_utils::ra_tls`]. The key is generated at
+/// startup and never leaves the enclave.
+pub async fn server_config(attester: &Attester, hostname: &str) -> Result<ServerConfig> {
+    let key_pair = KeyPair::generate()?;
+    let attestation = attester
+        .quote(public_key_report_data(
+            RA_TLS_TAG,
+            &key_pair.public_key_der(),
+        ))
+        .await?;
+
+    let mut params = CertificateParams::new(vec![host(hostname).to_string()])?;
+    params
+        .custom_extensions
+        .push(CustomExtension::from_oid_content(
+            TDX_QUOTE_OID,
+            attestation.quote,
+        ));
+    let certificate = params.self_signed(&key_pair)?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![certificate.der().clone()],
+            PrivateKeyDer::Pkcs8(key_pair.serialize_der().into()),
+        )
+        .context("invalid RA-TLS certificate")?;
+    // The gRPC services need HTTP/2.
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// The host of the server public host name, which may include a scheme and a port.
+fn host(hostname: &str) -> &str {
+    let host = hostname
+        .split_once("://")
+        .map_or(hostname, |(_, host)| host);
+
+    host.split(['/', ':']).next().unwrap_or(host)
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use alloy_primitives::{Address, B256};
+use prost::Message;
+use sp1_tee_private_types::{
+    AdminProofRequest, AdminProofRequestId, AuditEntry, DispatchStatus, FulfillerKeyStatus,
+    GetAuditLogRequest, GetAuditLogResponse, KeyRotationStatus, ListProofRequestsResponse,
+    SetPriorityRequest, StdinStats, admin_server::Admin,
+};
+use sp1_tee_private_utils::{
+    ADMIN_SIGNATURE_HEADER, ADMIN_TIMESTAMP_HEADER, admin_message, recover_signer,
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    control::DispatchControl,
+    db::{AdminAction, Db, ProofRequestState, RequestInfo, now},
+    fulfillers::ActiveFulfillers,
+    server::DefaultPrivateProverServer,
+};
+
+/// How far the timestamp of a signed admin request can be from the server clock.
+const ADMIN_SIGNATURE_VALIDITY: Duration = Duration::from_secs(60);
+
+/// The maximum number of audit log entries returned at once.
+const MAX_AUDIT_LOG_ENTRIES: usize = 1000;
+
+/// The number of rejected calls kept in memory, the oldest are dropped first.
+const MAX_RECENT_REJECTIONS: usize = 100;
+
+/// Lets the admins inspect and control the queue. Every call is signed by an admin key, see
+/// [`sp1_tee_private_utils::admin_request`], and recorded in the audit log. The rejected calls
+/// are only counted, and the most recent kept in memory, as anyone can make them.
+pub struct DefaultAdminServer<DB: Db> {
+    prover: DefaultPrivateProverServer<DB>,
+    control: Arc<DispatchControl>,
+    fulfillers: Arc<ActiveFulfillers>,
+    admins: HashSet<Address>,
+    /// The signatures accepted within [`ADMIN_SIGNATURE_VALIDITY`], so none is accepted twice.
+    seen: Mutex<HashSet<SeenSignature>>,
+    rejections: Mutex<Rejections>,
+    db: Arc<DB>,
+}
+
+/// The admin calls rejected since the server started.
+#[derive(Debug, Default)]
+struct Rejections {
+    count: u64,
+    /// The most recent ones, up to [`MAX_RECENT_REJECTIONS`].
+    recent: VecDeque<AdminAction>,
+}
+
+/// An accepted admin signature. Only the `r` value of the signature is kept: unlike `s`, it can
+/// not be altered to get another valid signature of the same message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SeenSignature {
+    admin: Address,
+    timestamp: u64,
+    r: B256,
+}
+
+impl<DB: Db> DefaultAdminServer<DB> {
+    pub fn new(
+        prover: DefaultPrivateProverServer<DB>,
+        control: Arc<DispatchControl>,
+        fulfillers: Arc<ActiveFulfillers>,
+        admins: HashSet<Address>,
+        db: Arc<DB>,
+    ) -> Self {
+        Self {
+            prover,
+            control,
+            fulfillers,
+            admins,
+            seen: Mutex::default(),
+            rejections: Mutex::default(),
+            db,
+        }
+    }
+
+    /// Recover the admin key that signed the request to `method`, and count the call as
+    /// rejected if it was not validly signed by an admin.
+    fn authorize<T: Message>(&self, request: &Request<T>, method: &str) -> Result<Address, Status> {
+        self.verify(request, method).map_err(|(signer, status)| {
+            self.reject(signer, method, &status);
+            status
+        })
+    }
+
+    /// Count a rejected call, and keep it among the most recent ones.
+    fn reject(&self, signer: Option<Address>, method: &str, status: &Status) {
+        tracing::debug!(?signer, method, "Rejected admin call: {}", status.message());
+
+        let mut rejections = self.rejections.lock().unwrap();
+        rejections.count += 1;
+
+        if rejections.recent.len() == MAX_RECENT_REJECTIONS {
+            rejections.recent.pop_front();
+        }
+        rejections.recent.push_back(AdminAction {
+            timestamp: now(),
+            admin: signer.unwrap_or_default(),
+            action: method.to_string(),
+            details: String::new(),
+            outcome: format!("{:?}: {}", status.code(), status.message()),
+        });
+    }
+
+    /// Check the admin signature of the request to `method`, returning the recovered signer along
+    /// with the error if the call was validly signed.
+    fn verify<T: Message>(
+        &self,
+        request: &Request<T>,
+        method: &str,
+    ) -> Result<Address, (Option<Address>, Status)> {
+        let header = |name: &str| {
+            request
+                .metadata()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+
+        let (Some(timestamp), Some(signature)) = (
+            header(ADMIN_TIMESTAMP_HEADER).and_then(|t| t.parse::<u64>().ok()),
+            header(ADMIN_SIGNATURE_HEADER)
+                .and_then(|signature| hex::decode(signature.trim_start_matches("0x")).ok()),
+        ) else {
+            return Err((None, Status::unauthenticated("Missing admin signature")));
+        };
+
+        let now = now();
+        if now.abs_diff(timestamp) > ADMIN_SIGNATURE_VALIDITY.as_secs() {
+            return Err((None, Status::unauthenticated("Expired admin signature")));
+        }
+
+        let message = admin_message(method, timestamp, &request.get_ref().encode_to_vec());
+        let admin = recover_signer(&message, &signature)
+            .map_err(|_| (None, Status::unauthenticated("Invalid signature")))?;
+
+        if !self.admins.contains(&admin) {
+            return Err((Some(admin), Status::permission_denied("Not an admin key")));
+        }
+
+        let seen = SeenSignature {
+            admin,
+            timestamp,
+            r: B256::from_slice(&signature[..32]),
+        };
+        let mut signatures = self.seen.lock().unwrap();
+        signatures
+            .retain(|seen| now.abs_diff(seen.timestamp) <= ADMIN_SIGNATURE_VALIDITY.as_secs());
+        if !signatures.insert(seen) {
+            return Err((
+                Some(admin),
+                Status::unauthenticated("Admin signature already used"),
+            ));
+        }
+
+        Ok(admin)
+    }
+
+    /// Record an admin action and its outcome in the audit log.
+    async fn audit<T>(
+        &self,
+        admin: Address,
+        action: &str,
+        details: impl Display,
+        result: &Result<T, Status>,
+    ) {
+        let action = AdminAction {
+            timestamp: now(),
+            admin,
+            action: action.to_string(),
+            details: details.to_string(),
+            outcome: match result {
+                Ok(_) => "ok".to_string(),
+                Err(status) => format!("{:?}: {}", status.code(), status.message()),
+            },
+        };
+
+        tracing::info!(
+            %admin,
+            action = action.action,
+            details = action.details,
+            outcome = action.outcome,
+            "Admin action"
+        );
+
+        if let Err(err) = self.db.record_admin_action(action).await {
+            tracing::error!("Failed to record the admin action: {err}");
+        }
+    }
+
+    async fn status(&self) -> Result<DispatchStatus, Status> {
+        Ok(DispatchStatus {
+            paused: self.control.is_paused(),
+            draining: self.control.is_draining(),
+            queued: self.db.queued_proof_request_count().await? as u64,
+            leased: self.db.leased_proof_request_count().await? as u64,
+        })
+    }
+
+    /// The active fulfiller addresses, and the retired ones still assigned to active requests,
+    /// with the number of requests assigned to each.
+    async fn key_rotation_status(&self) -> Result<KeyRotationStatus, Status> {
+        let mut keys = self
+            .fulfillers
+            .addresses()
+            .into_iter()
+            .map(|address| {
+                let status = FulfillerKeyStatus {
+                    address: address.to_vec(),
+                    active: true,
+                    ..Default::default()
+                };
+                (address, status)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for info in self.db.active_requests().await? {
+            let Some(fulfiller) = info
+                .proof_request
+                .fulfiller
+                .as_deref()
+                .and_then(|fulfiller| Address::try_from(fulfiller).ok())
+            else {
+                continue;
+            };
+
+            let status = keys.entry(fulfiller).or_insert_with(|| FulfillerKeyStatus {
+                address: fulfiller.to_vec(),
+                active: false,
+                ..Default::default()
+            });
+
+            match info.state {
+                ProofRequestState::Queued => status.queued += 1,
+                ProofRequestState::Leased => status.leased += 1,
+                _ => {}
+            }
+        }
+
+        Ok(KeyRotationStatus {
+            keys: keys.into_values().collect(),
+        })
+    }
+}
+
+/// The metadata of a proof request, leaving its stdin out.
+fn admin_proof_request(info: RequestInfo) -> AdminProofRequest {
+    AdminProofRequest {
+        request_id: info.proof_request.request_id,
+        requester: info.requester.to_vec(),
+        state: info.state.as_str().to_string(),
+        priority: info.priority,
+        vk_hash: info.proof_request.vk_hash,
+        mode: info.proof_request.mode,
+        deadline: info.proof_request.deadline,
+        cycle_limit: info.proof_request.cycle_limit,
+        gas_limit: info.proof_request.gas_limit,
+        queued_at: info.queued_at,
+        lease_expires_at: info.lease_expires_at.unwrap_or_default(),
+        attempts: info.attempts,
+    }
+}
+
+fn audit_entry(action: AdminAction) -> AuditEntry {
+    AuditEntry {
+        timestamp: action.timestamp,
+        admin: action.admin.to_vec(),
+        action: action.action,
+        details: action.details,
+        outcome: action.outcome,
+    }
+}
+
+fn request_id(request_id: &[u8]) -> Result<B256, Status> {
+    B256::try_from(request_id).map_err(|_| Status::invalid_argument("Invalid request ID"))
+}
+
+#[tonic::async_trait]
+impl<DB: Db> Admin for DefaultAdminServer<DB> {
+    async fn list_proof_requests(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<ListProofRequestsResponse>, Status> {
+        let admin = self.authorize(&request, "ListProofRequests")?;
+
+        let result = self.db.active_requests().await.map_err(Status::from);
+        self.audit(admin, "ListProofRequests", "", &result).await;
+
+        Ok(Response::new(ListProofRequestsResponse {
+            requests: result?.into_iter().map(admin_proof_request).collect(),
+        }))
+    }
+
+    async fn get_proof_request(
+        &self,
+        request: Request<AdminProofRequestId>,
+    ) -> Result<Response<AdminProofRequest>, Status> {
+        let admin = self.authorize(&request, "GetProofRequest")?;
+        let request_id = request_id(&request.get_ref().request_id)?;
+
+        let result = match self.db.request_info(request_id.as_slice()).await {
+            Ok(Some(info)) => Ok(admin_proof_request(info)),
+            Ok(None) => Err(Status::not_found("Unknown proof request")),
+            Err(err) => Err(err.into()),
+        };
+        self.audit(admin, "GetProofRequest", request_id, &result)
+            .await;
+
+        result.map(Response::new)
+    }
+
+    async fn set_priority(
+        &self,
+        request: Request<SetPriorityRequest>,
+    ) -> Result<Response<()>, Status> {
+        let admin = self.authorize(&request, "SetPriority")?;
+        let priority = request.get_ref().priority;
+        let request_id = request_id(&request.get_ref().request_id)?;
+
+        let result = match self.db.set_priority(request_id.as_slice(), priority).await {
+            Ok(true) => {
+                self.prover.notify_dispatch();
+                Ok(())
+            }
+            Ok(false) => Err(Status::failed_precondition(
+                "The proof request is no longer queued nor leased",
+            )),
+            Err(err) => Err(err.into()),
+        };
+        self.audit(
+            admin,
+            "SetPriority",
+            format!("{request_id} priority={priority}"),
+            &result,
+        )
+        .await;
+
+        result.map(Response::new)
+    }
+
+    async fn cancel_proof_request(
+        &self,
+        request: Request<AdminProofRequestId>,
+    ) -> Result<Response<()>, Status> {
+        let admin = self.authorize(&request, "CancelProofRequest")?;
+        let request_id = request_id(&request.get_ref().request_id)?;
+
+        let result = self.prover.cancel(request_id.as_slice()).await;
+        self.audit(admin, "CancelProofRequest", request_id, &result)
+            .await;
+
+        result.map(Response::new)
+    }
+
+    async fn pause(&self, request: Request<()>) -> Result<Response<DispatchStatus>, Status> {
+        let admin = self.authorize(&request, "Pause")?;
+
+        self.control.pause();
+        let result = self.status().await;
+        self.audit(admin, "Pause", "", &result).await;
+
+        result.map(Response::new)
+    }
+
+    async fn resume(&self, request: Request<()>) -> Result<Response<DispatchStatus>, Status> {
+        let admin = self.authorize(&request, "Resume")?;
+
+        self.control.resume();
+        self.prover.notify_dispatch();
+        let result = self.status().await;
+        self.audit(admin, "Resume", "", &result).await;
+
+        result.map(Response::new)
+    }
+
+    async fn drain(&self, request: Request<()>) -> Result<Response<DispatchStatus>, Status> {
+        let admin = self.authorize(&request, "Drain")?;
+
+        self.control.drain();
+        let result = self.status().await;
+        self.audit(admin, "Drain", "", &result).await;
+
+        result.map(Response::new)
+    }
+
+    async fn get_status(&self, request: Request<()>) -> Result<Response<DispatchStatus>, Status> {
+        let admin = self.authorize(&request, "GetStatus")?;
+
+        let result = self.status().await;
+        self.audit(admin, "GetStatus", "", &result).await;
+
+        result.map(Response::new)
+    }
+
+    async fn get_audit_log(
+        &self,
+        request: Request<GetAuditLogRequest>,
+    ) -> Result<Response<GetAuditLogResponse>, Status> {
+        let admin = self.authorize(&request, "GetAuditLog")?;
+        let limit = match request.get_ref().limit as usize {
+            0 => MAX_AUDIT_LOG_ENTRIES,
+            limit => limit.min(MAX_AUDIT_LOG_ENTRIES),
+        };
+
+        let result = self.db.admin_actions(limit).await.map_err(Status::from);
+        self.audit(admin, "GetAuditLog", format!("limit={limit}"), &result)
+            .await;
+
+        let entries = result?.into_iter().map(audit_entry).collect();
+        let rejections = self.rejections.lock().unwrap();
+
+        Ok(Response::new(GetAuditLogResponse {
+            entries,
+            rejected_calls: rejections.count,
+            recent_rejections: rejections
+                .recent
+                .iter()
+                .rev()
+                .cloned()
+                .map(audit_entry)
+                .collect(),
+        }))
+    }
+
+    async fn get_key_rotation_status(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<KeyRotationStatus>, Status> {
+        let admin = self.authorize(&request, "GetKeyRotationStatus")?;
+
+        let result = self.key_rotation_status().await;
+        self.audit(admin, "GetKeyRotationStatus", "", &result).await;
+
+        result.map(Response::new)
+    }
+
+    async fn get_stdin_stats(&self, request: Request<()>) -> Result<Response<StdinStats>, Status> {
+        let admin = self.authorize(&request, "GetStdinStats")?;
+
+        let result = self
+            .db
+            .stdin_stats()
+            .await
+            .map(|stats| StdinStats {
+                count: stats.count as u64,
+                total_bytes: stats.total_bytes,
+                claimed: stats.claimed as u64,
+                spilled: stats.spilled as u64,
+            })
+            .map_err(Status::from);
+        self.audit(admin, "GetStdinStats", "", &result).await;
+
+        result.map(Response::new)
+    }
+}
@@ -5,22 +5,37 @@ use sp1_sdk::network::proto::artifact::{
     ArtifactType, CreateArtifactRequest, CreateArtifactResponse,
     artifact_store_client::ArtifactStoreClient, artifact_store_server::ArtifactStore,
 };
-use sp1_tee_private_utils::{configure_endpoint, generate_id, presigned_url};
+use sp1_tee_private_utils::{
+    artifact_uri, configure_endpoint, generate_id, presigned_url, recover_signer,
+};
 use tonic::{Request, Response, Status, transport::Channel};
 
-use crate::db::Db;
+use crate::{db::Db, policy::AccessPolicy, quota::Quotas, url_signer::UrlSigner};
 
 pub struct DefaultArtifactStoreServer<DB: Db> {
     hostname: String,
     network_rpc_url: String,
+    url_signer: Arc<UrlSigner>,
+    policy: Arc<AccessPolicy>,
+    quotas: Arc<Quotas>,
     db: Arc<DB>,
 }
 
 impl<DB: Db> DefaultArtifactStoreServer<DB> {
-    pub async fn new(hostname: String, network_rpc_url: String, db: Arc<DB>) -> Self {
+    pub async fn new(
+        hostname: String,
+        network_rpc_url: String,
+        url_signer: Arc<UrlSigner>,
+        policy: Arc<AccessPolicy>,
+        quotas: Arc<Quotas>,
+        db: Arc<DB>,
+    ) -> Self {
         Self {
             hostname,
             network_rpc_url,
+            url_signer,
+            policy,
+            quotas,
             db,
         }
     }
@@ -43,6 +58,11 @@ impl<DB: Db> ArtifactStore for DefaultArtifactStoreServer<DB> {
         let artifact_type = ArtifactType::try_from(request.artifact_type)
             .unwrap_or(ArtifactType::UnspecifiedArtifactType);
 
+        // The SDK signs the artifact creation with the requester key.
+        let requester = recover_signer(b"create_artifact", &request.signature)
+            .map_err(|_| Status::unauthenticated("Invalid signature"))?;
+        self.policy.current().check_requester(&requester)?;
+
         match artifact_type {
             ArtifactType::Program => {
                 let mut artifact_store = self.artifact_store_client().await.unwrap();
@@ -50,16 +70,24 @@ impl<DB: Db> ArtifactStore for DefaultArtifactStoreServer<DB> {
                 artifact_store.create_artifact(request).await
             }
             ArtifactType::Stdin => {
+                let usage = self.db.requester_usage(&requester).await?;
+                self.quotas.check_stdin(&requester, &usage)?;
+
                 let id = generate_id();
+                let (token, expires_at) = self.url_signer.sign(&id);
                 let artifact_presigned_url =
-                    presigned_url(&self.hostname, ArtifactType::Stdin, &id);
+                    presigned_url(&self.hostname, ArtifactType::Stdin, &id, &token);
 
-                tracing::info!("created presigned url: {}", artifact_presigned_url);
+                tracing::info!(
+                    "created presigned url for {id}, owned by {requester}, expiring at {expires_at}"
+                );
 
-                self.db.insert_artifact_request(id).await;
+                self.db
+                    .insert_artifact_request(id.clone(), requester, expires_at)
+                    .await?;
 
                 Ok(Response::new(CreateArtifactResponse {
-                    artifact_uri: artifact_presigned_url.clone(),
+                    artifact_uri: artifact_uri(&self.hostname, ArtifactType::Stdin, &id),
                     artifact_presigned_url,
                 }))
             }
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use sp1_tee_private_types::{GetAttestationRequest, GetAttestationResponse, attestation_server};
+use tonic::{Request, Response, Status};
+
+use crate::attestation::Attester;
+
+pub struct DefaultAttestationServer {
+    attester: Arc<Attester>,
+}
+
+impl DefaultAttestationServer {
+    pub fn new(attester: Arc<Attester>) -> Self {
+        Self { attester }
+    }
+}
+
+#[tonic::async_trait]
+impl attestation_server::Attestation for DefaultAttestationServer {
+    async fn get_attestation(
+        &self,
+        request: Request<GetAttestationRequest>,
+    ) -> Result<Response<GetAttestationResponse>, Status> {
+        let request = request.into_inner();
+        let attestation = self
+            .attester
+            .attest(&request.nonce, &request.report_data)
+            .await?;
+
+        Ok(Response::new(GetAttestationResponse {
+            quote: attestation.quote,
+            event_log: attestation.event_log,
+            report_data: attestation.report_data.to_vec(),
+            fulfiller_addresses: attestation
+                .fulfiller_addresses
+                .iter()
+                .map(|address| address.to_vec())
+                .collect(),
+        }))
+    }
+}
@@ -1,3 +1,9 @@
+mod admin;
+pub use admin::DefaultAdminServer;
+
+mod attestation;
+pub use attestation::DefaultAttestationServer;
+
 mod artifact_store;
 pub use artifact_store::DefaultArtifactStoreServer;
 
@@ -1,53 +1,218 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
 
 use alloy_primitives::{Address, B256};
 use anyhow::Result;
-use sp1_sdk::{
-    NetworkSigner,
-    network::proto::base_types::{
+use async_stream::try_stream;
+use futures::Stream;
+use sp1_sdk::network::proto::{
+    artifact::ArtifactType,
+    base_types::{
         CreateProgramRequest, CreateProgramResponse, GetNonceRequest, GetNonceResponse,
         GetProgramRequest, GetProgramResponse, GetProofRequestDetailsRequest,
-        GetProofRequestStatusRequest, GetProofRequestStatusResponse, ProofRequest,
-        RequestProofRequest, RequestProofResponse, RequestProofResponseBody,
+        GetProofRequestStatusRequest, GetProofRequestStatusResponse, MessageFormat,
+        RequestProofRequest, RequestProofRequestBody, RequestProofResponse,
+        RequestProofResponseBody,
     },
 };
-use sp1_tee_private_types::prover_network_server::ProverNetwork;
-use sp1_tee_private_utils::prover_network_client;
+use sp1_tee_private_types::{
+    CancelProofRequestRequest, LeaseHandle, NackProofRequestRequest, ProofRequestLease,
+    RenewLeaseResponse, SubscribeProofRequestsRequest, prover_network_server::ProverNetwork,
+};
+use sp1_tee_private_utils::{
+    Signable, artifact_uri, cancel_message, prover_network_client, recover_signer,
+};
+use tokio::{sync::Notify, time::sleep};
 use tonic::{Request, Response, Status};
 
-use crate::db::Db;
+use crate::{
+    backpressure::Backpressure,
+    control::DispatchControl,
+    db::{Db, ProofRequestState, now},
+    fulfillers::ActiveFulfillers,
+    policy::AccessPolicy,
+    quota::Quotas,
+};
+
+/// How often a subscription checks the queue when it was not notified of any change.
+const SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How far the timestamp of a cancellation can be from the server clock.
+const CANCEL_SIGNATURE_VALIDITY: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone)]
 pub struct DefaultPrivateProverServer<DB: Db> {
     hostname: String,
     network_rpc_url: String,
-    fulfiller_address: Address,
-    artifacts_port: u16,
+    fulfillers: Arc<ActiveFulfillers>,
+    policy: Arc<AccessPolicy>,
+    quotas: Arc<Quotas>,
+    backpressure: Arc<Backpressure>,
+    control: Arc<DispatchControl>,
+    /// The base URL the fulfiller downloads the stdins from.
+    artifacts_url: String,
+    /// Whether this instance serves the internal channel, the only one where the fulfiller
+    /// methods are allowed.
+    internal: bool,
+    lease_duration: Duration,
+    priority_classes: HashMap<Address, u32>,
+    /// The addresses allowed to cancel any proof request.
+    admins: HashSet<Address>,
+    dispatch: Arc<Notify>,
     db: Arc<DB>,
 }
 
 impl<DB: Db> DefaultPrivateProverServer<DB> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         hostname: String,
         network_rpc_url: String,
-        fulfiller_private_key: String,
-        artifacts_port: u16,
+        fulfillers: Arc<ActiveFulfillers>,
+        policy: Arc<AccessPolicy>,
+        quotas: Arc<Quotas>,
+        backpressure: Arc<Backpressure>,
+        control: Arc<DispatchControl>,
+        artifacts_url: String,
+        lease_duration: Duration,
+        priority_classes: HashMap<Address, u32>,
+        admins: HashSet<Address>,
+        dispatch: Arc<Notify>,
         db: Arc<DB>,
     ) -> Self {
-        let fulfiller_signer = NetworkSigner::local(&fulfiller_private_key).unwrap();
-
         Self {
             hostname,
             network_rpc_url,
-            fulfiller_address: fulfiller_signer.address(),
-            artifacts_port,
+            fulfillers,
+            policy,
+            quotas,
+            backpressure,
+            control,
+            artifacts_url,
+            internal: false,
+            lease_duration,
+            priority_classes,
+            admins,
+            dispatch,
             db,
         }
     }
+
+    /// The same server, serving the internal channel authenticated by the fulfiller.
+    pub fn internal(&self) -> Self {
+        Self {
+            internal: true,
+            ..self.clone()
+        }
+    }
+
+    /// Reject the fulfiller methods outside of the internal channel.
+    fn check_internal(&self) -> Result<(), Status> {
+        if self.internal {
+            Ok(())
+        } else {
+            Err(Status::permission_denied(
+                "Only the fulfiller can call this method, over the internal channel",
+            ))
+        }
+    }
+
+    /// Wake up the subscriptions, to hand out the queued requests.
+    pub fn notify_dispatch(&self) {
+        self.dispatch.notify_waiters();
+    }
+
+    /// Cancel a queued or leased proof request, and delete its stdin.
+    ///
+    /// A queued request is handed to a fulfiller worker, which marks it as unfulfillable on the
+    /// network, as only the fulfiller holds the fulfiller keys. The cancellation is kept in the
+    /// database until the worker acks it, see [`Db::lease_cancellation`]. The worker proving a
+    /// leased request learns of the cancellation when renewing its lease, then aborts and marks
+    /// it as unfulfillable.
+    pub async fn cancel(&self, request_id: &[u8]) -> Result<(), Status> {
+        let cancelled = self.db.cancel_request(request_id).await?.ok_or_else(|| {
+            Status::failed_precondition("The proof request is no longer queued nor leased")
+        })?;
+
+        let request_id = B256::from_slice(request_id);
+        tracing::info!(?request_id, "Cancelled proof request");
+
+        match cancelled.lease_id {
+            Some(lease_id) => self.backpressure.released(&lease_id),
+            None => self.notify_dispatch(),
+        }
+
+        Ok(())
+    }
+
+    /// The priority class of a requester, 0 if not configured.
+    fn priority(&self, requester: &Address) -> u32 {
+        self.priority_classes
+            .get(requester)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Recover the requester from the signature of a proof request, the same way the network
+    /// does, and return it with the request body.
+    fn verify_requester(
+        request: &RequestProofRequest,
+    ) -> Result<(Address, &RequestProofRequestBody), Status> {
+        if request.format != MessageFormat::Binary as i32 {
+            return Err(Status::invalid_argument(
+                "Only the binary message format is supported",
+            ));
+        }
+
+        let body = request
+            .body
+            .as_ref()
+            .ok_or_else(|| Status::invalid_argument("missing request body"))?;
+
+        let requester = body
+            .recover_signer(&request.signature)
+            .map_err(|_| Status::unauthenticated("Invalid signature"))?;
+
+        Ok((requester, body))
+    }
+
+    /// Check that the stdin of a proof request was uploaded to this server by the requester, and
+    /// return its artifact ID.
+    async fn check_stdin(
+        &self,
+        body: &RequestProofRequestBody,
+        requester: Address,
+    ) -> Result<String, Status> {
+        let stdin_id = body
+            .stdin_uri
+            .strip_prefix(&artifact_uri(&self.hostname, ArtifactType::Stdin, ""))
+            .ok_or_else(|| {
+                Status::invalid_argument(
+                    "The stdin URI does not refer to an artifact of this server",
+                )
+            })?;
+
+        match self.db.stdin_owner(stdin_id).await? {
+            Some(owner) if owner == requester => Ok(stdin_id.to_string()),
+            Some(_) => {
+                tracing::warn!(%requester, stdin_id, "Proof request with another requester stdin");
+                Err(Status::permission_denied(
+                    "The stdin was not uploaded by the requester",
+                ))
+            }
+            None => Err(Status::failed_precondition("The stdin was not uploaded")),
+        }
+    }
 }
 
 #[tonic::async_trait]
 impl<DB: Db> ProverNetwork for DefaultPrivateProverServer<DB> {
+    type SubscribeProofRequestsStream =
+        Pin<Box<dyn Stream<Item = Result<ProofRequestLease, Status>> + Send + 'static>>;
+
     /// Proxy CreateProgram requests to the prover network, as the programs need to be registered in order to be able
     /// to send proof request to the prover network.
     async fn create_program(
@@ -92,12 +257,33 @@ impl<DB: Db> ProverNetwork for DefaultPrivateProverServer<DB> {
     /// Also inserts them to a queue to be executed and proved by the enclave.
     /// The requests sent to the prover network are associated to a *fake* fulfiller,
     /// and their fulfillment status are updated by the enclave.
+    #[tracing::instrument(skip_all, fields(requester))]
     async fn request_proof(
         &self,
         request: Request<RequestProofRequest>,
     ) -> Result<Response<RequestProofResponse>, Status> {
         tracing::debug!("Start request proof");
         let request = request.into_inner();
+
+        // Reject the invalid signatures before any network round trip.
+        let (requester, body) = Self::verify_requester(&request)?;
+        tracing::Span::current().record("requester", tracing::field::display(requester));
+
+        self.policy.current().check_request(&requester, body)?;
+        let stdin_id = self.check_stdin(body, requester).await?;
+
+        // Count the request against the quotas until it is queued, or released if it fails.
+        let usage = self.db.requester_usage(&requester).await?;
+        let reservation = self
+            .quotas
+            .reserve_request(&requester, &usage, body.cycle_limit)?;
+
+        // Turn the request away while the network can still assign it to another prover.
+        self.control.check_accepting()?;
+        let queued = self.db.queued_proof_request_count().await?;
+        let leased = self.db.leased_proof_request_count().await?;
+        self.backpressure.check(queued, leased)?;
+
         let mut network_client = prover_network_client(&self.network_rpc_url)
             .await
             .map_err(|err| Status::internal(err.to_string()))?;
@@ -122,19 +308,21 @@ impl<DB: Db> ProverNetwork for DefaultPrivateProverServer<DB> {
                 let request_id = B256::from_slice(&proof_request.request_id);
 
                 // Override stdin URL
-                proof_request.stdin_uri = proof_request.stdin_uri.replace(
-                    &self.hostname,
-                    #[cfg(feature = "local")]
-                    &format!("http://localhost:{}", self.artifacts_port),
-                    #[cfg(not(feature = "local"))]
-                    &format!("http://server:{}", self.artifacts_port),
-                );
+                proof_request.stdin_uri = proof_request
+                    .stdin_uri
+                    .replace(&self.hostname, &self.artifacts_url);
 
                 if let Some(fulfiller) = &proof_request.fulfiller
-                    && fulfiller == self.fulfiller_address.as_slice()
+                    && self.fulfillers.contains(fulfiller)
                 {
-                    tracing::debug!(?request_id, "Insert proof request");
-                    self.db.insert_request(proof_request).await;
+                    let priority = self.priority(&requester);
+
+                    tracing::debug!(?request_id, priority, "Insert proof request");
+                    self.db
+                        .insert_request(proof_request, requester, stdin_id, priority)
+                        .await?;
+                    reservation.commit();
+                    self.dispatch.notify_waiters();
                 } else {
                     tracing::error!(
                         ?request_id,
@@ -163,15 +351,242 @@ impl<DB: Db> ProverNetwork for DefaultPrivateProverServer<DB> {
         Ok(Response::new(response))
     }
 
+    /// Lease the next queued proof request to a fulfiller worker. The worker must renew the
+    /// lease while proving, otherwise the request goes back on the queue.
     async fn take_next_proof_request(
         &self,
         _: Request<()>,
-    ) -> Result<Response<ProofRequest>, Status> {
-        self.db
-            .pop_request()
-            .await
-            .map(Response::new)
-            .ok_or_else(|| Status::not_found("No proof requests in the queue"))
+    ) -> Result<Response<ProofRequestLease>, Status> {
+        self.check_internal()?;
+
+        if self.control.is_paused() {
+            return Err(Status::unavailable("The dispatch is paused"));
+        }
+
+        let lease = self
+            .db
+            .lease_request(self.lease_duration)
+            .await?
+            .ok_or_else(|| Status::not_found("No proof requests in the queue"))?;
+
+        let request_id = B256::from_slice(&lease.proof_request.request_id);
+        tracing::debug!(?request_id, lease_id = %lease.lease_id, "Leased proof request");
+        self.backpressure.leased(&lease.lease_id);
+
+        Ok(Response::new(ProofRequestLease {
+            proof_request: Some(lease.proof_request),
+            lease_id: lease.lease_id,
+            expires_at: lease.expires_at,
+            cancelled: false,
+            last_attempt: lease.last_attempt,
+        }))
+    }
+
+    /// Push leased proof requests to a fulfiller worker as soon as they are queued, holding at
+    /// most `capacity` leases for the worker at once.
+    async fn subscribe_proof_requests(
+        &self,
+        request: Request<SubscribeProofRequestsRequest>,
+    ) -> Result<Response<Self::SubscribeProofRequestsStream>, Status> {
+        self.check_internal()?;
+
+        let capacity = request.into_inner().capacity.max(1) as usize;
+        let lease_duration = self.lease_duration;
+        let dispatch = self.dispatch.clone();
+        let backpressure = self.backpressure.clone();
+        let control = self.control.clone();
+        let db = self.db.clone();
+
+        tracing::info!(capacity, "Fulfiller worker subscribed");
+
+        let stream = try_stream! {
+            let mut in_flight: Vec<LeaseHandle> = Vec::new();
+
+            loop {
+                // Register for notifications before checking the queue, so no change is missed.
+                let notified = dispatch.notified();
+                tokio::pin!(notified);
+                notified.as_mut().enable();
+
+                // Forget the leases that were acked, nacked or have expired.
+                let mut held = Vec::with_capacity(in_flight.len());
+                for handle in in_flight.drain(..) {
+                    if db.lease_is_held(&handle.request_id, &handle.lease_id).await? {
+                        held.push(handle);
+                    }
+                }
+                in_flight = held;
+
+                // The cancellations do not count against the capacity, and are handed out again
+                // if the worker does not ack them before their lease expires.
+                if let Some(lease) = db.lease_cancellation(lease_duration).await? {
+                    yield ProofRequestLease {
+                        proof_request: Some(lease.proof_request),
+                        lease_id: lease.lease_id,
+                        expires_at: lease.expires_at,
+                        cancelled: true,
+                        last_attempt: false,
+                    };
+
+                    continue;
+                }
+
+                if in_flight.len() < capacity && !control.is_paused() {
+                    if let Some(lease) = db.lease_request(lease_duration).await? {
+                        let request_id = B256::from_slice(&lease.proof_request.request_id);
+                        tracing::debug!(
+                            ?request_id,
+                            lease_id = %lease.lease_id,
+                            "Pushing proof request"
+                        );
+                        backpressure.leased(&lease.lease_id);
+
+                        in_flight.push(LeaseHandle {
+                            request_id: lease.proof_request.request_id.clone(),
+                            lease_id: lease.lease_id.clone(),
+                        });
+
+                        yield ProofRequestLease {
+                            proof_request: Some(lease.proof_request),
+                            lease_id: lease.lease_id,
+                            expires_at: lease.expires_at,
+                            cancelled: false,
+                            last_attempt: lease.last_attempt,
+                        };
+
+                        continue;
+                    }
+                }
+
+                tokio::select! {
+                    _ = &mut notified => {}
+                    _ = sleep(SUBSCRIPTION_POLL_INTERVAL) => {}
+                }
+            }
+        };
+
+        Ok(Response::new(
+            Box::pin(stream) as Self::SubscribeProofRequestsStream
+        ))
+    }
+
+    async fn renew_lease(
+        &self,
+        request: Request<LeaseHandle>,
+    ) -> Result<Response<RenewLeaseResponse>, Status> {
+        self.check_internal()?;
+
+        let lease = request.into_inner();
+
+        if let Some(expires_at) = self
+            .db
+            .renew_lease(&lease.request_id, &lease.lease_id, self.lease_duration)
+            .await?
+        {
+            return Ok(Response::new(RenewLeaseResponse { expires_at }));
+        }
+
+        // Tell the worker to stop proving a cancelled request.
+        match self.db.request_state(&lease.request_id).await? {
+            Some(ProofRequestState::Cancelled) => {
+                Err(Status::cancelled("The proof request was cancelled"))
+            }
+            _ => Err(Status::failed_precondition("The lease is no longer held")),
+        }
+    }
+
+    async fn ack_proof_request(
+        &self,
+        request: Request<LeaseHandle>,
+    ) -> Result<Response<()>, Status> {
+        self.check_internal()?;
+
+        let lease = request.into_inner();
+
+        if self
+            .db
+            .complete_request(&lease.request_id, &lease.lease_id)
+            .await?
+        {
+            self.backpressure.completed(&lease.lease_id);
+            self.dispatch.notify_waiters();
+
+            return Ok(Response::new(()));
+        }
+
+        // The worker reported a cancellation to the network.
+        if self
+            .db
+            .ack_cancellation(&lease.request_id, &lease.lease_id)
+            .await?
+        {
+            return Ok(Response::new(()));
+        }
+
+        Err(Status::failed_precondition("The lease is no longer held"))
+    }
+
+    async fn nack_proof_request(
+        &self,
+        request: Request<NackProofRequestRequest>,
+    ) -> Result<Response<()>, Status> {
+        self.check_internal()?;
+
+        let request = request.into_inner();
+        let lease = request
+            .lease
+            .ok_or_else(|| Status::invalid_argument("missing lease"))?;
+
+        if !self
+            .db
+            .fail_request(&lease.request_id, &lease.lease_id, request.requeue)
+            .await?
+        {
+            return Err(Status::failed_precondition("The lease is no longer held"));
+        }
+
+        self.backpressure.released(&lease.lease_id);
+        self.dispatch.notify_waiters();
+
+        Ok(Response::new(()))
+    }
+
+    /// Cancel a queued or leased proof request, on behalf of its requester or an admin, see
+    /// [`DefaultPrivateProverServer::cancel`].
+    #[tracing::instrument(skip_all, fields(caller))]
+    async fn cancel_proof_request(
+        &self,
+        request: Request<CancelProofRequestRequest>,
+    ) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+        let body = request
+            .body
+            .as_ref()
+            .ok_or_else(|| Status::invalid_argument("missing request body"))?;
+
+        if now().abs_diff(body.timestamp) > CANCEL_SIGNATURE_VALIDITY.as_secs() {
+            return Err(Status::unauthenticated("Expired cancellation signature"));
+        }
+
+        let caller = recover_signer(&cancel_message(body), &request.signature)
+            .map_err(|_| Status::unauthenticated("Invalid signature"))?;
+        tracing::Span::current().record("caller", tracing::field::display(caller));
+
+        let requester = self
+            .db
+            .requester(&body.request_id)
+            .await?
+            .ok_or_else(|| Status::not_found("Unknown proof request"))?;
+
+        if caller != requester && !self.admins.contains(&caller) {
+            return Err(Status::permission_denied(
+                "Only the requester or an admin can cancel the proof request",
+            ));
+        }
+
+        self.cancel(&body.request_id).await?;
+
+        Ok(Response::new(()))
     }
 
     // Retrieve the proof request status from the enclave DB.
@@ -0,0 +1,475 @@
+use std::{io, path::PathBuf, pin::Pin};
+
+use aes_gcm::{
+    AeadCore, Aes256Gcm, Key, KeyInit, Nonce,
+    aead::{Aead, OsRng},
+};
+use anyhow::Context;
+use async_stream::try_stream;
+use axum::body::Bytes;
+use futures::Stream;
+use p256::{PublicKey, SecretKey, ecdh::diffie_hellman, elliptic_curve::sec1::ToEncodedPoint};
+use sp1_tee_private_utils::{
+    DstackClient,
+    stdin_encryption::{STDIN_CHUNK_SIZE, chunk_nonce, derive_stdin_key},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+};
+use zeroize::Zeroizing;
+
+/// The dstack KMS path of the stdin master key.
+const MASTER_KEY_PATH: &str = "sp1-tee-private-proving/stdin-master-key";
+
+/// The size of the authentication tag of an encrypted chunk.
+const TAG_SIZE: usize = 16;
+
+/// The size of the encrypted chunks of a stdin, including the authentication tag.
+const ENCRYPTED_CHUNK_SIZE: usize = STDIN_CHUNK_SIZE + TAG_SIZE;
+
+/// The size of the nonce prepended to a wrapped data key.
+const NONCE_SIZE: usize = 12;
+
+/// The key wrapping the data keys of the stdins. It is derived from the dstack KMS and never
+/// leaves the enclaves, so the stdins stored before a restart can still be read.
+pub struct MasterKey {
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        let key = Zeroizing::new(key);
+
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_slice())),
+        }
+    }
+
+    /// Derive the master key from the dstack KMS.
+    pub async fn derive(dstack: &DstackClient) -> anyhow::Result<Self> {
+        let key = dstack
+            .get_secret(MASTER_KEY_PATH, "stdin-encryption")
+            .await
+            .context("failed to derive the stdin master key")?;
+
+        Ok(Self::new(*key))
+    }
+
+    #[cfg(test)]
+    pub fn generate() -> Self {
+        Self {
+            cipher: Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng)),
+        }
+    }
+
+    /// Encrypt a data key, returning the nonce followed by the ciphertext.
+    fn wrap(&self, data_key: &[u8; 32]) -> io::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, data_key.as_slice())
+            .map_err(|_| io::Error::other("failed to wrap stdin data key"))?;
+
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    fn unwrap(&self, wrapped: &[u8]) -> io::Result<Zeroizing<[u8; 32]>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid wrapped data key");
+
+        if wrapped.len() < NONCE_SIZE {
+            return Err(invalid());
+        }
+
+        let (nonce, ciphertext) = wrapped.split_at(NONCE_SIZE);
+        let data_key = Zeroizing::new(
+            self.cipher
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| invalid())?,
+        );
+
+        Ok(Zeroizing::new(
+            data_key.as_slice().try_into().map_err(|_| invalid())?,
+        ))
+    }
+}
+
+/// The enclave key the clients encrypt their stdins to, see
+/// [`sp1_tee_private_utils::stdin_encryption`]. It is generated at startup and never leaves the
+/// enclave.
+pub struct TransportKey {
+    secret: SecretKey,
+    public_key: Vec<u8>,
+}
+
+impl TransportKey {
+    pub fn generate() -> Self {
+        let secret = SecretKey::random(&mut OsRng);
+        let public_key = secret
+            .public_key()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+
+        Self { secret, public_key }
+    }
+
+    /// The SEC1 uncompressed public key.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Derive the data key of a stdin encrypted to this key by the holder of
+    /// `ephemeral_public_key`.
+    pub fn data_key(&self, ephemeral_public_key: &[u8]) -> io::Result<Zeroizing<[u8; 32]>> {
+        let ephemeral = PublicKey::from_sec1_bytes(ephemeral_public_key).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid ephemeral public key")
+        })?;
+        let shared_secret = diffie_hellman(self.secret.to_nonzero_scalar(), ephemeral.as_affine());
+
+        Ok(derive_stdin_key(
+            shared_secret.raw_secret_bytes(),
+            ephemeral.to_encoded_point(false).as_bytes(),
+            &self.public_key,
+        ))
+    }
+}
+
+/// A stdin held by the server, encrypted with its own data key.
+///
+/// It is stored in the format of [`sp1_tee_private_utils::stdin_encryption`], without the
+/// ephemeral public key, so a stdin encrypted by the client is stored as received. The data key
+/// is only stored wrapped by the [`MasterKey`].
+#[derive(Debug, Clone)]
+pub struct Stdin {
+    data: StdinData,
+    wrapped_key: Vec<u8>,
+    len: u64,
+}
+
+/// Where the encrypted chunks of a stdin are stored.
+#[derive(Debug, Clone)]
+pub enum StdinData {
+    Memory(Bytes),
+    /// Stdins above the spill threshold are stored in a file.
+    Spilled(PathBuf),
+}
+
+impl Stdin {
+    pub fn from_parts(data: StdinData, wrapped_key: Vec<u8>, len: u64) -> Self {
+        Self {
+            data,
+            wrapped_key,
+            len,
+        }
+    }
+
+    pub fn data(&self) -> &StdinData {
+        &self.data
+    }
+
+    pub fn wrapped_key(&self) -> &[u8] {
+        &self.wrapped_key
+    }
+
+    /// The size of the plaintext.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Stream the decrypted stdin.
+    pub fn decrypt(
+        &self,
+        master_key: &MasterKey,
+    ) -> io::Result<impl Stream<Item = io::Result<Bytes>> + Send + 'static> {
+        let data_key = master_key.unwrap(&self.wrapped_key)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(data_key.as_slice()));
+        let data = self.data.clone();
+
+        Ok(try_stream! {
+            let (mut reader, total_len): (Pin<Box<dyn AsyncRead + Send>>, u64) = match data {
+                StdinData::Memory(bytes) => {
+                    let len = bytes.len() as u64;
+                    (Box::pin(io::Cursor::new(bytes)), len)
+                }
+                StdinData::Spilled(path) => {
+                    let file = File::open(&path).await?;
+                    let len = file.metadata().await?.len();
+                    (Box::pin(file), len)
+                }
+            };
+            let mut chunk = vec![0; ENCRYPTED_CHUNK_SIZE];
+            let mut offset = 0;
+            let mut index = 0;
+
+            loop {
+                let remaining = total_len - offset;
+                let last = remaining <= ENCRYPTED_CHUNK_SIZE as u64;
+                let size = remaining.min(ENCRYPTED_CHUNK_SIZE as u64) as usize;
+
+                reader.read_exact(&mut chunk[..size]).await?;
+
+                let nonce = chunk_nonce(index, last);
+                let plaintext = cipher
+                    .decrypt(Nonce::from_slice(&nonce), &chunk[..size])
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "corrupted stdin"))?;
+
+                yield Bytes::from(plaintext);
+
+                if last {
+                    break;
+                }
+
+                offset += size as u64;
+                index += 1;
+            }
+        })
+    }
+
+    /// Delete the spill file of the stdin, if any.
+    pub async fn remove(&self) {
+        if let StdinData::Spilled(path) = &self.data
+            && let Err(err) = tokio::fs::remove_file(path).await
+        {
+            tracing::warn!("Failed to remove spill file {}: {err}", path.display());
+        }
+    }
+}
+
+/// Encrypts a [`Stdin`] as it is received, keeping it in memory up to the spill threshold, and
+/// moving it to a spill file past it. The file is deleted if the writer is dropped before
+/// [`StdinWriter::finish`] is called.
+pub struct StdinWriter {
+    spill_path: PathBuf,
+    spill_threshold: u64,
+    data_key: Zeroizing<[u8; 32]>,
+    /// Encrypts the received plaintext, `None` if the stdin is received already encrypted.
+    cipher: Option<Aes256Gcm>,
+    plaintext: Zeroizing<Vec<u8>>,
+    encrypted: Vec<u8>,
+    file: Option<File>,
+    index: u64,
+    len: u64,
+    finished: bool,
+}
+
+impl StdinWriter {
+    pub fn new(spill_path: PathBuf, spill_threshold: u64) -> Self {
+        let data_key: Zeroizing<[u8; 32]> = Zeroizing::new(Aes256Gcm::generate_key(OsRng).into());
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(data_key.as_slice()));
+
+        Self::with_key(spill_path, spill_threshold, data_key, Some(cipher))
+    }
+
+    /// A writer storing as is a stdin encrypted by the client under `data_key`, without the
+    /// ephemeral public key.
+    pub fn encrypted(
+        spill_path: PathBuf,
+        spill_threshold: u64,
+        data_key: Zeroizing<[u8; 32]>,
+    ) -> Self {
+        Self::with_key(spill_path, spill_threshold, data_key, None)
+    }
+
+    fn with_key(
+        spill_path: PathBuf,
+        spill_threshold: u64,
+        data_key: Zeroizing<[u8; 32]>,
+        cipher: Option<Aes256Gcm>,
+    ) -> Self {
+        Self {
+            spill_path,
+            spill_threshold,
+            data_key,
+            cipher,
+            plaintext: Zeroizing::new(vec![]),
+            encrypted: vec![],
+            file: None,
+            index: 0,
+            len: 0,
+            finished: false,
+        }
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.len += data.len() as u64;
+
+        if self.cipher.is_none() {
+            return self.store(data).await;
+        }
+
+        self.plaintext.extend_from_slice(data);
+
+        // Only flush when more than a chunk is buffered, so the final chunk is never empty
+        // unless the whole stdin is.
+        while self.plaintext.len() > STDIN_CHUNK_SIZE {
+            let rest = Zeroizing::new(self.plaintext.split_off(STDIN_CHUNK_SIZE));
+            let chunk = std::mem::replace(&mut self.plaintext, rest);
+            self.write_chunk(&chunk, false).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn finish(mut self, master_key: &MasterKey) -> io::Result<Stdin> {
+        let len = match self.cipher {
+            Some(_) => {
+                let chunk = std::mem::take(&mut self.plaintext);
+                self.write_chunk(&chunk, true).await?;
+                self.len
+            }
+            None => plaintext_len(self.len).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "truncated encrypted stdin")
+            })?,
+        };
+
+        let data = match &mut self.file {
+            Some(file) => {
+                file.sync_all().await?;
+                StdinData::Spilled(self.spill_path.clone())
+            }
+            None => StdinData::Memory(Bytes::from(std::mem::take(&mut self.encrypted))),
+        };
+        let wrapped_key = master_key.wrap(&self.data_key)?;
+        self.finished = true;
+
+        Ok(Stdin::from_parts(data, wrapped_key, len))
+    }
+
+    async fn write_chunk(&mut self, chunk: &[u8], last: bool) -> io::Result<()> {
+        let cipher = self
+            .cipher
+            .as_ref()
+            .expect("only plaintext stdins are chunked");
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&chunk_nonce(self.index, last)), chunk)
+            .map_err(|_| io::Error::other("failed to encrypt stdin chunk"))?;
+        self.index += 1;
+
+        self.store(&ciphertext).await
+    }
+
+    /// Append encrypted chunks to the memory buffer, or to the spill file past the threshold.
+    async fn store(&mut self, ciphertext: &[u8]) -> io::Result<()> {
+        if self.file.is_none()
+            && (self.encrypted.len() + ciphertext.len()) as u64 > self.spill_threshold
+        {
+            let mut file = File::create(&self.spill_path).await?;
+            file.write_all(&std::mem::take(&mut self.encrypted)).await?;
+            self.file = Some(file);
+        }
+
+        match &mut self.file {
+            Some(file) => file.write_all(ciphertext).await,
+            None => {
+                self.encrypted.extend_from_slice(ciphertext);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The plaintext size of an encrypted stdin of `encrypted_len` bytes, or `None` if it can not
+/// be a sequence of chunks.
+fn plaintext_len(encrypted_len: u64) -> Option<u64> {
+    let chunk_size = ENCRYPTED_CHUNK_SIZE as u64;
+    let chunks = encrypted_len.div_ceil(chunk_size);
+    let last_chunk = encrypted_len - (chunks.max(1) - 1) * chunk_size;
+
+    (chunks > 0 && last_chunk >= TAG_SIZE as u64).then(|| encrypted_len - chunks * TAG_SIZE as u64)
+}
+
+impl Drop for StdinWriter {
+    fn drop(&mut self) {
+        if !self.finished && self.file.is_some() {
+            let _ = std::fs::remove_file(&self.spill_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use sp1_tee_private_utils::stdin_encryption::{PUBLIC_KEY_SIZE, encrypt_stdin};
+
+    use super::*;
+
+    async fn read(stdin: &Stdin, master_key: &MasterKey) -> Vec<u8> {
+        stdin
+            .decrypt(master_key)
+            .unwrap()
+            .try_fold(Vec::new(), |mut read, chunk| async move {
+                read.extend_from_slice(&chunk);
+                Ok(read)
+            })
+            .await
+            .unwrap()
+    }
+
+    async fn roundtrip(spill_threshold: u64) {
+        let master_key = MasterKey::generate();
+        let spill_path = std::env::temp_dir().join(format!(
+            "stdin-test-{}-{spill_threshold}.stdin",
+            std::process::id()
+        ));
+        let plaintext = (0..3 * STDIN_CHUNK_SIZE + 17)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+
+        let mut writer = StdinWriter::new(spill_path.clone(), spill_threshold);
+        for part in plaintext.chunks(1000) {
+            writer.write(part).await.unwrap();
+        }
+        let stdin = writer.finish(&master_key).await.unwrap();
+
+        // The stored stdin does not contain the plaintext.
+        let stored = match stdin.data() {
+            StdinData::Memory(bytes) => bytes.to_vec(),
+            StdinData::Spilled(path) => std::fs::read(path).unwrap(),
+        };
+        assert!(!stored.windows(64).any(|window| window == &plaintext[..64]));
+
+        assert_eq!(read(&stdin, &master_key).await, plaintext);
+
+        // The data key can only be unwrapped with the master key.
+        assert!(stdin.decrypt(&MasterKey::generate()).is_err());
+
+        stdin.remove().await;
+        assert!(!spill_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_roundtrip() {
+        roundtrip(u64::MAX).await;
+    }
+
+    #[tokio::test]
+    async fn test_spilled_roundtrip() {
+        roundtrip(STDIN_CHUNK_SIZE as u64).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_encrypted_roundtrip() {
+        let master_key = MasterKey::generate();
+        let transport_key = TransportKey::generate();
+
+        for size in [0, 17, STDIN_CHUNK_SIZE, 2 * STDIN_CHUNK_SIZE + 1] {
+            let plaintext = (0..size).map(|i| i as u8).collect::<Vec<_>>();
+            let body = encrypt_stdin(transport_key.public_key(), &plaintext).unwrap();
+            let (ephemeral_public_key, chunks) = body.split_at(PUBLIC_KEY_SIZE);
+
+            let data_key = transport_key.data_key(ephemeral_public_key).unwrap();
+            let mut writer = StdinWriter::encrypted(PathBuf::new(), u64::MAX, data_key);
+            writer.write(chunks).await.unwrap();
+            let stdin = writer.finish(&master_key).await.unwrap();
+
+            assert_eq!(stdin.len(), size as u64);
+            assert_eq!(read(&stdin, &master_key).await, plaintext);
+        }
+
+        // A truncated upload is rejected.
+        let mut writer = StdinWriter::encrypted(PathBuf::new(), u64::MAX, Zeroizing::new([0; 32]));
+        writer.write(&[0; 8]).await.unwrap();
+        assert!(writer.finish(&master_key).await.is_err());
+    }
+}
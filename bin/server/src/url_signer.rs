@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sp1_tee_private_utils::DstackClient;
+use zeroize::Zeroizing;
+
+use crate::db::now;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The dstack KMS path of the key signing the upload URLs.
+const URL_SIGNING_KEY_PATH: &str = "sp1-tee-private-proving/upload-url-key";
+
+/// Signs and verifies the tokens carried by the presigned upload URLs.
+///
+/// A token is `<expires_at>.<mac>`, where the MAC is an HMAC-SHA256 of the artifact ID and the
+/// expiry. The key is derived from the dstack KMS and never leaves the enclaves, so the URLs
+/// issued before a restart stay valid.
+pub struct UrlSigner {
+    key: Zeroizing<[u8; 32]>,
+    ttl: Duration,
+}
+
+impl UrlSigner {
+    /// Create a signer issuing tokens valid for `ttl`.
+    pub fn new(key: [u8; 32], ttl: Duration) -> Self {
+        Self {
+            key: Zeroizing::new(key),
+            ttl,
+        }
+    }
+
+    /// Create a signer whose key is derived from the dstack KMS, issuing tokens valid for `ttl`.
+    pub async fn derive(dstack: &DstackClient, ttl: Duration) -> Result<Self> {
+        let key = dstack
+            .get_secret(URL_SIGNING_KEY_PATH, "url-signing")
+            .await
+            .context("failed to derive the upload URL signing key")?;
+
+        Ok(Self::new(*key, ttl))
+    }
+
+    /// Sign an upload token for the artifact, and return it alongside its expiry.
+    pub fn sign(&self, id: &str) -> (String, u64) {
+        let expires_at = now() + self.ttl.as_secs();
+        let mac = self.mac(id, expires_at).finalize().into_bytes();
+
+        (format!("{expires_at}.{}", hex::encode(mac)), expires_at)
+    }
+
+    /// Whether the token was signed for the artifact and has not expired yet.
+    pub fn verify(&self, id: &str, token: &str) -> bool {
+        let Some((expires_at, mac)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(expires_at) = expires_at.parse::<u64>() else {
+            return false;
+        };
+        let Ok(mac) = hex::decode(mac) else {
+            return false;
+        };
+
+        expires_at > now() && self.mac(id, expires_at).verify_slice(&mac).is_ok()
+    }
+
+    fn mac(&self, id: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(self.key.as_slice()).expect("HMAC accepts any key size");
+        mac.update(id.as_bytes());
+        mac.update(&[0]);
+        mac.update(&expires_at.to_be_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_token() {
+        let signer = UrlSigner::new([1; 32], Duration::from_secs(60));
+        let (token, _) = signer.sign("artifact_1");
+
+        assert!(signer.verify("artifact_1", &token));
+        assert!(!signer.verify("artifact_2", &token));
+        assert!(!UrlSigner::new([2; 32], Duration::from_secs(60)).verify("artifact_1", &token));
+
+        // Pushing the expiry back invalidates the MAC.
+        let (_, mac) = token.split_once('.').unwrap();
+        assert!(!signer.verify("artifact_1", &format!("{}.{mac}", now() + 3600)));
+
+        let signer = UrlSigner::new([1; 32], Duration::ZERO);
+        let (expired, _) = signer.sign("artifact_1");
+        assert!(!signer.verify("artifact_1", &expired));
+    }
+}
@@ -42,12 +42,58 @@ fn main() {
                 .codec_path("tonic::codec::ProstCodec")
                 .build(),
         )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("cancel_proof_request")
+                .route_name("CancelProofRequest")
+                .input_type("crate::CancelProofRequestRequest")
+                .output_type("crate::Unit")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
         .method(
             tonic_build::manual::Method::builder()
                 .name("take_next_proof_request")
                 .route_name("TakeNextProofRequest")
                 .input_type("crate::Unit")
-                .output_type("sp1_sdk::network::proto::base_types::ProofRequest")
+                .output_type("crate::ProofRequestLease")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("subscribe_proof_requests")
+                .route_name("SubscribeProofRequests")
+                .input_type("crate::SubscribeProofRequestsRequest")
+                .output_type("crate::ProofRequestLease")
+                .codec_path("tonic::codec::ProstCodec")
+                .server_streaming()
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("renew_lease")
+                .route_name("RenewLease")
+                .input_type("crate::LeaseHandle")
+                .output_type("crate::RenewLeaseResponse")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("ack_proof_request")
+                .route_name("AckProofRequest")
+                .input_type("crate::LeaseHandle")
+                .output_type("crate::Unit")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("nack_proof_request")
+                .route_name("NackProofRequest")
+                .input_type("crate::NackProofRequestRequest")
+                .output_type("crate::Unit")
                 .codec_path("tonic::codec::ProstCodec")
                 .build(),
         )
@@ -62,5 +108,127 @@ fn main() {
         )
         .build();
 
-    tonic_build::manual::Builder::new().compile(&[network_service]);
+    let attestation_service = tonic_build::manual::Service::builder()
+        .name("Attestation")
+        .package("attestation")
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("get_attestation")
+                .route_name("GetAttestation")
+                .input_type("crate::GetAttestationRequest")
+                .output_type("crate::GetAttestationResponse")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .build();
+
+    let admin_service = tonic_build::manual::Service::builder()
+        .name("Admin")
+        .package("admin")
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("list_proof_requests")
+                .route_name("ListProofRequests")
+                .input_type("crate::Unit")
+                .output_type("crate::ListProofRequestsResponse")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("get_proof_request")
+                .route_name("GetProofRequest")
+                .input_type("crate::AdminProofRequestId")
+                .output_type("crate::AdminProofRequest")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("set_priority")
+                .route_name("SetPriority")
+                .input_type("crate::SetPriorityRequest")
+                .output_type("crate::Unit")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("cancel_proof_request")
+                .route_name("CancelProofRequest")
+                .input_type("crate::AdminProofRequestId")
+                .output_type("crate::Unit")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("pause")
+                .route_name("Pause")
+                .input_type("crate::Unit")
+                .output_type("crate::DispatchStatus")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("resume")
+                .route_name("Resume")
+                .input_type("crate::Unit")
+                .output_type("crate::DispatchStatus")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("drain")
+                .route_name("Drain")
+                .input_type("crate::Unit")
+                .output_type("crate::DispatchStatus")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("get_status")
+                .route_name("GetStatus")
+                .input_type("crate::Unit")
+                .output_type("crate::DispatchStatus")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("get_audit_log")
+                .route_name("GetAuditLog")
+                .input_type("crate::GetAuditLogRequest")
+                .output_type("crate::GetAuditLogResponse")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("get_key_rotation_status")
+                .route_name("GetKeyRotationStatus")
+                .input_type("crate::Unit")
+                .output_type("crate::KeyRotationStatus")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("get_stdin_stats")
+                .route_name("GetStdinStats")
+                .input_type("crate::Unit")
+                .output_type("crate::StdinStats")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .build();
+
+    tonic_build::manual::Builder::new().compile(&[
+        network_service,
+        attestation_service,
+        admin_service,
+    ]);
 }
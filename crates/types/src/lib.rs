@@ -1,3 +1,259 @@
+use sp1_sdk::network::proto::base_types::ProofRequest;
+
 include!(concat!(env!("OUT_DIR"), "/network.ProverNetwork.rs"));
+include!(concat!(env!("OUT_DIR"), "/attestation.Attestation.rs"));
+include!(concat!(env!("OUT_DIR"), "/admin.Admin.rs"));
 
 pub type Unit = ();
+
+/// A proof request handed out to a fulfiller worker under a lease.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProofRequestLease {
+    #[prost(message, optional, tag = "1")]
+    pub proof_request: Option<ProofRequest>,
+    #[prost(string, tag = "2")]
+    pub lease_id: String,
+    /// The unix timestamp (in seconds) after which the lease expires if not renewed.
+    #[prost(uint64, tag = "3")]
+    pub expires_at: u64,
+    /// Whether the request was cancelled while queued. The worker then only marks it as
+    /// unfulfillable on the network and acks the lease, which cannot be renewed. The cancellation
+    /// is handed out again if the lease expires first.
+    #[prost(bool, tag = "4")]
+    pub cancelled: bool,
+    /// Whether the request is failed, rather than put back on the queue, if this attempt does not
+    /// prove it. The worker then marks it as unfulfillable on the network, even after an error
+    /// that another attempt could have overcome.
+    #[prost(bool, tag = "5")]
+    pub last_attempt: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribeProofRequestsRequest {
+    /// The maximum number of leases the worker can hold at once. The server pushes a new lease
+    /// only when one of the previous ones has been acked, nacked or has expired.
+    #[prost(uint32, tag = "1")]
+    pub capacity: u32,
+}
+
+/// Identifies a lease held by a fulfiller worker.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LeaseHandle {
+    #[prost(bytes = "vec", tag = "1")]
+    pub request_id: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub lease_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RenewLeaseResponse {
+    /// The new unix timestamp (in seconds) after which the lease expires.
+    #[prost(uint64, tag = "1")]
+    pub expires_at: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NackProofRequestRequest {
+    #[prost(message, optional, tag = "1")]
+    pub lease: Option<LeaseHandle>,
+    /// Whether the request should go back to the queue, or be marked as failed.
+    #[prost(bool, tag = "2")]
+    pub requeue: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CancelProofRequestRequestBody {
+    #[prost(bytes = "vec", tag = "1")]
+    pub request_id: Vec<u8>,
+    /// The unix timestamp (in seconds) of the cancellation. The server rejects the signatures
+    /// made too long ago, so they can not be replayed later.
+    #[prost(uint64, tag = "2")]
+    pub timestamp: u64,
+}
+
+/// Cancel a queued or leased proof request. Only its requester or an admin can cancel it.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CancelProofRequestRequest {
+    /// The signature of the body, tagged with `cancel_message` from the utils crate, by the
+    /// requester or an admin key.
+    #[prost(bytes = "vec", tag = "1")]
+    pub signature: Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub body: Option<CancelProofRequestRequestBody>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetAttestationRequest {
+    /// A fresh 32-byte nonce chosen by the caller, so the quote can not be replayed.
+    #[prost(bytes = "vec", tag = "1")]
+    pub nonce: Vec<u8>,
+    /// Caller data to bind to the quote, of at most 1024 bytes.
+    #[prost(bytes = "vec", tag = "2")]
+    pub report_data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetAttestationResponse {
+    /// The TDX quote.
+    #[prost(bytes = "vec", tag = "1")]
+    pub quote: Vec<u8>,
+    /// The JSON-encoded event log, used to replay the RTMRs.
+    #[prost(string, tag = "2")]
+    pub event_log: String,
+    /// The 64-byte report data embedded in the quote: the tagged SHA-512 hash of the nonce, the
+    /// number of active fulfiller addresses, the addresses, the length of the caller report data
+    /// and the caller report data.
+    #[prost(bytes = "vec", tag = "3")]
+    pub report_data: Vec<u8>,
+    /// The addresses of the active fulfiller keys, in ascending order.
+    #[prost(bytes = "vec", repeated, tag = "4")]
+    pub fulfiller_addresses: Vec<Vec<u8>>,
+}
+
+/// The metadata of a proof request, as reported to the admins. The stdin is never reported.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AdminProofRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub request_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub requester: Vec<u8>,
+    /// One of `queued`, `leased`, `completed`, `failed`, `expired` or `cancelled`.
+    #[prost(string, tag = "3")]
+    pub state: String,
+    #[prost(uint32, tag = "4")]
+    pub priority: u32,
+    #[prost(bytes = "vec", tag = "5")]
+    pub vk_hash: Vec<u8>,
+    #[prost(int32, tag = "6")]
+    pub mode: i32,
+    #[prost(uint64, tag = "7")]
+    pub deadline: u64,
+    #[prost(uint64, tag = "8")]
+    pub cycle_limit: u64,
+    #[prost(uint64, tag = "9")]
+    pub gas_limit: u64,
+    /// The unix timestamp (in seconds) at which the request was queued.
+    #[prost(uint64, tag = "10")]
+    pub queued_at: u64,
+    /// The unix timestamp (in seconds) after which the lease expires, 0 if not leased.
+    #[prost(uint64, tag = "11")]
+    pub lease_expires_at: u64,
+    /// The number of times the request was leased.
+    #[prost(uint32, tag = "12")]
+    pub attempts: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListProofRequestsResponse {
+    /// The queued and leased proof requests, oldest first.
+    #[prost(message, repeated, tag = "1")]
+    pub requests: Vec<AdminProofRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AdminProofRequestId {
+    #[prost(bytes = "vec", tag = "1")]
+    pub request_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SetPriorityRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub request_id: Vec<u8>,
+    #[prost(uint32, tag = "2")]
+    pub priority: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DispatchStatus {
+    /// Whether the queued proof requests are held back from the fulfiller.
+    #[prost(bool, tag = "1")]
+    pub paused: bool,
+    /// Whether new proof requests are rejected, while the queued ones are still proved.
+    #[prost(bool, tag = "2")]
+    pub draining: bool,
+    #[prost(uint64, tag = "3")]
+    pub queued: u64,
+    #[prost(uint64, tag = "4")]
+    pub leased: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetAuditLogRequest {
+    /// The maximum number of entries to return, the most recent first. 0 returns as many as the
+    /// server allows.
+    #[prost(uint32, tag = "1")]
+    pub limit: u32,
+}
+
+/// An admin action, as recorded in the audit log.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AuditEntry {
+    /// The unix timestamp (in seconds) of the action.
+    #[prost(uint64, tag = "1")]
+    pub timestamp: u64,
+    /// The address of the admin key.
+    #[prost(bytes = "vec", tag = "2")]
+    pub admin: Vec<u8>,
+    /// The admin RPC called.
+    #[prost(string, tag = "3")]
+    pub action: String,
+    #[prost(string, tag = "4")]
+    pub details: String,
+    /// `ok`, or the error returned to the admin.
+    #[prost(string, tag = "5")]
+    pub outcome: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetAuditLogResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<AuditEntry>,
+    /// The number of admin calls rejected since the server started. They are not recorded in the
+    /// audit log.
+    #[prost(uint64, tag = "2")]
+    pub rejected_calls: u64,
+    /// The most recent rejected calls, the most recent first, under the recovered address or the
+    /// zero address if the call was not validly signed.
+    #[prost(message, repeated, tag = "3")]
+    pub recent_rejections: Vec<AuditEntry>,
+}
+
+/// A fulfiller address, either active or still assigned to queued or leased proof requests.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FulfillerKeyStatus {
+    #[prost(bytes = "vec", tag = "1")]
+    pub address: Vec<u8>,
+    /// Whether the server accepts new proof requests assigned to the address.
+    #[prost(bool, tag = "2")]
+    pub active: bool,
+    #[prost(uint64, tag = "3")]
+    pub queued: u64,
+    #[prost(uint64, tag = "4")]
+    pub leased: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct KeyRotationStatus {
+    /// The fulfiller addresses, in ascending order. A retired address can be removed from the
+    /// fulfiller keys once it has no queued nor leased request left.
+    #[prost(message, repeated, tag = "1")]
+    pub keys: Vec<FulfillerKeyStatus>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StdinStats {
+    /// The number of stdins held by the server.
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+    /// Their total size, in bytes.
+    #[prost(uint64, tag = "2")]
+    pub total_bytes: u64,
+    /// The number of stdins referred to by a queued or leased proof request. The others are
+    /// deleted once their retention ends.
+    #[prost(uint64, tag = "3")]
+    pub claimed: u64,
+    /// The number of stdins stored in a spill file rather than in the database or memory.
+    #[prost(uint64, tag = "4")]
+    pub spilled: u64,
+}
@@ -0,0 +1,43 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+use tonic::Request;
+
+use crate::Signer;
+
+/// The header carrying the admin signature of an admin RPC, see [`admin_message`].
+pub const ADMIN_SIGNATURE_HEADER: &str = "x-admin-signature";
+
+/// The header carrying the unix timestamp (in seconds) signed with an admin RPC.
+pub const ADMIN_TIMESTAMP_HEADER: &str = "x-admin-timestamp";
+
+/// The message an admin key signs to call the admin RPC `method` at `timestamp`, binding the
+/// encoded request.
+pub fn admin_message(method: &str, timestamp: u64, request: &[u8]) -> Vec<u8> {
+    let mut message = format!("admin:{method}:{timestamp}:").into_bytes();
+    message.extend_from_slice(request);
+    message
+}
+
+/// Build a request to the admin RPC `method`, signed by an admin key.
+pub async fn admin_request<T: Message>(
+    signer: &dyn Signer,
+    method: &str,
+    message: T,
+) -> anyhow::Result<Request<T>> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let signature = signer
+        .sign_message(&admin_message(method, timestamp, &message.encode_to_vec()))
+        .await?;
+
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(ADMIN_TIMESTAMP_HEADER, timestamp.into());
+    request.metadata_mut().insert(
+        ADMIN_SIGNATURE_HEADER,
+        hex::encode(signature.as_bytes()).parse()?,
+    );
+
+    Ok(request)
+}
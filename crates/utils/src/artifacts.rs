@@ -1,13 +1,33 @@
 use mti::prelude::{MagicTypeIdExt, V7};
 use sp1_sdk::network::proto::artifact::ArtifactType;
 
+/// The header carrying the fulfiller signature of a stdin download, see [`download_message`].
+pub const DOWNLOAD_SIGNATURE_HEADER: &str = "x-fulfiller-signature";
+
+/// The header carrying the unix timestamp (in seconds) signed with a stdin download.
+pub const DOWNLOAD_TIMESTAMP_HEADER: &str = "x-fulfiller-timestamp";
+
 pub fn generate_id() -> String {
     // Create a TypeID.
     let type_id = "artifact".create_type_id::<V7>();
     type_id.to_string()
 }
 
-pub fn presigned_url(hostname: &str, artifact_type: ArtifactType, id: &str) -> String {
+/// The URI of an artifact, as referenced by the proof requests.
+pub fn artifact_uri(hostname: &str, artifact_type: ArtifactType, id: &str) -> String {
     let artifact_name = artifact_type.as_str_name().to_lowercase();
     format!("{hostname}/artifacts/{artifact_name}/{id}")
 }
+
+/// The message the fulfiller signs to download the artifact `id` at `timestamp`.
+pub fn download_message(id: &str, timestamp: u64) -> Vec<u8> {
+    format!("download_artifact:{id}:{timestamp}").into_bytes()
+}
+
+/// The URL used to upload an artifact, carrying the token that authorizes the upload.
+pub fn presigned_url(hostname: &str, artifact_type: ArtifactType, id: &str, token: &str) -> String {
+    format!(
+        "{}?token={token}",
+        artifact_uri(hostname, artifact_type, id)
+    )
+}
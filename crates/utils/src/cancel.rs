@@ -0,0 +1,31 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+use sp1_tee_private_types::{CancelProofRequestRequest, CancelProofRequestRequestBody};
+
+use crate::Signer;
+
+/// The message a requester or an admin key signs to cancel a proof request. The body is tagged,
+/// so no other message signed by the key can be passed off as a cancellation.
+pub fn cancel_message(body: &CancelProofRequestRequestBody) -> Vec<u8> {
+    let mut message = b"cancel-proof-request:".to_vec();
+    message.extend_from_slice(&body.encode_to_vec());
+    message
+}
+
+/// Build a request cancelling a proof request, signed by its requester or an admin key.
+pub async fn cancel_request(
+    signer: &dyn Signer,
+    request_id: Vec<u8>,
+) -> anyhow::Result<CancelProofRequestRequest> {
+    let body = CancelProofRequestRequestBody {
+        request_id,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    let signature = signer.sign_message(&cancel_message(&body)).await?;
+
+    Ok(CancelProofRequestRequest {
+        signature: signature.as_bytes().to_vec(),
+        body: Some(body),
+    })
+}
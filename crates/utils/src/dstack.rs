@@ -0,0 +1,207 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    Request,
+    body::Bytes,
+    client::conn::http1,
+    header::{CONTENT_TYPE, HOST},
+};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use sha2::{Digest, Sha512};
+use tokio::net::UnixStream;
+use zeroize::Zeroizing;
+
+/// The path of the dstack guest agent socket, as mounted in the containers.
+pub const DSTACK_SOCKET_PATH: &str = "/var/run/dstack.sock";
+
+/// The maximum size of the report data embedded in a TDX quote.
+pub const MAX_REPORT_DATA_SIZE: usize = 64;
+
+/// The tag of the report data of the attestations requested by the clients.
+pub const ATTESTATION_TAG: &str = "sp1-tee/attest/v2";
+
+/// The tag of the report data binding the enclave transport key.
+pub const ENCLAVE_KEY_TAG: &str = "sp1-tee/enclave-key/v1";
+
+/// The report data of a quote produced for the `tag` use: the SHA-512 hash of the tag followed by
+/// `parts`. Each use has its own tag, so a quote produced for one use cannot be passed off for
+/// another.
+pub fn tagged_report_data(tag: &str, parts: &[&[u8]]) -> [u8; MAX_REPORT_DATA_SIZE] {
+    let mut hasher = Sha512::new_with_prefix(tag);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// A client of the dstack guest agent, reached through its Unix socket.
+#[derive(Debug, Clone)]
+pub struct DstackClient {
+    socket_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetQuoteResponse {
+    /// The hex-encoded TDX quote.
+    pub quote: String,
+    /// The JSON-encoded event log, used to replay the RTMRs.
+    pub event_log: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetKeyResponse {
+    /// The hex-encoded secp256k1 private key.
+    pub key: String,
+    /// The hex-encoded signatures chaining the key to the app KMS root key.
+    pub signature_chain: Vec<String>,
+}
+
+impl DstackClient {
+    pub fn new(socket_path: impl Into<PathBuf>) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+        }
+    }
+
+    /// Get a TDX quote embedding `report_data`, of at most [`MAX_REPORT_DATA_SIZE`] bytes.
+    pub async fn get_quote(&self, report_data: &[u8]) -> Result<GetQuoteResponse> {
+        if report_data.len() > MAX_REPORT_DATA_SIZE {
+            bail!("report data larger than {MAX_REPORT_DATA_SIZE} bytes");
+        }
+
+        self.call(
+            "GetQuote",
+            json!({ "report_data": hex::encode(report_data) }),
+        )
+        .await
+    }
+
+    /// Derive a key bound to the app identity and `path`. Every instance of the app, and every
+    /// container of an instance, derives the same key, which never leaves the enclaves.
+    pub async fn get_key(&self, path: &str, purpose: &str) -> Result<GetKeyResponse> {
+        self.call("GetKey", json!({ "path": path, "purpose": purpose }))
+            .await
+    }
+
+    /// Derive a 32-byte secret bound to the app identity and `path`, see [`Self::get_key`].
+    pub async fn get_secret(&self, path: &str, purpose: &str) -> Result<Zeroizing<[u8; 32]>> {
+        let response = self.get_key(path, purpose).await?;
+        let key = Zeroizing::new(hex::decode(response.key.trim_start_matches("0x"))?);
+        let secret = key
+            .as_slice()
+            .try_into()
+            .context("expected a 32-byte key")?;
+
+        Ok(Zeroizing::new(secret))
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: impl Serialize) -> Result<T> {
+        let stream = UnixStream::connect(&self.socket_path)
+            .await
+            .with_context(|| format!("failed to connect to {}", self.socket_path.display()))?;
+        let (mut sender, connection) = http1::handshake(TokioIo::new(stream)).await?;
+
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                tracing::warn!("dstack connection error: {err}");
+            }
+        });
+
+        let request = Request::post(format!("/{method}"))
+            .header(HOST, "localhost")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(serde_json::to_vec(&params)?)))?;
+        let response = sender.send_request(request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+
+        if !status.is_success() {
+            bail!(
+                "dstack {method} failed with {status}: {}",
+                String::from_utf8_lossy(&body)
+            );
+        }
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+/// A stand-in for the dstack guest agent, for the tests.
+#[cfg(any(test, feature = "mock"))]
+pub mod mock {
+    use std::path::Path;
+
+    use serde_json::{Value, json};
+    use sha2::{Digest, Sha256};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{UnixListener, UnixStream},
+    };
+
+    /// Serve the dstack guest agent methods on a Unix socket: `GetQuote` returns a quote made of
+    /// the report data, and `GetKey` the SHA-256 hash of `seed` and the path.
+    pub fn spawn(socket_path: &Path, seed: [u8; 32]) {
+        let _ = std::fs::remove_file(socket_path);
+        let listener = UnixListener::bind(socket_path).unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, seed));
+            }
+        });
+    }
+
+    async fn serve(mut stream: UnixStream, seed: [u8; 32]) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+
+        // Read the headers, then the body announced by the content length.
+        let (head, body_start, body_len) = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let head = text[..end].to_string();
+                let body_len = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|len| len.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                break (head, end + 4, body_len);
+            }
+        };
+        while request.len() < body_start + body_len {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let params: Value = serde_json::from_slice(&request[body_start..]).unwrap();
+        let body = if head.starts_with("POST /GetQuote ") {
+            json!({ "quote": params["report_data"], "event_log": "[]" })
+        } else if head.starts_with("POST /GetKey ") {
+            let path = params["path"].as_str().unwrap_or_default();
+            let key = Sha256::new()
+                .chain_update(seed)
+                .chain_update(path)
+                .finalize();
+            json!({ "key": hex::encode(key), "signature_chain": [] })
+        } else {
+            json!({})
+        };
+
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+}
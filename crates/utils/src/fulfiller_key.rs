@@ -0,0 +1,230 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+
+use alloy_primitives::Address;
+use anyhow::{Context, Result, ensure};
+
+use crate::{DstackClient, DstackSigner, LocalSigner, RemoteSigner, Signer};
+
+/// The dstack KMS path of the default fulfiller key.
+pub const FULFILLER_KEY_PATH: &str = "sp1-tee-private-proving/fulfiller";
+
+/// The seed of the [`crate::MockSigner`] keys, shared by the server and the fulfiller.
+#[cfg(feature = "mock")]
+const MOCK_SIGNER_SEED: &str = "sp1-tee-private-proving/mock-fulfiller";
+
+/// The dstack KMS path of the fulfiller key identified by `key_id`, or of the default one.
+pub fn fulfiller_key_path(key_id: Option<&str>) -> String {
+    match key_id {
+        Some(key_id) => format!("{FULFILLER_KEY_PATH}/{key_id}"),
+        None => FULFILLER_KEY_PATH.to_string(),
+    }
+}
+
+/// Where the fulfiller signing keys come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FulfillerKeySource {
+    /// The `FULFILLER_PRIVATE_KEY` environment variable.
+    #[default]
+    Env,
+    /// The dstack KMS, so the keys never exist outside of the enclaves running this app.
+    Dstack,
+    /// Remote signing services, see [`RemoteSigner`].
+    Remote,
+    /// Well-known keys, for local development only.
+    #[cfg(feature = "mock")]
+    Mock,
+}
+
+impl FromStr for FulfillerKeySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "env" => Ok(Self::Env),
+            "dstack" => Ok(Self::Dstack),
+            "remote" => Ok(Self::Remote),
+            #[cfg(feature = "mock")]
+            "mock" => Ok(Self::Mock),
+            _ => {
+                let expected = Self::names()
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Err(format!(
+                    "invalid key source `{s}`, expected one of {expected}"
+                ))
+            }
+        }
+    }
+}
+
+impl FulfillerKeySource {
+    /// The names of the key sources enabled in this build.
+    fn names() -> Vec<&'static str> {
+        #[allow(unused_mut)]
+        let mut names = vec!["env", "dstack", "remote"];
+        #[cfg(feature = "mock")]
+        names.push("mock");
+        names
+    }
+}
+
+/// The fulfiller keys, indexed by address. Holding several keys lets the requests assigned to a
+/// retired key be fulfilled while the new one is rolled out.
+#[derive(Clone)]
+pub struct FulfillerKeySet {
+    signers: BTreeMap<Address, Arc<dyn Signer>>,
+}
+
+impl FulfillerKeySet {
+    pub fn new(signers: impl IntoIterator<Item = Arc<dyn Signer>>) -> Self {
+        Self {
+            signers: signers
+                .into_iter()
+                .map(|signer| (signer.address(), signer))
+                .collect(),
+        }
+    }
+
+    /// The signer of a fulfiller address, if the set holds its key.
+    pub fn get(&self, address: &[u8]) -> Option<Arc<dyn Signer>> {
+        let address = Address::try_from(address).ok()?;
+        self.signers.get(&address).cloned()
+    }
+
+    /// The addresses of the keys, in ascending order.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.signers.keys().copied().collect()
+    }
+}
+
+/// Build the fulfiller key set from its source: the `private_keys` for the `env` source, one
+/// key per `remote_signer_urls` for the `remote` one, and one derived key per `key_ids` for the
+/// `dstack` one, or the default key if there is none.
+pub async fn fulfiller_key_set(
+    source: FulfillerKeySource,
+    private_keys: &[String],
+    key_ids: &[String],
+    remote_signer_urls: &[String],
+    dstack: &DstackClient,
+) -> Result<FulfillerKeySet> {
+    let mut signers: Vec<Arc<dyn Signer>> = Vec::new();
+
+    match source {
+        FulfillerKeySource::Env => {
+            for private_key in private_keys {
+                signers.push(Arc::new(LocalSigner::new(private_key)?));
+            }
+        }
+        FulfillerKeySource::Dstack => {
+            if key_ids.is_empty() {
+                signers.push(Arc::new(DstackSigner::derive(dstack, None).await?));
+            }
+            for key_id in key_ids {
+                signers.push(Arc::new(DstackSigner::derive(dstack, Some(key_id)).await?));
+            }
+        }
+        FulfillerKeySource::Remote => {
+            for url in remote_signer_urls {
+                let signer = RemoteSigner::connect(url)
+                    .await
+                    .with_context(|| format!("failed to connect to the remote signer {url}"))?;
+                signers.push(Arc::new(signer));
+            }
+        }
+        #[cfg(feature = "mock")]
+        FulfillerKeySource::Mock => {
+            tracing::warn!("Using the mock fulfiller signers, do not use them in production");
+
+            if key_ids.is_empty() {
+                signers.push(Arc::new(crate::MockSigner::new(MOCK_SIGNER_SEED)));
+            }
+            for key_id in key_ids {
+                let seed = format!("{MOCK_SIGNER_SEED}/{key_id}");
+                signers.push(Arc::new(crate::MockSigner::new(&seed)));
+            }
+        }
+    }
+
+    ensure!(
+        !signers.is_empty(),
+        "no fulfiller key configured for the `{source:?}` key source"
+    );
+
+    Ok(FulfillerKeySet::new(signers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dstack::mock;
+
+    const PRIVATE_KEY: &str = "0x0101010101010101010101010101010101010101010101010101010101010101";
+
+    #[test]
+    fn test_key_source_error_lists_the_enabled_sources() {
+        let err = "unknown".parse::<FulfillerKeySource>().unwrap_err();
+        assert!(err.contains("`env`, `dstack`, `remote`"));
+        assert_eq!(err.contains("`mock`"), cfg!(feature = "mock"));
+    }
+
+    #[tokio::test]
+    async fn test_fulfiller_key_set() {
+        let socket_path =
+            std::env::temp_dir().join(format!("dstack-key-test-{}.sock", std::process::id()));
+        mock::spawn(&socket_path, [1; 32]);
+        let dstack = DstackClient::new(&socket_path);
+
+        let default = fulfiller_key_set(FulfillerKeySource::Dstack, &[], &[], &[], &dstack)
+            .await
+            .unwrap();
+        assert_eq!(default.addresses().len(), 1);
+
+        // Every container of the app derives the same keys.
+        let key_ids = ["2024".to_string(), "2025".to_string()];
+        let rotated = fulfiller_key_set(FulfillerKeySource::Dstack, &[], &key_ids, &[], &dstack)
+            .await
+            .unwrap();
+        let again = fulfiller_key_set(FulfillerKeySource::Dstack, &[], &key_ids, &[], &dstack)
+            .await
+            .unwrap();
+        assert_eq!(rotated.addresses().len(), 2);
+        assert_eq!(rotated.addresses(), again.addresses());
+        assert!(!rotated.addresses().contains(&default.addresses()[0]));
+
+        for address in rotated.addresses() {
+            let signer = rotated.get(address.as_slice()).unwrap();
+            assert_eq!(signer.address(), address);
+        }
+        assert!(rotated.get(default.addresses()[0].as_slice()).is_none());
+        assert!(rotated.get(&[1; 4]).is_none());
+
+        let env = fulfiller_key_set(
+            FulfillerKeySource::Env,
+            &[PRIVATE_KEY.to_string()],
+            &[],
+            &[],
+            &dstack,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            env.addresses(),
+            vec![LocalSigner::new(PRIVATE_KEY).unwrap().address()]
+        );
+
+        assert!(
+            fulfiller_key_set(FulfillerKeySource::Env, &[], &[], &[], &dstack)
+                .await
+                .is_err()
+        );
+        assert!(
+            fulfiller_key_set(FulfillerKeySource::Remote, &[], &[], &[], &dstack)
+                .await
+                .is_err()
+        );
+
+        let _ = std::fs::remove_file(&socket_path);
+    }
+}
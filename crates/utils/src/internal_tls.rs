@@ -0,0 +1,202 @@
+//! The mutually authenticated TLS channel between the server and the fulfiller.
+//!
+//! Both derive the same internal CA key from the dstack KMS, which only releases it to the
+//! enclaves running this app. Each then generates its own leaf key, and a certificate signed by
+//! the CA, so a peer is accepted only if it runs inside one of these enclaves.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use hkdf::Hkdf;
+use p256::{SecretKey, pkcs8::EncodePrivateKey};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use rustls::{
+    ClientConfig, RootCertStore, ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+    server::WebPkiClientVerifier,
+};
+use sha2::Sha256;
+use tonic::transport::{Certificate as TonicCertificate, ClientTlsConfig, Identity};
+use zeroize::Zeroizing;
+
+use crate::DstackClient;
+
+/// The name the server certificate is issued for, and checked against by the fulfiller.
+pub const INTERNAL_SERVER_NAME: &str = "server";
+
+/// The dstack KMS path of the internal CA key.
+const CA_KEY_PATH: &str = "sp1-tee-private-proving/internal-ca";
+
+const CA_KEY_INFO: &[u8] = b"sp1-tee-private-proving internal CA";
+
+const CA_NAME: &str = "SP1 TEE Private Proving Internal CA";
+
+/// The side of the channel an identity is issued for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InternalRole {
+    Server,
+    Fulfiller,
+}
+
+/// A leaf certificate signed by the internal CA, alongside the CA certificate to verify the peer.
+pub struct InternalIdentity {
+    ca_certificate: Certificate,
+    certificate: Certificate,
+    key: KeyPair,
+}
+
+impl InternalIdentity {
+    /// Derive the internal CA key from the dstack KMS, and issue a new identity for `role`.
+    pub async fn derive(dstack: &DstackClient, role: InternalRole) -> Result<Self> {
+        let response = dstack
+            .get_key(CA_KEY_PATH, "internal-tls")
+            .await
+            .context("failed to derive the internal CA key")?;
+        let secret = Zeroizing::new(hex::decode(response.key.trim_start_matches("0x"))?);
+
+        Self::issue(&secret, role)
+    }
+
+    /// Issue a new identity for `role`, under the CA whose key is derived from `secret`.
+    pub fn issue(secret: &[u8], role: InternalRole) -> Result<Self> {
+        let mut ca_secret = Zeroizing::new([0; 32]);
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(CA_KEY_INFO, ca_secret.as_mut_slice())
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        let ca_key = SecretKey::from_slice(ca_secret.as_slice())?.to_pkcs8_der()?;
+        let ca_key = KeyPair::try_from(ca_key.as_bytes())?;
+
+        // The certificate differs on every derivation, but its name and key, which are the only
+        // parts used to verify the chains, do not.
+        let mut ca_params = CertificateParams::default();
+        let mut ca_name = DistinguishedName::new();
+        ca_name.push(DnType::CommonName, CA_NAME);
+        ca_params.distinguished_name = ca_name;
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let ca_certificate = ca_params.self_signed(&ca_key)?;
+
+        let (names, usage) = match role {
+            InternalRole::Server => (
+                vec![INTERNAL_SERVER_NAME.to_string(), "localhost".to_string()],
+                ExtendedKeyUsagePurpose::ServerAuth,
+            ),
+            InternalRole::Fulfiller => (
+                vec!["fulfiller".to_string()],
+                ExtendedKeyUsagePurpose::ClientAuth,
+            ),
+        };
+        let key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(names)?;
+        params.extended_key_usages = vec![usage];
+        let certificate = params.signed_by(&key, &ca_certificate, &ca_key)?;
+
+        Ok(Self {
+            ca_certificate,
+            certificate,
+            key,
+        })
+    }
+
+    /// The server TLS configuration, rejecting the clients without a certificate signed by the
+    /// internal CA.
+    pub fn server_config(&self) -> Result<ServerConfig> {
+        let verifier = WebPkiClientVerifier::builder(Arc::new(self.roots()?)).build()?;
+        let mut config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(vec![self.certificate.der().clone()], self.private_key())?;
+        // The gRPC services need HTTP/2.
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(config)
+    }
+
+    /// The client TLS configuration, only trusting a server certificate signed by the internal
+    /// CA.
+    pub fn client_config(&self) -> Result<ClientConfig> {
+        Ok(ClientConfig::builder()
+            .with_root_certificates(self.roots()?)
+            .with_client_auth_cert(vec![self.certificate.der().clone()], self.private_key())?)
+    }
+
+    /// The client TLS configuration of the gRPC channels.
+    pub fn tonic_config(&self) -> ClientTlsConfig {
+        ClientTlsConfig::new()
+            .ca_certificate(TonicCertificate::from_pem(self.ca_certificate.pem()))
+            .identity(Identity::from_pem(
+                self.certificate.pem(),
+                self.key.serialize_pem(),
+            ))
+            .domain_name(INTERNAL_SERVER_NAME)
+    }
+
+    fn roots(&self) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca_certificate.der().clone())?;
+
+        Ok(roots)
+    }
+
+    fn private_key(&self) -> PrivateKeyDer<'static> {
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.key.serialize_der()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use rustls::{ClientConnection, ServerConnection, pki_types::ServerName};
+
+    use super::*;
+
+    /// Run a TLS handshake in memory, and send a message from the client to the server.
+    fn handshake(server: &InternalIdentity, client: &InternalIdentity) -> Result<Vec<u8>> {
+        let mut server = ServerConnection::new(Arc::new(server.server_config()?))?;
+        let mut client = ClientConnection::new(
+            Arc::new(client.client_config()?),
+            ServerName::try_from(INTERNAL_SERVER_NAME)?,
+        )?;
+        client.writer().write_all(b"stdin")?;
+
+        for _ in 0..10 {
+            let mut buf = Vec::new();
+            client.write_tls(&mut buf)?;
+            server.read_tls(&mut buf.as_slice())?;
+            server.process_new_packets()?;
+
+            let mut buf = Vec::new();
+            server.write_tls(&mut buf)?;
+            client.read_tls(&mut buf.as_slice())?;
+            client.process_new_packets()?;
+        }
+
+        let mut received = vec![0; 5];
+        server.reader().read_exact(&mut received)?;
+
+        Ok(received)
+    }
+
+    #[test]
+    fn test_mutual_authentication() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+        let server = InternalIdentity::issue(&[1; 32], InternalRole::Server).unwrap();
+        let fulfiller = InternalIdentity::issue(&[1; 32], InternalRole::Fulfiller).unwrap();
+        assert_eq!(handshake(&server, &fulfiller).unwrap(), b"stdin");
+
+        // A peer outside of the enclaves can not derive the CA key.
+        let intruder = InternalIdentity::issue(&[2; 32], InternalRole::Fulfiller).unwrap();
+        assert!(handshake(&server, &intruder).is_err());
+
+        let intruder = InternalIdentity::issue(&[2; 32], InternalRole::Server).unwrap();
+        assert!(handshake(&intruder, &fulfiller).is_err());
+
+        // A fulfiller certificate can not be used to impersonate the server.
+        assert!(handshake(&fulfiller, &fulfiller).is_err());
+    }
+}
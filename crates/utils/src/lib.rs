@@ -1,17 +1,60 @@
 use std::time::Duration;
 
 use sp1_sdk::network::proto::base_network::prover_network_client::ProverNetworkClient;
-use sp1_tee_private_types::prover_network_client::ProverNetworkClient as PrivateNetworkClient;
+use sp1_tee_private_types::{
+    admin_client::AdminClient, prover_network_client::ProverNetworkClient as PrivateNetworkClient,
+};
 use tonic::transport::{Channel, ClientTlsConfig, Endpoint, Error};
 
+mod admin;
+pub use admin::{ADMIN_SIGNATURE_HEADER, ADMIN_TIMESTAMP_HEADER, admin_message, admin_request};
+
 mod artifacts;
-pub use artifacts::{generate_id, presigned_url};
+pub use artifacts::{
+    DOWNLOAD_SIGNATURE_HEADER, DOWNLOAD_TIMESTAMP_HEADER, artifact_uri, download_message,
+    generate_id, presigned_url,
+};
+
+mod cancel;
+pub use cancel::{cancel_message, cancel_request};
+
+mod dstack;
+pub use dstack::{
+    ATTESTATION_TAG, DSTACK_SOCKET_PATH, DstackClient, ENCLAVE_KEY_TAG, GetKeyResponse,
+    GetQuoteResponse, MAX_REPORT_DATA_SIZE, tagged_report_data,
+};
+
+#[cfg(any(test, feature = "mock"))]
+pub use dstack::mock as dstack_mock;
+
+mod fulfiller_key;
+pub use fulfiller_key::{
+    FULFILLER_KEY_PATH, FulfillerKeySet, FulfillerKeySource, fulfiller_key_path, fulfiller_key_set,
+};
 
 mod retry;
 pub use retry::retry_operation;
 
 mod signable;
-pub use signable::Signable;
+pub use signable::{Signable, recover_signer};
+
+mod watch;
+pub use watch::watch_file;
+
+mod signer;
+pub use signer::{
+    DstackSigner, LocalSigner, RemoteAddressResponse, RemoteSignRequest, RemoteSignResponse,
+    RemoteSigner, Signer,
+};
+
+#[cfg(any(test, feature = "mock"))]
+pub use signer::MockSigner;
+
+pub mod internal_tls;
+
+pub mod ra_tls;
+
+pub mod stdin_encryption;
 
 /// Configures the endpoint for the gRPC client.
 ///
@@ -40,7 +83,25 @@ pub async fn prover_network_client(rpc_url: &str) -> Result<ProverNetworkClient<
     Ok(ProverNetworkClient::new(channel))
 }
 
-pub async fn private_network_client(rpc_url: &str) -> Result<PrivateNetworkClient<Channel>, Error> {
-    let channel = configure_endpoint(rpc_url)?.connect().await?;
+/// Connect to the private server, over the internal mutually authenticated channel if
+/// `tls_config` is set, see [`internal_tls`].
+pub async fn private_network_client(
+    rpc_url: &str,
+    tls_config: Option<ClientTlsConfig>,
+) -> Result<PrivateNetworkClient<Channel>, Error> {
+    let mut endpoint = configure_endpoint(rpc_url)?;
+
+    if let Some(tls_config) = tls_config {
+        endpoint = endpoint.tls_config(tls_config)?;
+    }
+
+    let channel = endpoint.connect().await?;
     Ok(PrivateNetworkClient::new(channel))
 }
+
+/// Connect to the admin service of the private server. The calls must be signed with
+/// [`admin_request`].
+pub async fn admin_client(rpc_url: &str) -> Result<AdminClient<Channel>, Error> {
+    let channel = configure_endpoint(rpc_url)?.connect().await?;
+    Ok(AdminClient::new(channel))
+}
@@ -0,0 +1,180 @@
+//! RA-TLS: the server certificate carries a TDX quote whose report data commits to the
+//! certificate public key, so a client can check it talks to an attested enclave during the TLS
+//! handshake.
+
+use std::{fmt, sync::Arc};
+
+use anyhow::{Context, Result, bail};
+use rustls::{
+    DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::{MAX_REPORT_DATA_SIZE, tagged_report_data};
+
+/// The OID of the X.509 extension holding the TDX quote, as used by Gramine and the other RA-TLS
+/// implementations.
+pub const TDX_QUOTE_OID: &[u64] = &[1, 2, 840, 113741, 1, 5, 5, 1, 6];
+
+/// The offset of the report data in a TDX v4 quote: the 48-byte header, followed by the first
+/// 520 bytes of the TD report body.
+const QUOTE_REPORT_DATA_OFFSET: usize = 568;
+
+/// The tag of the report data binding the RA-TLS certificate public key.
+pub const RA_TLS_TAG: &str = "sp1-tee/ra-tls/v1";
+
+/// The report data committing to a public key for the `tag` use, such as [`RA_TLS_TAG`] or
+/// [`crate::ENCLAVE_KEY_TAG`].
+pub fn public_key_report_data(tag: &str, public_key: &[u8]) -> [u8; MAX_REPORT_DATA_SIZE] {
+    tagged_report_data(tag, &[public_key])
+}
+
+/// Extract the TDX quote of a DER-encoded RA-TLS certificate, checking its report data commits
+/// to the certificate public key. The quote itself still has to be verified by the caller.
+pub fn certificate_quote(certificate: &[u8]) -> Result<Vec<u8>> {
+    let (_, certificate) =
+        X509Certificate::from_der(certificate).context("invalid RA-TLS certificate")?;
+    let oid = TDX_QUOTE_OID
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".");
+
+    let quote = certificate
+        .extensions()
+        .iter()
+        .find(|extension| extension.oid.to_id_string() == oid)
+        .context("the certificate does not contain a TDX quote")?
+        .value;
+    let Some(report_data) =
+        quote.get(QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + MAX_REPORT_DATA_SIZE)
+    else {
+        bail!("the TDX quote is truncated");
+    };
+
+    if report_data != public_key_report_data(RA_TLS_TAG, certificate.public_key().raw) {
+        bail!("the TDX quote does not commit to the certificate public key");
+    }
+
+    Ok(quote.to_vec())
+}
+
+/// A rustls verifier accepting the self-signed RA-TLS certificates whose quote commits to their
+/// key, and passes the `verify_quote` check.
+pub struct RaTlsVerifier {
+    verify_quote: Box<dyn Fn(&[u8]) -> Result<()> + Send + Sync>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl RaTlsVerifier {
+    pub fn new(
+        provider: Arc<CryptoProvider>,
+        verify_quote: impl Fn(&[u8]) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            verify_quote: Box::new(verify_quote),
+            provider,
+        }
+    }
+}
+
+impl fmt::Debug for RaTlsVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RaTlsVerifier").finish_non_exhaustive()
+    }
+}
+
+impl ServerCertVerifier for RaTlsVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let quote = certificate_quote(end_entity)
+            .map_err(|err| rustls::Error::General(format!("{err:#}")))?;
+        (self.verify_quote)(&quote).map_err(|err| rustls::Error::General(format!("{err:#}")))?;
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{CertificateParams, CustomExtension, KeyPair};
+
+    use super::*;
+
+    fn certificate(key_pair: &KeyPair, report_data: &[u8]) -> Vec<u8> {
+        let mut quote = vec![0; QUOTE_REPORT_DATA_OFFSET + MAX_REPORT_DATA_SIZE + 16];
+        quote[QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + MAX_REPORT_DATA_SIZE]
+            .copy_from_slice(report_data);
+
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params
+            .custom_extensions
+            .push(CustomExtension::from_oid_content(TDX_QUOTE_OID, quote));
+
+        params.self_signed(key_pair).unwrap().der().to_vec()
+    }
+
+    #[test]
+    fn test_certificate_quote() {
+        let key_pair = KeyPair::generate().unwrap();
+        let report_data = public_key_report_data(RA_TLS_TAG, &key_pair.public_key_der());
+
+        let quote = certificate_quote(&certificate(&key_pair, &report_data)).unwrap();
+        assert_eq!(
+            &quote[QUOTE_REPORT_DATA_OFFSET..QUOTE_REPORT_DATA_OFFSET + MAX_REPORT_DATA_SIZE],
+            report_data
+        );
+
+        // A quote made for another key is rejected.
+        let other =
+            public_key_report_data(RA_TLS_TAG, &KeyPair::generate().unwrap().public_key_der());
+        assert!(certificate_quote(&certificate(&key_pair, &other)).is_err());
+
+        let params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        let plain = params.self_signed(&key_pair).unwrap();
+        assert!(certificate_quote(plain.der()).is_err());
+    }
+}
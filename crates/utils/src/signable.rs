@@ -1,13 +1,61 @@
+use alloy_primitives::{Address, Signature};
 use prost::Message;
-use sp1_sdk::NetworkSigner;
+
+use crate::Signer;
 
 pub trait Signable: Message {
-    async fn sign(&self, signer: &NetworkSigner) -> anyhow::Result<Vec<u8>>;
+    async fn sign(&self, signer: &dyn Signer) -> anyhow::Result<Vec<u8>>;
+
+    /// Recover the address that signed the message with [`Signable::sign`].
+    fn recover_signer(&self, signature: &[u8]) -> anyhow::Result<Address>;
 }
 
 impl<T: Message> Signable for T {
-    async fn sign(&self, signer: &NetworkSigner) -> anyhow::Result<Vec<u8>> {
+    async fn sign(&self, signer: &dyn Signer) -> anyhow::Result<Vec<u8>> {
         let signature = signer.sign_message(self.encode_to_vec().as_slice()).await?;
         Ok(signature.as_bytes().to_vec())
     }
+
+    fn recover_signer(&self, signature: &[u8]) -> anyhow::Result<Address> {
+        recover_signer(&self.encode_to_vec(), signature)
+    }
+}
+
+/// Recover the address that signed `message` with [`Signer::sign_message`].
+pub fn recover_signer(message: &[u8], signature: &[u8]) -> anyhow::Result<Address> {
+    let signature = Signature::from_raw(signature)?;
+    Ok(signature.recover_address_from_msg(message)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use sp1_sdk::network::proto::base_types::RequestProofRequestBody;
+
+    use super::*;
+    use crate::MockSigner;
+
+    #[tokio::test]
+    async fn test_recover_signer() {
+        let signer = MockSigner::new("requester");
+        let body = RequestProofRequestBody {
+            nonce: 1,
+            vk_hash: vec![2; 32],
+            cycle_limit: 1_000_000,
+            ..Default::default()
+        };
+
+        let signature = body.sign(&signer).await.unwrap();
+        assert_eq!(body.recover_signer(&signature).unwrap(), signer.address());
+
+        // A signature over another message recovers another address.
+        let tampered = RequestProofRequestBody {
+            cycle_limit: 2_000_000,
+            ..body
+        };
+        assert_ne!(
+            tampered.recover_signer(&signature).unwrap(),
+            signer.address()
+        );
+        assert!(tampered.recover_signer(&signature[..64]).is_err());
+    }
 }
@@ -0,0 +1,261 @@
+use alloy_primitives::{Address, Signature};
+use anyhow::{Context, Result, ensure};
+use serde::{Deserialize, Serialize};
+use sp1_sdk::NetworkSigner;
+use zeroize::Zeroizing;
+
+use crate::{DstackClient, fulfiller_key_path};
+
+/// Signs messages with the EIP-191 scheme expected by the prover network, see
+/// [`crate::recover_signer`].
+#[tonic::async_trait]
+pub trait Signer: Send + Sync {
+    fn address(&self) -> Address;
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature>;
+}
+
+/// A signer holding its key in memory.
+pub struct LocalSigner {
+    signer: NetworkSigner,
+}
+
+impl LocalSigner {
+    /// Create a signer from a hex-encoded private key.
+    pub fn new(private_key: &str) -> Result<Self> {
+        Ok(Self {
+            signer: NetworkSigner::local(private_key)?,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Signer for LocalSigner {
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        Ok(self.signer.sign_message(message).await?)
+    }
+}
+
+/// A signer whose key is derived from the dstack KMS, so it never exists outside of the
+/// enclaves running this app.
+pub struct DstackSigner {
+    signer: LocalSigner,
+}
+
+impl DstackSigner {
+    /// Derive the fulfiller key identified by `key_id`, or the default one if `None`.
+    pub async fn derive(dstack: &DstackClient, key_id: Option<&str>) -> Result<Self> {
+        let response = dstack
+            .get_key(&fulfiller_key_path(key_id), "signing")
+            .await
+            .context("failed to derive the fulfiller key")?;
+        let private_key = Zeroizing::new(response.key);
+
+        Ok(Self {
+            signer: LocalSigner::new(&private_key)?,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Signer for DstackSigner {
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        self.signer.sign_message(message).await
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteAddressResponse {
+    pub address: Address,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteSignRequest {
+    /// The hex-encoded message.
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteSignResponse {
+    /// The hex-encoded 65-byte signature.
+    pub signature: String,
+}
+
+/// A signer delegating to a remote signing service, which serves its address on
+/// `GET /address`, and signs the messages on `POST /sign`.
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    address: Address,
+}
+
+impl RemoteSigner {
+    /// Connect to the signing service, and fetch its address.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let url = url.trim_end_matches('/').to_string();
+        let response = client
+            .get(format!("{url}/address"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<RemoteAddressResponse>()
+            .await
+            .context("invalid remote signer address")?;
+
+        Ok(Self {
+            client,
+            url,
+            address: response.address,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Signer for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        let response = self
+            .client
+            .post(format!("{}/sign", self.url))
+            .json(&RemoteSignRequest {
+                message: hex::encode(message),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<RemoteSignResponse>()
+            .await?;
+
+        let signature = hex::decode(response.signature.trim_start_matches("0x"))?;
+        let signature = Signature::from_raw(&signature)?;
+
+        // Do not send anything signed by another key to the network.
+        ensure!(
+            signature.recover_address_from_msg(message)? == self.address,
+            "the remote signer signed with another key"
+        );
+
+        Ok(signature)
+    }
+}
+
+/// A signer with a well-known key, for local development only.
+#[cfg(any(test, feature = "mock"))]
+pub struct MockSigner {
+    signer: LocalSigner,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockSigner {
+    /// Create a signer whose key is the SHA-256 hash of `seed`.
+    pub fn new(seed: &str) -> Self {
+        use sha2::{Digest, Sha256};
+
+        let private_key = Zeroizing::new(hex::encode(Sha256::digest(seed)));
+
+        Self {
+            signer: LocalSigner::new(&private_key).expect("a SHA-256 hash is a valid key"),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+#[tonic::async_trait]
+impl Signer for MockSigner {
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        self.signer.sign_message(message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, sync::Arc};
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::{Method, Request, Response, body::Bytes, server::conn::http1, service::service_fn};
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::recover_signer;
+
+    /// Serve the remote signer API on a local port, signing with `signer`.
+    async fn spawn_remote_signer(signer: Arc<dyn Signer>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let signer = signer.clone();
+                let service = service_fn(move |request: Request<hyper::body::Incoming>| {
+                    let signer = signer.clone();
+                    async move {
+                        let body = match (request.method(), request.uri().path()) {
+                            (&Method::GET, "/address") => {
+                                serde_json::to_vec(&RemoteAddressResponse {
+                                    address: signer.address(),
+                                })
+                                .unwrap()
+                            }
+                            _ => {
+                                let body = request.into_body().collect().await.unwrap();
+                                let request: RemoteSignRequest =
+                                    serde_json::from_slice(&body.to_bytes()).unwrap();
+                                let message = hex::decode(request.message).unwrap();
+                                let signature = signer.sign_message(&message).await.unwrap();
+
+                                serde_json::to_vec(&RemoteSignResponse {
+                                    signature: hex::encode(signature.as_bytes()),
+                                })
+                                .unwrap()
+                            }
+                        };
+
+                        Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(body))))
+                    }
+                });
+
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer() {
+        let backend = Arc::new(MockSigner::new("remote"));
+        let url = spawn_remote_signer(backend.clone()).await;
+
+        let signer = RemoteSigner::connect(&url).await.unwrap();
+        assert_eq!(signer.address(), backend.address());
+
+        let signature = signer.sign_message(b"fulfill_proof").await.unwrap();
+        assert_eq!(
+            recover_signer(b"fulfill_proof", &signature.as_bytes()).unwrap(),
+            backend.address()
+        );
+
+        // A service signing with another key than the one it advertised is rejected.
+        let url = spawn_remote_signer(Arc::new(MockSigner::new("other"))).await;
+        let mut signer = RemoteSigner::connect(&url).await.unwrap();
+        signer.address = backend.address();
+        assert!(signer.sign_message(b"fulfill_proof").await.is_err());
+    }
+}
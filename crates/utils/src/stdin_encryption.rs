@@ -0,0 +1,91 @@
+//! The encryption of the stdins to the enclave transport key, so no proxy between the client and
+//! the enclave ever sees them in plaintext.
+//!
+//! An encrypted upload body is the SEC1 uncompressed ephemeral public key of the sender, followed
+//! by the stdin split in chunks of [`STDIN_CHUNK_SIZE`] bytes. Each chunk is sealed with
+//! AES-256-GCM, under the key derived with HKDF-SHA256 from the P-256 ECDH shared secret, and a
+//! nonce made of the big-endian chunk index and a final chunk flag, so chunks can be neither
+//! reordered nor truncated.
+
+use aes_gcm::{
+    Aes256Gcm, Key, KeyInit, Nonce,
+    aead::{Aead, OsRng},
+};
+use anyhow::{Result, anyhow};
+use hkdf::Hkdf;
+use p256::{PublicKey, ecdh::EphemeralSecret, elliptic_curve::sec1::ToEncodedPoint};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+/// The header announcing an upload encrypted to the enclave transport key.
+pub const STDIN_ENCRYPTION_HEADER: &str = "x-stdin-encryption";
+
+/// The value of [`STDIN_ENCRYPTION_HEADER`] for the scheme described in this module.
+pub const STDIN_ENCRYPTION_SCHEME: &str = "p256-hkdf-sha256-aes256gcm";
+
+/// The size of the plaintext chunks of a stdin.
+pub const STDIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The size of a SEC1 uncompressed P-256 public key.
+pub const PUBLIC_KEY_SIZE: usize = 65;
+
+const KEY_INFO: &[u8] = b"sp1-tee-private-proving stdin";
+
+/// The nonce of the chunk `index` of a stdin.
+pub fn chunk_nonce(index: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&index.to_be_bytes());
+    nonce[8] = last as u8;
+    nonce
+}
+
+/// Derive the key of a stdin from the ECDH shared secret, bound to the SEC1 uncompressed public
+/// keys of both parties.
+pub fn derive_stdin_key(
+    shared_secret: &[u8],
+    ephemeral_public_key: &[u8],
+    recipient_public_key: &[u8],
+) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0; 32]);
+
+    Hkdf::<Sha256>::new(None, shared_secret)
+        .expand_multi_info(
+            &[KEY_INFO, ephemeral_public_key, recipient_public_key],
+            key.as_mut_slice(),
+        )
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+    key
+}
+
+/// Encrypt a stdin to the enclave transport key, and return the upload body.
+pub fn encrypt_stdin(recipient_public_key: &[u8], stdin: &[u8]) -> Result<Vec<u8>> {
+    let recipient = PublicKey::from_sec1_bytes(recipient_public_key)?;
+    let ephemeral = EphemeralSecret::random(&mut OsRng);
+    let ephemeral_public_key = ephemeral.public_key().to_encoded_point(false);
+    let shared_secret = ephemeral.diffie_hellman(&recipient);
+
+    let key = derive_stdin_key(
+        shared_secret.raw_secret_bytes(),
+        ephemeral_public_key.as_bytes(),
+        recipient.to_encoded_point(false).as_bytes(),
+    );
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_slice()));
+
+    let mut body = ephemeral_public_key.as_bytes().to_vec();
+    // An empty stdin is still sealed in a single empty chunk.
+    let count = stdin.len().div_ceil(STDIN_CHUNK_SIZE).max(1);
+
+    for index in 0..count {
+        let start = index * STDIN_CHUNK_SIZE;
+        let end = (start + STDIN_CHUNK_SIZE).min(stdin.len());
+        let nonce = chunk_nonce(index as u64, index == count - 1);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), &stdin[start..end])
+            .map_err(|_| anyhow!("failed to encrypt stdin chunk"))?;
+
+        body.extend_from_slice(&ciphertext);
+    }
+
+    Ok(body)
+}
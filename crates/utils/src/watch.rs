@@ -0,0 +1,28 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// How often the watched files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Call `reload` whenever the modification time of the file changes, so the configuration it
+/// holds can be updated without a restart.
+pub async fn watch_file(path: PathBuf, mut reload: impl FnMut(&Path) + Send) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let mut last_modified: Option<SystemTime> = modified(&path);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let current = modified(&path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        reload(&path);
+    }
+}